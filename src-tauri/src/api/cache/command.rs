@@ -0,0 +1,19 @@
+use tauri::{AppHandle, Wry};
+
+use crate::{
+    api::cache::lib::{compact_caches_for_dir, CacheCompactionReport, DEFAULT_MAX_AGE_DAYS},
+    errors::ApplicationError,
+};
+
+#[tauri::command]
+pub(crate) fn compact_token_caches(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    max_age_days: Option<u64>,
+) -> Result<CacheCompactionReport, ApplicationError> {
+    compact_caches_for_dir(
+        &app,
+        &directory_path,
+        max_age_days.unwrap_or(DEFAULT_MAX_AGE_DAYS),
+    )
+}
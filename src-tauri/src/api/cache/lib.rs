@@ -0,0 +1,147 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Wry};
+
+use crate::{
+    api::{
+        git::tokenize::prune_git_token_cache,
+        tokenize::prune_token_cache,
+        tree::{cache::cache, index::ensure_index},
+    },
+    errors::{codes, ApplicationError},
+    store::{data_write_lock, open_store, save_store, StoreCategoryKey},
+};
+
+pub(crate) const DEFAULT_MAX_AGE_DAYS: u64 = 30;
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+pub(crate) fn days_to_ms(days: u64) -> u64 {
+    days.saturating_mul(DAY_MS)
+}
+
+/// Whether an entry last used at `used_ms` should be stamped again. Stamping
+/// at most once a day keeps cache hits from writing to the store every time.
+pub(crate) fn needs_use_stamp(used_ms: u64, now: u64) -> bool {
+    now.saturating_sub(used_ms) >= DAY_MS
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PruneStats {
+    pub(crate) kept_entries: usize,
+    pub(crate) removed_entries: usize,
+    pub(crate) reclaimed_bytes: usize,
+    #[serde(skip)]
+    pub(crate) removed_keys: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CacheCompactionReport {
+    pub(crate) root: String,
+    pub(crate) token_cache: PruneStats,
+    pub(crate) git_token_cache: PruneStats,
+    pub(crate) reclaimed_bytes: usize,
+}
+
+/// Rewrites the `store_key` cache of `root` in the store, keeping the entries
+/// for which `keep` returns a value. `keep` may also re-key or restamp them.
+pub(crate) fn prune_store_cache(
+    app: &AppHandle<Wry>,
+    root: &str,
+    store_key: &str,
+    mut keep: impl FnMut(&str, &Value) -> Option<(String, Value)>,
+) -> Result<PruneStats, ApplicationError> {
+    let _guard = data_write_lock();
+    let store = open_store(app)?;
+
+    let mut data: Map<String, Value> = store
+        .get(StoreCategoryKey::DATA)
+        .and_then(|value| value.as_object().cloned())
+        .unwrap_or_else(Map::new);
+
+    let Some(cache_object) = data
+        .get_mut(root)
+        .and_then(|directory| directory.as_object_mut())
+        .and_then(|directory| directory.get_mut(store_key))
+        .and_then(|cache| cache.as_object_mut())
+    else {
+        store.close_resource();
+        return Ok(PruneStats::default());
+    };
+
+    let size_before = serde_json::to_string(cache_object).map_or(0, |s| s.len());
+    let mut stats = PruneStats::default();
+    let mut pruned = Map::new();
+
+    for (key, value) in cache_object.iter() {
+        match keep(key, value) {
+            Some((new_key, new_value)) => {
+                pruned.insert(new_key, new_value);
+            }
+            None => {
+                stats.removed_entries += 1;
+                stats.removed_keys.push(key.clone());
+            }
+        }
+    }
+
+    stats.kept_entries = pruned.len();
+    stats.reclaimed_bytes =
+        size_before.saturating_sub(serde_json::to_string(&pruned).map_or(0, |s| s.len()));
+
+    *cache_object = pruned;
+
+    store.set(StoreCategoryKey::DATA, Value::Object(data));
+    save_store(&store)?;
+    store.close_resource();
+
+    Ok(stats)
+}
+
+/// Drops token cache entries for files that are no longer in the tree and
+/// entries of both caches not used in `max_age_days`.
+pub(crate) fn compact_caches_for_dir(
+    app: &AppHandle<Wry>,
+    root: &str,
+    max_age_days: u64,
+) -> Result<CacheCompactionReport, ApplicationError> {
+    let max_age_ms = days_to_ms(max_age_days);
+
+    ensure_index(root, false)?;
+    let token_cache = {
+        let guard = cache().read().expect("cache read poisoned");
+        // The root may have been closed since `ensure_index` built its index.
+        let tree_index = guard.get(root).ok_or_else(|| ApplicationError {
+            code: codes::DIRECTORY_READ_ERROR,
+            message: Some(root.to_string()),
+        })?;
+        prune_token_cache(app, root, tree_index, max_age_ms)?
+    };
+    let git_token_cache = prune_git_token_cache(app, root, max_age_ms)?;
+
+    Ok(CacheCompactionReport {
+        root: root.to_string(),
+        reclaimed_bytes: token_cache.reclaimed_bytes + git_token_cache.reclaimed_bytes,
+        token_cache,
+        git_token_cache,
+    })
+}
+
+/// Runs the compaction in the background the first time a root's cache is
+/// loaded, and reports the result with a `token-cache-compacted` event.
+pub(crate) fn spawn_cache_compaction(app: AppHandle<Wry>, root: String) {
+    std::thread::spawn(move || {
+        if let Ok(report) = compact_caches_for_dir(&app, &root, DEFAULT_MAX_AGE_DAYS) {
+            let _ = app.emit("token-cache-compacted", report);
+        }
+    });
+}
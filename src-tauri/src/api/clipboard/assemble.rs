@@ -0,0 +1,247 @@
+use serde::Serialize;
+use std::path::Path;
+
+const FILE_CONTENTS_OPENING_TAG: &str = "<file_contents>";
+const FILE_CONTENTS_CLOSING_TAG: &str = "</file_contents>";
+
+const TREE_OPENING_TAG: &str = "<file_tree>";
+const TREE_CLOSING_TAG: &str = "</file_tree>";
+const TREE_LEGEND: &str =
+    "The contents of the files marked with an asterisk (*) are included below.";
+
+const REPOSITORY_OPENING_TAG: &str = "<repository>";
+const REPOSITORY_CLOSING_TAG: &str = "</repository>";
+
+const GIT_DIFF_OPENING_TAG: &str = "<git_diff>";
+const GIT_DIFF_CLOSING_TAG: &str = "</git_diff>";
+
+const GIT_STASH_OPENING_TAG: &str = "<git_stash>";
+const GIT_STASH_CLOSING_TAG: &str = "</git_stash>";
+
+const GIT_LOG_OPENING_TAG: &str = "<git_log>";
+const GIT_LOG_CLOSING_TAG: &str = "</git_log>";
+
+const WEB_PAGES_OPENING_TAG: &str = "<web_pages>";
+const WEB_PAGES_CLOSING_TAG: &str = "</web_pages>";
+const WEB_PAGES_SEPARATOR: &str = "\n\n* * *\n\n";
+/// Separates pages copied on their own, which go without the group tags.
+const STANDALONE_WEB_PAGES_SEPARATOR: &str = "\n\nEnd of web page.\n###\n\n";
+
+const META_INSTRUCTIONS_OPENING_TAG: &str = "<meta_instructions>";
+const META_INSTRUCTIONS_CLOSING_TAG: &str = "</meta_instructions>";
+
+const USER_INSTRUCTIONS_OPENING_TAG: &str = "<user_instructions>";
+const USER_INSTRUCTIONS_CLOSING_TAG: &str = "</user_instructions>";
+
+pub(crate) const SECTION_SEPARATOR: &str = "\n\n";
+
+/// Declared in the order `AssembledPrompt::render` emits the groups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SectionKind {
+    Tree,
+    File,
+    Repository,
+    GitDiff,
+    GitStash,
+    GitLog,
+    WebPage,
+    MetaInstruction,
+    UserInstruction,
+}
+
+/// A single unit of the prompt: the rendered tree, one file, the repository
+/// summary, the diff, one stash, one web page or one instruction. `body` holds
+/// only the content; the headers and wrapping tags are added by `render`.
+#[derive(Clone, Debug)]
+pub struct PromptSection {
+    pub kind: SectionKind,
+    pub label: String,
+    pub body: String,
+}
+
+/// Renders a file as a fence under a `File:` line, the way file sections of
+/// the prompt appear. `label` follows the path, as in `File: a.rs (before)`.
+pub(crate) fn render_file_block(path: &str, label: Option<&str>, contents: &str) -> String {
+    let ext = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+    let label = label
+        .map(|label| format!(" ({})", label))
+        .unwrap_or_default();
+    let newline = if contents.is_empty() || contents.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+
+    format!(
+        "File: {}{}\n```{}\n{}{}```",
+        path, label, ext, contents, newline
+    )
+}
+
+impl PromptSection {
+    pub fn new(kind: SectionKind, label: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            kind,
+            label: label.into(),
+            body: body.into(),
+        }
+    }
+
+    /// The section as it appears in the payload, without the group tags.
+    pub fn render(&self) -> String {
+        match self.kind {
+            SectionKind::File => render_file_block(&self.label, None, &self.body),
+            SectionKind::WebPage => format!(
+                "The following content was fetched from: {}\n{}",
+                self.label, self.body
+            ),
+            SectionKind::MetaInstruction | SectionKind::UserInstruction => {
+                format!("{}\n{}", self.label, self.body).trim().to_string()
+            }
+            SectionKind::Tree
+            | SectionKind::Repository
+            | SectionKind::GitDiff
+            | SectionKind::GitStash
+            | SectionKind::GitLog => self.body.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AssembledPrompt {
+    pub root: String,
+    pub sections: Vec<PromptSection>,
+}
+
+impl AssembledPrompt {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: root.to_string(),
+            sections: Vec::new(),
+        }
+    }
+
+    pub fn extend(&mut self, sections: impl IntoIterator<Item = PromptSection>) {
+        self.sections.extend(sections);
+    }
+
+    fn rendered_of_kind(&self, kind: SectionKind) -> Vec<String> {
+        self.sections
+            .iter()
+            .filter(|section| section.kind == kind)
+            .map(|section| section.render())
+            .filter(|text| !text.is_empty())
+            .collect()
+    }
+
+    /// Renders the sections into the payload, grouping them under their tags
+    /// in a fixed order regardless of the order they were pushed in.
+    pub fn render(&self) -> String {
+        let mut groups: Vec<String> = Vec::new();
+
+        let tree = self.rendered_of_kind(SectionKind::Tree);
+        if !tree.is_empty() {
+            groups.push(format!(
+                "{}\n{}\n{}\n\n{}\n{}",
+                TREE_OPENING_TAG,
+                self.root,
+                tree.join("\n"),
+                TREE_LEGEND,
+                TREE_CLOSING_TAG,
+            ));
+        }
+
+        let files = self.rendered_of_kind(SectionKind::File);
+        if !files.is_empty() {
+            groups.push(format!(
+                "{}\n{}\n{}",
+                FILE_CONTENTS_OPENING_TAG,
+                files.concat(),
+                FILE_CONTENTS_CLOSING_TAG,
+            ));
+        }
+
+        let repository = self.rendered_of_kind(SectionKind::Repository);
+        if !repository.is_empty() {
+            groups.push(format!(
+                "{}\n{}{}",
+                REPOSITORY_OPENING_TAG,
+                repository.concat(),
+                REPOSITORY_CLOSING_TAG
+            ));
+        }
+
+        let diff = self.rendered_of_kind(SectionKind::GitDiff);
+        if !diff.is_empty() {
+            groups.push(format!(
+                "{}\n{}\n{}",
+                GIT_DIFF_OPENING_TAG,
+                diff.concat(),
+                GIT_DIFF_CLOSING_TAG
+            ));
+        }
+
+        let stashes = self.rendered_of_kind(SectionKind::GitStash);
+        if !stashes.is_empty() {
+            groups.push(format!(
+                "{}\n{}\n{}",
+                GIT_STASH_OPENING_TAG,
+                stashes.join("\n"),
+                GIT_STASH_CLOSING_TAG
+            ));
+        }
+
+        let log = self.rendered_of_kind(SectionKind::GitLog);
+        if !log.is_empty() {
+            groups.push(format!(
+                "{}\n{}\n{}",
+                GIT_LOG_OPENING_TAG,
+                log.concat(),
+                GIT_LOG_CLOSING_TAG
+            ));
+        }
+
+        let pages = self.rendered_of_kind(SectionKind::WebPage);
+        if !pages.is_empty() {
+            groups.push(format!(
+                "{}\n{}\n{}",
+                WEB_PAGES_OPENING_TAG,
+                pages.join(WEB_PAGES_SEPARATOR),
+                WEB_PAGES_CLOSING_TAG
+            ));
+        }
+
+        let meta = self.rendered_of_kind(SectionKind::MetaInstruction);
+        if !meta.is_empty() {
+            groups.push(format!(
+                "{}\n{}\n{}",
+                META_INSTRUCTIONS_OPENING_TAG,
+                meta.join(SECTION_SEPARATOR),
+                META_INSTRUCTIONS_CLOSING_TAG
+            ));
+        }
+
+        let user = self.rendered_of_kind(SectionKind::UserInstruction);
+        if !user.is_empty() {
+            groups.push(format!(
+                "{}\n{}\n{}",
+                USER_INSTRUCTIONS_OPENING_TAG,
+                user.join(SECTION_SEPARATOR),
+                USER_INSTRUCTIONS_CLOSING_TAG
+            ));
+        }
+
+        groups.join(SECTION_SEPARATOR)
+    }
+
+    /// Renders only the web pages, untagged, as `copy_pages_to_clipboard`
+    /// copies them.
+    pub(crate) fn render_standalone_pages(&self) -> String {
+        self.rendered_of_kind(SectionKind::WebPage)
+            .join(STANDALONE_WEB_PAGES_SEPARATOR)
+    }
+}
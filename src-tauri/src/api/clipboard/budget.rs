@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::{
+    clipboard::assemble::{AssembledPrompt, SectionKind},
+    model::lib::{context_usage, ContextUsage},
+    tokenize::{count_tokens_for_text, count_tokens_per_line},
+};
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TruncationStrategy {
+    /// Replace the largest files with a marker until the payload fits.
+    #[default]
+    DropLargestFiles,
+    /// Keep the head and tail of every file, sharing the budget between them.
+    HeadTail,
+    /// Drop web pages, largest first, before touching any file.
+    DropWebPagesFirst,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionTokenCount {
+    pub kind: SectionKind,
+    pub label: String,
+    pub token_count: usize,
+    pub truncated_token_count: usize,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTokenSummary {
+    pub total_token_count: usize,
+    pub max_tokens: Option<usize>,
+    pub within_budget: bool,
+    pub usage: Option<ContextUsage>,
+    pub sections: Vec<SectionTokenCount>,
+}
+
+impl PromptTokenSummary {
+    /// Updates the total along with the figures derived from it, for when the
+    /// payload changes after the budget was applied.
+    pub fn set_total_token_count(&mut self, total_token_count: usize) {
+        self.total_token_count = total_token_count;
+        self.within_budget = self.max_tokens.is_none_or(|max| total_token_count <= max);
+        self.usage = context_usage(total_token_count);
+    }
+}
+
+fn truncation_marker(truncated_tokens: usize) -> String {
+    format!("[truncated {} tokens]\n", truncated_tokens)
+}
+
+struct BudgetState {
+    counts: Vec<usize>,
+    truncated: Vec<usize>,
+    dropped: Vec<bool>,
+    overhead: usize,
+}
+
+impl BudgetState {
+    fn new(prompt: &AssembledPrompt) -> Self {
+        let counts: Vec<usize> = prompt
+            .sections
+            .iter()
+            .map(|section| count_tokens_for_text(&section.render()))
+            .collect();
+
+        let sections_total: usize = counts.iter().sum();
+        let overhead = count_tokens_for_text(&prompt.render()).saturating_sub(sections_total);
+
+        Self {
+            truncated: vec![0; counts.len()],
+            dropped: vec![false; counts.len()],
+            counts,
+            overhead,
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.overhead + self.counts.iter().sum::<usize>()
+    }
+
+    fn replace_body(&mut self, prompt: &mut AssembledPrompt, idx: usize, body: String) {
+        let section = &mut prompt.sections[idx];
+        section.body = body;
+        self.counts[idx] = count_tokens_for_text(&section.render());
+    }
+}
+
+fn drop_section(prompt: &mut AssembledPrompt, state: &mut BudgetState, idx: usize) {
+    let dropped = count_tokens_for_text(&prompt.sections[idx].body);
+    state.truncated[idx] += dropped;
+    state.dropped[idx] = true;
+    let marker = truncation_marker(state.truncated[idx]);
+    state.replace_body(prompt, idx, marker);
+}
+
+fn drop_largest_until_fits(
+    prompt: &mut AssembledPrompt,
+    state: &mut BudgetState,
+    kinds: &[SectionKind],
+    max_tokens: usize,
+) {
+    let mut candidates: Vec<usize> = (0..prompt.sections.len())
+        .filter(|&idx| kinds.contains(&prompt.sections[idx].kind) && !state.dropped[idx])
+        .collect();
+
+    candidates.sort_by(|&a, &b| state.counts[b].cmp(&state.counts[a]));
+
+    for idx in candidates {
+        if state.total() <= max_tokens {
+            break;
+        }
+
+        drop_section(prompt, state, idx);
+    }
+}
+
+/// Keeps as many leading and trailing lines of `text` as fit in `keep_tokens`,
+/// split evenly between both ends, and marks the removed middle.
+pub fn truncate_head_tail(text: &str, keep_tokens: usize) -> (String, usize) {
+    let line_counts = count_tokens_per_line(text);
+    let total: usize = line_counts.iter().sum();
+
+    if total <= keep_tokens {
+        return (text.to_string(), 0);
+    }
+
+    let keep_tokens = keep_tokens.saturating_sub(count_tokens_for_text(&truncation_marker(total)));
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+
+    let mut head_end = 0;
+    let mut head_tokens = 0;
+    while head_end < lines.len() && head_tokens + line_counts[head_end] <= keep_tokens / 2 {
+        head_tokens += line_counts[head_end];
+        head_end += 1;
+    }
+
+    let mut tail_start = lines.len();
+    let mut tail_tokens = 0;
+    while tail_start > head_end
+        && head_tokens + tail_tokens + line_counts[tail_start - 1] <= keep_tokens
+    {
+        tail_tokens += line_counts[tail_start - 1];
+        tail_start -= 1;
+    }
+
+    let truncated_tokens = total - head_tokens - tail_tokens;
+    let mut out = lines[..head_end].concat();
+
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+
+    out.push_str(&truncation_marker(truncated_tokens));
+    out.push_str(&lines[tail_start..].concat());
+
+    (out, truncated_tokens)
+}
+
+/// Shares what is left of the budget between the files, smallest first, so
+/// that files under their share are kept whole and the rest are cut to size.
+fn head_tail_files(prompt: &mut AssembledPrompt, state: &mut BudgetState, max_tokens: usize) {
+    let mut files: Vec<usize> = (0..prompt.sections.len())
+        .filter(|&idx| prompt.sections[idx].kind == SectionKind::File)
+        .collect();
+
+    if files.is_empty() {
+        return;
+    }
+
+    let files_total: usize = files.iter().map(|&idx| state.counts[idx]).sum();
+    let fixed = state.total() - files_total;
+    let mut remaining_budget = max_tokens.saturating_sub(fixed);
+    let mut remaining_files = files.len();
+
+    files.sort_by_key(|&idx| state.counts[idx]);
+
+    for idx in files {
+        let allowance = remaining_budget / remaining_files;
+        remaining_files -= 1;
+
+        if state.counts[idx] > allowance {
+            let header_tokens =
+                state.counts[idx].saturating_sub(count_tokens_for_text(&prompt.sections[idx].body));
+            let keep = allowance.saturating_sub(header_tokens);
+            let (body, truncated) = truncate_head_tail(&prompt.sections[idx].body, keep);
+
+            state.truncated[idx] += truncated;
+            state.replace_body(prompt, idx, body);
+        }
+
+        remaining_budget = remaining_budget.saturating_sub(state.counts[idx]);
+    }
+}
+
+/// Shrinks the prompt in place until it fits in `max_tokens` using the given
+/// strategy, then falls back to dropping the largest files and web pages.
+/// Tree, diff and instructions are never truncated.
+pub fn apply_token_budget(
+    prompt: &mut AssembledPrompt,
+    max_tokens: Option<usize>,
+    strategy: TruncationStrategy,
+) -> PromptTokenSummary {
+    let mut state = BudgetState::new(prompt);
+
+    if let Some(max_tokens) = max_tokens {
+        if state.total() > max_tokens {
+            match strategy {
+                TruncationStrategy::DropLargestFiles => {
+                    drop_largest_until_fits(prompt, &mut state, &[SectionKind::File], max_tokens)
+                }
+                TruncationStrategy::HeadTail => head_tail_files(prompt, &mut state, max_tokens),
+                TruncationStrategy::DropWebPagesFirst => {
+                    drop_largest_until_fits(prompt, &mut state, &[SectionKind::WebPage], max_tokens)
+                }
+            }
+
+            drop_largest_until_fits(
+                prompt,
+                &mut state,
+                &[SectionKind::File, SectionKind::WebPage],
+                max_tokens,
+            );
+        }
+    }
+
+    let total_token_count = count_tokens_for_text(&prompt.render());
+
+    PromptTokenSummary {
+        total_token_count,
+        max_tokens,
+        within_budget: max_tokens.is_none_or(|max| total_token_count <= max),
+        usage: context_usage(total_token_count),
+        sections: prompt
+            .sections
+            .iter()
+            .zip(state.counts.iter().zip(state.truncated.iter()))
+            .map(
+                |(section, (&token_count, &truncated_token_count))| SectionTokenCount {
+                    kind: section.kind,
+                    label: section.label.clone(),
+                    token_count,
+                    truncated_token_count,
+                },
+            )
+            .collect(),
+    }
+}
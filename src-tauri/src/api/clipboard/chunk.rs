@@ -0,0 +1,282 @@
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::api::{
+    clipboard::assemble::{AssembledPrompt, PromptSection, SectionKind, SECTION_SEPARATOR},
+    tokenize::{count_tokens_for_text, count_tokens_per_line},
+};
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PromptChunkCursor {
+    pub(crate) part: usize,
+    pub(crate) total_parts: usize,
+    pub(crate) remaining_parts: usize,
+    pub(crate) token_count: usize,
+}
+
+struct ChunkSession {
+    parts: Vec<String>,
+    next: usize,
+}
+
+static CHUNK_SESSIONS: OnceLock<Mutex<HashMap<String, ChunkSession>>> = OnceLock::new();
+
+fn chunk_sessions() -> &'static Mutex<HashMap<String, ChunkSession>> {
+    CHUNK_SESSIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub(crate) fn chunk_header(part: usize, total_parts: usize) -> String {
+    if part == total_parts {
+        format!(
+            "Part {}/{}, this is the last part. You now have the full context.",
+            part, total_parts
+        )
+    } else {
+        format!(
+            "Part {}/{}, reply only \"OK\" until the last part.",
+            part, total_parts
+        )
+    }
+}
+
+/// Cuts a section that doesn't fit in a part on its own into consecutive
+/// sections of whole lines, each under `limit` tokens. Lines that are too long
+/// on their own are cut too.
+fn split_section_by_lines(section: &PromptSection, limit: usize) -> Vec<PromptSection> {
+    let header_tokens = count_tokens_for_text(
+        &PromptSection::new(section.kind, section.label.clone(), "").render(),
+    );
+    let budget = limit.saturating_sub(header_tokens).max(1);
+
+    let line_counts = count_tokens_per_line(&section.body);
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+
+    for (line, tokens) in section.body.split_inclusive('\n').zip(line_counts) {
+        let line_pieces = if tokens > budget {
+            split_long_line(line, budget)
+        } else {
+            vec![(line, tokens)]
+        };
+
+        for (piece, tokens) in line_pieces {
+            if current_tokens + tokens > budget && !current.is_empty() {
+                pieces.push(std::mem::take(&mut current));
+                current_tokens = 0;
+            }
+
+            current.push_str(piece);
+            current_tokens += tokens;
+        }
+    }
+
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+
+    pieces
+        .into_iter()
+        .map(|body| PromptSection::new(section.kind, section.label.clone(), body))
+        .collect()
+}
+
+/// Cuts a single line that doesn't fit in `budget` tokens into pieces that do,
+/// at character boundaries. A character over the budget on its own still
+/// makes a piece.
+fn split_long_line(line: &str, budget: usize) -> Vec<(&str, usize)> {
+    let mut pieces = Vec::new();
+    let mut rest = line;
+
+    while !rest.is_empty() {
+        let first_char = rest.chars().next().map_or(1, char::len_utf8);
+        let mut end = rest.len();
+        let mut tokens = count_tokens_for_text(rest);
+
+        while tokens > budget && end > first_char {
+            let mut cut = (end * budget / tokens).max(first_char);
+            while !rest.is_char_boundary(cut) {
+                cut -= 1;
+            }
+
+            end = cut;
+            tokens = count_tokens_for_text(&rest[..end]);
+        }
+
+        pieces.push((&rest[..end], tokens));
+        rest = &rest[end..];
+    }
+
+    pieces
+}
+
+/// Tokens the group of `kind` adds to a part, measured by rendering it around
+/// a placeholder: the tags, the tree root and legend and the separator from
+/// the previous group when the group opens, and the separator between two of
+/// its sections for every section after the first.
+struct GroupCost {
+    opening: usize,
+    join: usize,
+}
+
+fn group_cost(root: &str, kind: SectionKind) -> GroupCost {
+    let section = PromptSection::new(kind, "placeholder", "placeholder\n");
+    let section_tokens = count_tokens_for_text(&section.render());
+
+    let mut prompt = AssembledPrompt::new(root);
+    prompt.sections.push(section.clone());
+    let one = count_tokens_for_text(&prompt.render());
+
+    prompt.sections.push(section);
+    let two = count_tokens_for_text(&prompt.render());
+
+    GroupCost {
+        opening: count_tokens_for_text(SECTION_SEPARATOR) + one.saturating_sub(section_tokens),
+        join: two.saturating_sub(one + section_tokens),
+    }
+}
+
+/// Tokens taken by the longest header a part gets when there are
+/// `total_parts` parts, blank line included. Counts grow with the number of
+/// digits, so the last part and the one before it are the longest of each
+/// kind.
+fn longest_header_tokens(total_parts: usize) -> usize {
+    if total_parts <= 1 {
+        return 0;
+    }
+
+    [total_parts - 1, total_parts]
+        .into_iter()
+        .map(|part| count_tokens_for_text(&format!("{}\n\n", chunk_header(part, total_parts))))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Packs `sections`, sorted by kind, into parts of at most `limit` tokens.
+fn pack_sections(root: &str, sections: &[PromptSection], limit: usize) -> Vec<AssembledPrompt> {
+    let mut chunks: Vec<AssembledPrompt> = Vec::new();
+    let mut current = AssembledPrompt::new(root);
+    let mut current_tokens = 0;
+    let mut group: Option<(SectionKind, GroupCost)> = None;
+
+    for section in sections {
+        if group.as_ref().is_none_or(|(kind, _)| *kind != section.kind) {
+            group = Some((section.kind, group_cost(root, section.kind)));
+        }
+        let (opening, join) = group
+            .as_ref()
+            .map_or((0, 0), |(_, cost)| (cost.opening, cost.join));
+
+        let pieces = if count_tokens_for_text(&section.render()) + opening > limit {
+            split_section_by_lines(section, limit.saturating_sub(opening))
+        } else {
+            vec![section.clone()]
+        };
+
+        for piece in pieces {
+            let opens_group = current
+                .sections
+                .last()
+                .is_none_or(|last| last.kind != piece.kind);
+            let piece_tokens = count_tokens_for_text(&piece.render());
+            let cost = piece_tokens + if opens_group { opening } else { join };
+
+            if current_tokens + cost > limit && !current.sections.is_empty() {
+                chunks.push(std::mem::replace(&mut current, AssembledPrompt::new(root)));
+                current_tokens = piece_tokens + opening;
+            } else {
+                current_tokens += cost;
+            }
+
+            current.sections.push(piece);
+        }
+    }
+
+    if !current.sections.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Packs the sections of `prompt` into parts of at most `max_tokens_per_part`
+/// tokens, headers included. Parts break between sections and only fall back
+/// to line boundaries when a single section is too large for a part.
+///
+/// The room kept for the headers depends on how many parts there are, so the
+/// sections are packed again whenever the count outgrows the first guess.
+pub fn split_into_chunks(
+    prompt: &AssembledPrompt,
+    max_tokens_per_part: usize,
+) -> Vec<AssembledPrompt> {
+    let mut sections = prompt.sections.clone();
+    sections.sort_by_key(|section| section.kind);
+
+    let mut total_parts = 1;
+
+    loop {
+        let limit = max_tokens_per_part
+            .saturating_sub(longest_header_tokens(total_parts))
+            .max(1);
+        let chunks = pack_sections(&prompt.root, &sections, limit);
+
+        if chunks.len() <= total_parts {
+            return chunks;
+        }
+
+        total_parts = chunks.len();
+    }
+}
+
+/// Prefixes every part with its header. A prompt that fits in one part is
+/// returned as is.
+pub fn render_chunks(rendered_parts: Vec<String>) -> Vec<String> {
+    let total_parts = rendered_parts.len();
+
+    if total_parts <= 1 {
+        return rendered_parts;
+    }
+
+    rendered_parts
+        .into_iter()
+        .enumerate()
+        .map(|(idx, part)| format!("{}\n\n{}", chunk_header(idx + 1, total_parts), part))
+        .collect()
+}
+
+pub(crate) fn store_chunk_session(root: &str, parts: Vec<String>) {
+    if let Ok(mut sessions) = chunk_sessions().lock() {
+        sessions.insert(root.to_string(), ChunkSession { parts, next: 0 });
+    }
+}
+
+/// Returns the requested part, or the next one when `part` is `None`, and
+/// moves the cursor past it.
+pub(crate) fn take_chunk(root: &str, part: Option<usize>) -> Option<(String, PromptChunkCursor)> {
+    let mut sessions = chunk_sessions().lock().ok()?;
+    let session = sessions.get_mut(root)?;
+
+    let idx = match part {
+        Some(part) => part.checked_sub(1)?,
+        None => session.next,
+    };
+
+    let text = session.parts.get(idx)?.clone();
+    session.next = idx + 1;
+
+    let total_parts = session.parts.len();
+
+    Some((
+        text.clone(),
+        PromptChunkCursor {
+            part: idx + 1,
+            total_parts,
+            remaining_parts: total_parts - session.next,
+            token_count: count_tokens_for_text(&text),
+        },
+    ))
+}
@@ -1,15 +1,46 @@
+use serde::Serialize;
 use std::collections::HashSet;
 use tauri::{AppHandle, Wry};
 
+use crate::api::clipboard::assemble::AssembledPrompt;
+use crate::api::clipboard::budget::{PromptTokenSummary, TruncationStrategy};
+use crate::api::clipboard::chunk::{store_chunk_session, take_chunk, PromptChunkCursor};
 use crate::api::clipboard::lib::{
-    build_clipboard_content, build_git_diff, build_instruction_sections, build_web_pages_section,
-    get_rendered_tree, write_to_clipboard,
+    build_web_pages_section, prepare_prompt, prepare_prompt_chunks, redact_prompt,
+    write_to_clipboard, PreparedPrompt, PromptInputs,
 };
+use crate::api::tokenize::count_tokens_for_text;
 
+use crate::api::git::{blame::FileBlameOptions, log::GitLogOptions, status::DiffScope};
 use crate::api::instruction::lib::Instruction;
+use crate::api::redact::lib::RedactionReport;
 use crate::api::tree::index::DirectoryNode;
-use crate::api::web::lib::load_page_contents_from_store;
-use crate::errors::ApplicationError;
+use crate::errors::{codes, ApplicationError};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CopyAllResult {
+    pub(crate) redaction: RedactionReport,
+    pub(crate) tokens: PromptTokenSummary,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PromptChunksResult {
+    pub(crate) total_parts: usize,
+    pub(crate) part_token_counts: Vec<usize>,
+    pub(crate) redaction: RedactionReport,
+}
+
+/// Copies the redacted prompt and reports what was redacted from it.
+fn copy_prompt(
+    app: &AppHandle<Wry>,
+    inputs: PromptInputs,
+) -> Result<RedactionReport, ApplicationError> {
+    let prepared = prepare_prompt(app, &inputs, None, None)?;
+    write_to_clipboard(app, prepared.payload)?;
+    Ok(prepared.redaction)
+}
 
 #[tauri::command]
 pub(crate) fn copy_instructions_to_clipboard(
@@ -17,11 +48,16 @@ pub(crate) fn copy_instructions_to_clipboard(
     directory_path: String,
     instruction_ids: Vec<String>,
     instructions: Vec<Instruction>,
-) -> Result<(), ApplicationError> {
-    let payload =
-        build_instruction_sections(&app, &directory_path, &instruction_ids, &instructions)?;
-
-    write_to_clipboard(&app, payload)
+) -> Result<RedactionReport, ApplicationError> {
+    copy_prompt(
+        &app,
+        PromptInputs {
+            root: directory_path,
+            instruction_ids,
+            instructions,
+            ..Default::default()
+        },
+    )
 }
 
 #[tauri::command]
@@ -29,44 +65,82 @@ pub(crate) fn copy_diffs_to_clipboard(
     app: AppHandle<Wry>,
     directory_path: String,
     paths: Vec<String>,
-) -> Result<(), ApplicationError> {
+    base_ref: Option<String>,
+    scope: Option<DiffScope>,
+) -> Result<RedactionReport, ApplicationError> {
     if paths.is_empty() {
-        return Ok(());
+        return Ok(RedactionReport::default());
     }
 
-    write_to_clipboard(&app, build_git_diff(&directory_path, paths))
+    copy_prompt(
+        &app,
+        PromptInputs {
+            root: directory_path,
+            git_diff_paths: paths,
+            git_diff_base_ref: base_ref,
+            git_diff_scope: scope.unwrap_or_default(),
+            ..Default::default()
+        },
+    )
 }
 
 #[tauri::command]
-pub(crate) fn copy_all_to_clipboard(
+pub(crate) fn copy_git_log_to_clipboard(
     app: AppHandle<Wry>,
-    full_tree: Vec<DirectoryNode>,
-    selected_nodes: HashSet<String>,
-    tree_display_mode: String,
-    git_diff_paths: Vec<String>,
-    instruction_ids: Vec<String>,
-    instructions: Vec<Instruction>,
-    root: String,
-    urls: Option<Vec<String>>,
-) -> Result<(), ApplicationError> {
-    let rendered_tree = get_rendered_tree(&tree_display_mode, &full_tree, &selected_nodes);
-
-    let base_payload =
-        build_clipboard_content(git_diff_paths, &selected_nodes, &rendered_tree, &root)?;
-
-    let instructions_payload =
-        build_instruction_sections(&app, &root, &instruction_ids, &instructions)?;
-
-    let web_pages_section = build_web_pages_section(&app, &root, &urls)?;
+    directory_path: String,
+    options: GitLogOptions,
+) -> Result<RedactionReport, ApplicationError> {
+    copy_prompt(
+        &app,
+        PromptInputs {
+            root: directory_path,
+            git_log: Some(options),
+            ..Default::default()
+        },
+    )
+}
 
-    let sections = [base_payload, web_pages_section, instructions_payload]
-        .into_iter()
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<_>>();
+#[tauri::command]
+pub(crate) fn copy_stash_to_clipboard(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    index: usize,
+) -> Result<RedactionReport, ApplicationError> {
+    copy_prompt(
+        &app,
+        PromptInputs {
+            root: directory_path,
+            git_stashes: vec![index],
+            ..Default::default()
+        },
+    )
+}
 
-    let payload = sections.join("\n\n");
+#[tauri::command]
+pub(crate) fn copy_all_to_clipboard(
+    app: AppHandle<Wry>,
+    inputs: PromptInputs,
+    max_tokens: Option<usize>,
+    truncation_strategy: Option<TruncationStrategy>,
+) -> Result<CopyAllResult, ApplicationError> {
+    let prepared = prepare_prompt(&app, &inputs, max_tokens, truncation_strategy)?;
+
+    write_to_clipboard(&app, prepared.payload)?;
+
+    Ok(CopyAllResult {
+        redaction: prepared.redaction,
+        tokens: prepared.tokens,
+    })
+}
 
-    write_to_clipboard(&app, payload)
+#[tauri::command]
+pub(crate) fn preview_prompt(
+    app: AppHandle<Wry>,
+    inputs: PromptInputs,
+    max_tokens: Option<usize>,
+    truncation_strategy: Option<TruncationStrategy>,
+) -> Result<PreparedPrompt, ApplicationError> {
+    prepare_prompt(&app, &inputs, max_tokens, truncation_strategy)
 }
 
 #[tauri::command]
@@ -76,13 +150,19 @@ pub(crate) fn copy_files_to_clipboard(
     tree_display_mode: String,
     full_tree: Vec<DirectoryNode>,
     selected_nodes: HashSet<String>,
-) -> Result<(), ApplicationError> {
-    let rendered_tree = get_rendered_tree(&tree_display_mode, &full_tree, &selected_nodes);
-
-    let payload =
-        build_clipboard_content(Vec::new(), &selected_nodes, &rendered_tree, &directory_path)?;
-
-    write_to_clipboard(&app, payload)
+    blame: Option<FileBlameOptions>,
+) -> Result<RedactionReport, ApplicationError> {
+    copy_prompt(
+        &app,
+        PromptInputs {
+            root: directory_path,
+            full_tree,
+            selected_nodes,
+            tree_display_mode,
+            blame,
+            ..Default::default()
+        },
+    )
 }
 
 #[tauri::command]
@@ -90,12 +170,52 @@ pub(crate) fn copy_pages_to_clipboard(
     app: AppHandle<Wry>,
     directory_path: String,
     urls: Vec<String>,
-) -> Result<(), ApplicationError> {
-    const PAGE_SEPARATOR: &str = "\n\nEnd of web page.\n###\n\n";
+) -> Result<RedactionReport, ApplicationError> {
+    let mut prompt = AssembledPrompt::new(&directory_path);
+    prompt.extend(build_web_pages_section(&app, &directory_path, &Some(urls))?);
 
-    let parts = load_page_contents_from_store(&app, &directory_path, &urls)?;
+    let redaction = redact_prompt(&app, &directory_path, &mut prompt)?;
+    write_to_clipboard(&app, prompt.render_standalone_pages())?;
+
+    Ok(redaction)
+}
+
+#[tauri::command]
+pub(crate) fn split_prompt_into_chunks(
+    app: AppHandle<Wry>,
+    inputs: PromptInputs,
+    max_tokens_per_part: usize,
+) -> Result<PromptChunksResult, ApplicationError> {
+    let (parts, redaction) = prepare_prompt_chunks(&app, &inputs, max_tokens_per_part)?;
+    let part_token_counts = parts
+        .iter()
+        .map(|part| count_tokens_for_text(part))
+        .collect();
+    let total_parts = parts.len();
+
+    store_chunk_session(&inputs.root, parts);
+
+    Ok(PromptChunksResult {
+        total_parts,
+        part_token_counts,
+        redaction,
+    })
+}
+
+/// Copies the part after the last one copied, or `part` (1-based) when given,
+/// and moves the cursor past it.
+#[tauri::command]
+pub(crate) fn copy_prompt_chunk(
+    app: AppHandle<Wry>,
+    root: String,
+    part: Option<usize>,
+) -> Result<PromptChunkCursor, ApplicationError> {
+    let (text, cursor) = take_chunk(&root, part).ok_or(ApplicationError {
+        code: codes::CHUNK_NOT_FOUND_ERROR,
+        message: Some("No prompt part left to copy".to_string()),
+    })?;
 
-    let payload = parts.join(PAGE_SEPARATOR);
+    write_to_clipboard(&app, text)?;
 
-    write_to_clipboard(&app, payload)
+    Ok(cursor)
 }
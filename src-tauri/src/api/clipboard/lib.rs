@@ -1,13 +1,32 @@
-use std::{collections::HashSet, fs, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, path::PathBuf};
 use tauri::{AppHandle, Wry};
 use tauri_plugin_clipboard_manager::ClipboardExt;
 
 use crate::{
     api::{
-        git::status::git_diff_text,
+        clipboard::{
+            assemble::{AssembledPrompt, PromptSection, SectionKind},
+            budget::{apply_token_budget, PromptTokenSummary, TruncationStrategy},
+            chunk::{render_chunks, split_into_chunks},
+        },
+        git::{
+            blame::{annotate_with_blame, FileBlameOptions},
+            log::{git_log_text, GitLogOptions},
+            repository::repository_text,
+            settings::git_diff_options,
+            snapshot,
+            stash::stash_diff_text,
+            status::{git_diff_text, DiffScope},
+        },
         instruction::lib::{
             get_saved_instructions, ContentLengthMode, Instruction, InstructionEntry,
         },
+        redact::{
+            command::load_redaction_patterns,
+            lib::{redact_payload, RedactionPattern, RedactionReport},
+        },
+        tokenize::count_tokens_for_text,
         tree::{
             index::DirectoryNode,
             render::lib::{render_full_tree, render_selected_tree},
@@ -18,20 +37,38 @@ use crate::{
     store::{open_store, StoreCategoryKey},
 };
 
-const FILE_CONTENTS_OPENING_TAG: &str = "<file_contents>";
-const FILE_CONTENTS_CLOSING_TAG: &str = "</file_contents>";
-
-const TREE_OPENING_TAG: &str = "<file_tree>";
-const TREE_CLOSING_TAG: &str = "</file_tree>";
-const TREE_LEGEND: &str =
-    "The contents of the files marked with an asterisk (*) are included below.";
-
-const GIT_DIFF_OPENING_TAG: &str = "<git_diff>";
-const GIT_DIFF_CLOSING_TAG: &str = "</git_diff>";
+/// Everything a copy or preview command can put into a prompt. The commands
+/// that build a whole prompt take it as is; those that only copy part of it
+/// leave the other fields at their defaults.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct PromptInputs {
+    pub(crate) root: String,
+    pub(crate) full_tree: Vec<DirectoryNode>,
+    pub(crate) selected_nodes: HashSet<String>,
+    pub(crate) tree_display_mode: String,
+    pub(crate) git_diff_paths: Vec<String>,
+    /// Ref or range the diffs compare against, HEAD when unset.
+    pub(crate) git_diff_base_ref: Option<String>,
+    pub(crate) git_diff_scope: DiffScope,
+    pub(crate) git_log: Option<GitLogOptions>,
+    /// Indexes of the stashes whose changes are included.
+    pub(crate) git_stashes: Vec<usize>,
+    /// Adds the branch, upstream and remotes of the repository.
+    pub(crate) include_repository: bool,
+    pub(crate) blame: Option<FileBlameOptions>,
+    pub(crate) instruction_ids: Vec<String>,
+    pub(crate) instructions: Vec<Instruction>,
+    pub(crate) urls: Option<Vec<String>>,
+}
 
-const WEB_PAGES_OPENING_TAG: &str = "<web_pages>";
-const WEB_PAGES_CLOSING_TAG: &str = "</web_pages>";
-const WEB_PAGES_SEPARATOR: &str = "\n\n* * *\n\n";
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PreparedPrompt {
+    pub(crate) payload: String,
+    pub(crate) tokens: PromptTokenSummary,
+    pub(crate) redaction: RedactionReport,
+}
 
 pub fn get_rendered_tree(
     tree_display_mode: &str,
@@ -45,8 +82,13 @@ pub fn get_rendered_tree(
     }
 }
 
-pub fn concatenate_files(selected_files: &HashSet<String>) -> Result<String, ApplicationError> {
-    let mut concatenated_files = String::new();
+/// Reads the selected files, annotating them with blame data when `blame` is
+/// set.
+pub fn build_files(
+    selected_files: &HashSet<String>,
+    blame: Option<&FileBlameOptions>,
+) -> Result<Vec<PromptSection>, ApplicationError> {
+    let mut sections = Vec::new();
 
     let mut file_strs: Vec<&String> = selected_files.iter().collect();
     file_strs.sort();
@@ -54,123 +96,145 @@ pub fn concatenate_files(selected_files: &HashSet<String>) -> Result<String, App
     for file_str in file_strs {
         let file = PathBuf::from(file_str);
 
-        if !file.is_file() {
+        if !snapshot::is_file(&file) {
             continue;
         }
 
-        let bytes = fs::read(&file).map_err(|_| ApplicationError {
+        let bytes = snapshot::read_file(&file).map_err(|_| ApplicationError {
             code: codes::FILE_READ_ERROR,
             message: Some(format!("Failed to read file: {}", file.display())),
         })?;
 
-        let header = format!("File: {}\n", file.display());
-        let ext = file.extension().and_then(|ext| ext.to_str()).unwrap_or("");
-        let wrapper_start = format!("```{}\n", ext);
-        let text = String::from_utf8_lossy(&bytes);
-        let wrapper_end = "```";
-
-        concatenated_files.push_str(&format!(
-            "{}{}{}{}",
-            header, wrapper_start, text, wrapper_end
+        let contents = String::from_utf8_lossy(&bytes);
+        let annotated = match blame {
+            Some(options) => annotate_with_blame(&file, &contents, options).map_err(|message| {
+                ApplicationError {
+                    code: codes::GIT_REF_ERROR,
+                    message: Some(message),
+                }
+            })?,
+            None => None,
+        };
+
+        sections.push(PromptSection::new(
+            SectionKind::File,
+            file.display().to_string(),
+            annotated.unwrap_or_else(|| contents.into_owned()),
         ));
     }
 
-    Ok(concatenated_files)
+    Ok(sections)
 }
 
-pub fn build_file_tree(rendered_tree: &str, root: &str) -> String {
+pub fn build_file_tree(rendered_tree: &str) -> Option<PromptSection> {
     if rendered_tree.is_empty() {
-        return "".to_string();
+        return None;
     }
 
-    format!(
-        "{}\n{}\n{}\n\n{}\n{}",
-        TREE_OPENING_TAG, root, rendered_tree, TREE_LEGEND, TREE_CLOSING_TAG,
-    )
+    Some(PromptSection::new(SectionKind::Tree, "tree", rendered_tree))
 }
 
-pub fn build_git_diff(root: &str, git_diff_paths: Vec<String>) -> String {
+/// Diffs of `git_diff_paths` against `base_ref` in `scope`, with the
+/// project's diff options.
+pub fn build_git_diff(
+    app: &AppHandle<Wry>,
+    root: &str,
+    git_diff_paths: Vec<String>,
+    base_ref: Option<&str>,
+    scope: DiffScope,
+) -> Result<Option<PromptSection>, ApplicationError> {
     if git_diff_paths.is_empty() {
-        return String::new();
+        return Ok(None);
     }
 
-    if let Some(diff) = git_diff_text(root, git_diff_paths) {
-        return format!(
-            "{}\n{}\n{}",
-            GIT_DIFF_OPENING_TAG, diff, GIT_DIFF_CLOSING_TAG
-        );
-    }
+    let options = git_diff_options(app, root);
 
-    String::new()
+    let text =
+        git_diff_text(root, git_diff_paths, base_ref, scope, &options).map_err(|message| {
+            ApplicationError {
+                code: codes::GIT_REF_ERROR,
+                message: Some(message),
+            }
+        })?;
+
+    Ok(text.map(|diff| PromptSection::new(SectionKind::GitDiff, "git_diff", diff)))
 }
 
-pub fn build_files(selected_nodes: &HashSet<String>) -> Result<String, ApplicationError> {
-    if selected_nodes.is_empty() {
-        return Ok(String::new());
+pub fn build_repository(root: &str, enabled: bool) -> Option<PromptSection> {
+    if !enabled {
+        return None;
     }
 
-    let concatenated_files = concatenate_files(selected_nodes)?;
-
-    Ok(format!(
-        "{}\n{}\n{}",
-        FILE_CONTENTS_OPENING_TAG, concatenated_files, FILE_CONTENTS_CLOSING_TAG,
-    ))
+    repository_text(root)
+        .filter(|text| !text.is_empty())
+        .map(|text| PromptSection::new(SectionKind::Repository, "repository", text))
 }
 
-pub fn build_web_pages_section(
+/// Diffs of the stashes at `indexes`, with the project's diff options.
+pub fn build_git_stashes(
     app: &AppHandle<Wry>,
-    directory_path: &str,
-    urls_opt: &Option<Vec<String>>,
-) -> Result<String, ApplicationError> {
-    let urls = match urls_opt {
-        Some(u) if !u.is_empty() => u,
-        _ => return Ok(String::new()),
-    };
+    root: &str,
+    indexes: &[usize],
+) -> Result<Vec<PromptSection>, ApplicationError> {
+    if indexes.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    let parts = load_page_contents_from_store(app, directory_path, urls)?;
+    let options = git_diff_options(app, root);
+    let mut sections = Vec::new();
 
-    if parts.is_empty() {
-        return Ok(String::new());
+    for &index in indexes {
+        let text = stash_diff_text(root, index, &options).map_err(|message| ApplicationError {
+            code: codes::GIT_REF_ERROR,
+            message: Some(message),
+        })?;
+
+        if let Some(text) = text {
+            sections.push(PromptSection::new(
+                SectionKind::GitStash,
+                format!("stash@{{{}}}", index),
+                text,
+            ));
+        }
     }
 
-    let payload = parts.join(WEB_PAGES_SEPARATOR);
-    Ok(format!(
-        "{}\n{}\n{}",
-        WEB_PAGES_OPENING_TAG, payload, WEB_PAGES_CLOSING_TAG
-    ))
+    Ok(sections)
 }
 
-pub fn build_clipboard_content(
-    git_diff_paths: Vec<String>,
-    selected_nodes: &HashSet<String>,
-    rendered_tree: &str,
+pub fn build_git_log(
     root: &str,
-) -> Result<String, ApplicationError> {
-    let file_tree = build_file_tree(rendered_tree, root);
-    let files = build_files(selected_nodes)?;
-    let git_diff = build_git_diff(root, git_diff_paths);
+    options: Option<&GitLogOptions>,
+) -> Result<Option<PromptSection>, ApplicationError> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
 
-    let sections = [file_tree, files, git_diff]
-        .into_iter()
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<_>>();
+    let text = git_log_text(root, options).map_err(|message| ApplicationError {
+        code: codes::GIT_REF_ERROR,
+        message: Some(message),
+    })?;
 
-    Ok(sections.join("\n\n"))
+    Ok(text
+        .filter(|log| !log.is_empty())
+        .map(|log| PromptSection::new(SectionKind::GitLog, "git_log", log)))
 }
 
-pub fn format_instruction_entries<T>(entries: &[T]) -> String
-where
-    T: InstructionEntry,
-{
-    entries
-        .iter()
-        .map(|entry| {
-            format!("{}\n{}", entry.name(), entry.content())
-                .trim()
-                .to_string()
-        })
-        .collect::<Vec<_>>()
-        .join("\n\n")
+pub fn build_web_pages_section(
+    app: &AppHandle<Wry>,
+    directory_path: &str,
+    urls_opt: &Option<Vec<String>>,
+) -> Result<Vec<PromptSection>, ApplicationError> {
+    let urls = match urls_opt {
+        Some(u) if !u.is_empty() => u,
+        _ => return Ok(Vec::new()),
+    };
+
+    let pages = load_page_contents_from_store(app, directory_path, urls)?;
+
+    Ok(pages
+        .into_iter()
+        .map(|(url, content)| PromptSection::new(SectionKind::WebPage, url, content))
+        .collect())
 }
 
 pub fn build_instruction_sections(
@@ -178,12 +242,12 @@ pub fn build_instruction_sections(
     directory_path: &str,
     instruction_ids: &[String],
     instructions: &[Instruction],
-) -> Result<String, ApplicationError> {
+) -> Result<Vec<PromptSection>, ApplicationError> {
     if instruction_ids.is_empty() && instructions.is_empty() {
-        return Ok(String::new());
+        return Ok(Vec::new());
     }
 
-    let mut meta_instructions = Vec::new();
+    let mut sections = Vec::new();
 
     if !instruction_ids.is_empty() {
         let store = open_store(app)?;
@@ -199,37 +263,146 @@ pub fn build_instruction_sections(
 
         for id in instruction_ids {
             if let Some(entry) = stored_instructions.iter().find(|i| &i.id == id) {
-                meta_instructions.push(entry.clone());
+                sections.push(instruction_section(SectionKind::MetaInstruction, entry));
             }
         }
 
         store.close_resource();
     }
 
-    let meta_section = format_instruction_entries(&meta_instructions);
-    let user_section = format_instruction_entries(instructions);
-
-    let mut sections = Vec::new();
-
-    if !meta_section.is_empty() {
-        sections.push(format!(
-            "<meta_instructions>\n{}\n</meta_instructions>",
-            meta_section
+    for instruction in instructions {
+        sections.push(instruction_section(
+            SectionKind::UserInstruction,
+            instruction,
         ));
     }
 
-    if !user_section.is_empty() {
-        sections.push(format!(
-            "<user_instructions>\n{}\n</user_instructions>",
-            user_section
-        ));
+    Ok(sections)
+}
+
+fn instruction_section<T>(kind: SectionKind, entry: &T) -> PromptSection
+where
+    T: InstructionEntry,
+{
+    PromptSection::new(kind, entry.name(), entry.content())
+}
+
+pub(crate) fn assemble_prompt(
+    app: &AppHandle<Wry>,
+    inputs: &PromptInputs,
+) -> Result<AssembledPrompt, ApplicationError> {
+    let rendered_tree = get_rendered_tree(
+        &inputs.tree_display_mode,
+        &inputs.full_tree,
+        &inputs.selected_nodes,
+    );
+
+    let mut prompt = AssembledPrompt::new(&inputs.root);
+    prompt.extend(build_file_tree(&rendered_tree));
+    prompt.extend(build_files(&inputs.selected_nodes, inputs.blame.as_ref())?);
+    prompt.extend(build_repository(&inputs.root, inputs.include_repository));
+    prompt.extend(build_git_diff(
+        app,
+        &inputs.root,
+        inputs.git_diff_paths.clone(),
+        inputs.git_diff_base_ref.as_deref(),
+        inputs.git_diff_scope,
+    )?);
+    prompt.extend(build_git_stashes(app, &inputs.root, &inputs.git_stashes)?);
+    prompt.extend(build_git_log(&inputs.root, inputs.git_log.as_ref())?);
+    prompt.extend(build_web_pages_section(app, &inputs.root, &inputs.urls)?);
+    prompt.extend(build_instruction_sections(
+        app,
+        &inputs.root,
+        &inputs.instruction_ids,
+        &inputs.instructions,
+    )?);
+
+    Ok(prompt)
+}
+
+/// Assembles, budgets and redacts the prompt. This is the payload exactly as
+/// the copy commands write it to the clipboard.
+pub(crate) fn prepare_prompt(
+    app: &AppHandle<Wry>,
+    inputs: &PromptInputs,
+    max_tokens: Option<usize>,
+    truncation_strategy: Option<TruncationStrategy>,
+) -> Result<PreparedPrompt, ApplicationError> {
+    let mut prompt = assemble_prompt(app, inputs)?;
+
+    let mut tokens = apply_token_budget(
+        &mut prompt,
+        max_tokens,
+        truncation_strategy.unwrap_or_default(),
+    );
+
+    let redaction = redact_prompt(app, &inputs.root, &mut prompt)?;
+    let payload = prompt.render();
+
+    if redaction.redacted_count > 0 {
+        tokens.set_total_token_count(count_tokens_for_text(&payload));
     }
 
-    if sections.is_empty() {
-        Ok(String::new())
-    } else {
-        Ok(sections.join("\n\n"))
+    Ok(PreparedPrompt {
+        payload,
+        tokens,
+        redaction,
+    })
+}
+
+/// Redacts the sections of `prompt` with the built-in detectors and the
+/// patterns of `root`.
+pub(crate) fn redact_prompt(
+    app: &AppHandle<Wry>,
+    root: &str,
+    prompt: &mut AssembledPrompt,
+) -> Result<RedactionReport, ApplicationError> {
+    let custom_patterns = load_redaction_patterns(app, root)?;
+    Ok(redact_sections(prompt, &custom_patterns))
+}
+
+/// Assembles the prompt, redacts it and splits it into parts of at most
+/// `max_tokens_per_part` tokens, each carrying its part header. Sections are
+/// redacted before splitting so a part boundary can't cut a secret in two.
+pub(crate) fn prepare_prompt_chunks(
+    app: &AppHandle<Wry>,
+    inputs: &PromptInputs,
+    max_tokens_per_part: usize,
+) -> Result<(Vec<String>, RedactionReport), ApplicationError> {
+    let mut prompt = assemble_prompt(app, inputs)?;
+    let redaction = redact_prompt(app, &inputs.root, &mut prompt)?;
+
+    let parts = split_into_chunks(&prompt, max_tokens_per_part)
+        .into_iter()
+        .map(|chunk| chunk.render())
+        .collect();
+
+    Ok((render_chunks(parts), redaction))
+}
+
+/// Redacts the body of every section in place. Hit lines count from the start
+/// of their section, whose label is the source when no file is named inside.
+fn redact_sections(
+    prompt: &mut AssembledPrompt,
+    custom_patterns: &[RedactionPattern],
+) -> RedactionReport {
+    let mut redaction = RedactionReport::default();
+
+    for section in &mut prompt.sections {
+        let (body, report) = redact_payload(&section.body, custom_patterns);
+        section.body = body;
+
+        redaction
+            .hits
+            .extend(report.hits.into_iter().map(|mut hit| {
+                hit.source.get_or_insert_with(|| section.label.clone());
+                hit
+            }));
     }
+
+    redaction.redacted_count = redaction.hits.len();
+    redaction
 }
 
 pub fn write_to_clipboard(app: &AppHandle<Wry>, payload: String) -> Result<(), ApplicationError> {
@@ -1,3 +1,6 @@
 pub(crate) use lib::get_rendered_tree;
+pub mod assemble;
+pub mod budget;
+pub mod chunk;
 pub(crate) mod command;
-mod lib;
+pub(crate) mod lib;
@@ -1,7 +1,12 @@
-use ignore::WalkBuilder;
+use serde::Serialize;
 use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
+use crate::api::directory::lib::directory_walker;
+use crate::api::git::snapshot::{
+    register_snapshot, snapshot_root, snapshot_tree, unregister_snapshot,
+};
+use crate::api::tree::cache::cache;
 use crate::api::tree::index::DirectoryNode;
 use crate::errors::{codes, ApplicationError};
 
@@ -9,14 +14,7 @@ use crate::errors::{codes, ApplicationError};
 pub(crate) fn list_directory(path: &str) -> Result<Vec<DirectoryNode>, ApplicationError> {
     let dir = PathBuf::from(&path);
 
-    let walker = WalkBuilder::new(&dir)
-        .hidden(false)
-        .git_ignore(true)
-        .git_exclude(true)
-        .git_global(false)
-        .parents(true)
-        .follow_links(false)
-        .build();
+    let walker = directory_walker(&dir).build();
 
     let mut children_map: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
     let mut is_dir_map: HashMap<PathBuf, bool> = HashMap::new();
@@ -150,3 +148,55 @@ pub(crate) fn list_directory(path: &str) -> Result<Vec<DirectoryNode>, Applicati
 
     Ok(result)
 }
+
+/// The tree of a commit, listed under `root` instead of the directory path.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RefSnapshot {
+    /// Stands in for the directory path in the selection, search, token and
+    /// copy commands, which then read files from `commit`.
+    pub(crate) root: String,
+    pub(crate) commit: String,
+    pub(crate) tree: Vec<DirectoryNode>,
+}
+
+/// Lists the tree of `git_ref` (a branch, tag or commit) in the repository at
+/// `directory_path` without checking it out.
+#[tauri::command]
+pub(crate) fn list_directory_at_ref(
+    directory_path: String,
+    git_ref: String,
+) -> Result<RefSnapshot, ApplicationError> {
+    let ref_error = |message: String| ApplicationError {
+        code: codes::GIT_REF_ERROR,
+        message: Some(message),
+    };
+
+    let root = snapshot_root(&directory_path, &git_ref);
+    let (commit, changed) =
+        register_snapshot(&directory_path, &git_ref, &root).map_err(ref_error)?;
+
+    // The ref may have moved since the index under this root was built.
+    if changed {
+        cache().write().expect("cache write poisoned").remove(&root);
+    }
+
+    let tree = snapshot_tree(&root)
+        .unwrap_or_else(|| Err(format!("{} isn't registered", root)))
+        .map_err(ref_error)?;
+
+    Ok(RefSnapshot {
+        root,
+        commit: commit.to_string(),
+        tree,
+    })
+}
+
+/// Forgets a snapshot listed by `list_directory_at_ref` and drops its index.
+/// Its files can't be read or copied afterwards.
+#[tauri::command]
+pub(crate) fn close_directory_at_ref(root: String) {
+    if unregister_snapshot(&root) {
+        cache().write().expect("cache write poisoned").remove(&root);
+    }
+}
@@ -1,6 +1,10 @@
+use ignore::WalkBuilder;
 use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 
+use crate::api::export::lib::PROJECT_EXPORT_DIRECTORY;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PickedDirectory {
@@ -9,6 +13,33 @@ pub struct PickedDirectory {
     pub pretty_path: String,
 }
 
+/// Whether `name` is a directory the app itself writes into projects, which
+/// is kept out of the tree and the git watcher.
+pub(crate) fn is_app_directory(name: &OsStr) -> bool {
+    name == PROJECT_EXPORT_DIRECTORY
+}
+
+/// Files of ignore rules the walker reads in every directory, the ones that
+/// take precedence first.
+pub(crate) const IGNORE_FILES: &[&str] = &[".ignore", ".gitignore"];
+
+/// Walks `dir` with the ignore rules of the file tree: the `IGNORE_FILES`,
+/// including those of parent directories, and `.git/info/exclude`. The app's
+/// own directories are skipped.
+pub(crate) fn directory_walker(dir: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(dir);
+    builder
+        .hidden(false)
+        .ignore(true)
+        .git_ignore(true)
+        .git_exclude(true)
+        .git_global(false)
+        .parents(true)
+        .follow_links(false)
+        .filter_entry(|entry| !is_app_directory(entry.file_name()));
+    builder
+}
+
 pub fn home_dir() -> Option<PathBuf> {
     std::env::var_os("HOME")
         .map(PathBuf::from)
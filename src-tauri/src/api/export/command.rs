@@ -0,0 +1,125 @@
+use rfd::FileDialog;
+use serde::Serialize;
+use serde_json::Value;
+use std::{fs, path::PathBuf};
+use tauri::{AppHandle, Wry};
+
+use crate::{
+    api::{
+        clipboard::{
+            budget::{PromptTokenSummary, TruncationStrategy},
+            lib::{prepare_prompt, PromptInputs},
+        },
+        export::lib::{
+            project_export_path, render_export, resolve_export_path, ExportDestination,
+            ExportFormat,
+        },
+        git::snapshot::snapshot_source,
+        redact::lib::RedactionReport,
+    },
+    errors::{codes, ApplicationError},
+    store::{read_project_value, write_project_value, StoreDataKey},
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ExportResult {
+    pub(crate) path: String,
+    pub(crate) format: ExportFormat,
+    pub(crate) redaction: RedactionReport,
+    pub(crate) tokens: PromptTokenSummary,
+}
+
+fn pick_export_path(format: ExportFormat) -> Result<PathBuf, ApplicationError> {
+    let picked = FileDialog::new()
+        .set_title("Export prompt")
+        .set_file_name(format!("prompt.{}", format.extension()))
+        .add_filter("Markdown", &["md"])
+        .add_filter("Text", &["txt"])
+        .add_filter("JSON", &["json"])
+        .add_filter("XML", &["xml"])
+        .save_file();
+
+    picked.ok_or(ApplicationError {
+        code: codes::DIALOG_CANCELLED,
+        message: None,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn export_prompt_to_file(
+    app: AppHandle<Wry>,
+    inputs: PromptInputs,
+    max_tokens: Option<usize>,
+    truncation_strategy: Option<TruncationStrategy>,
+    format: Option<ExportFormat>,
+    destination: Option<ExportDestination>,
+) -> Result<ExportResult, ApplicationError> {
+    let requested_format = format.unwrap_or(ExportFormat::Md);
+    // A snapshot root isn't on disk, so its exports go to the project it was
+    // listed from.
+    let project_root = snapshot_source(&inputs.root).unwrap_or(&inputs.root);
+
+    let path = match destination.unwrap_or_default() {
+        ExportDestination::Dialog => pick_export_path(requested_format)?,
+        ExportDestination::Project => project_export_path(project_root, requested_format),
+        ExportDestination::Configured => {
+            let configured: String =
+                read_project_value(&app, project_root, StoreDataKey::EXPORT_PATH).ok_or(
+                    ApplicationError {
+                        code: codes::EXPORT_PATH_NOT_SET_ERROR,
+                        message: None,
+                    },
+                )?;
+            resolve_export_path(project_root, &configured)
+        }
+        ExportDestination::Path { path } => PathBuf::from(path),
+    };
+
+    let format = format
+        .or_else(|| ExportFormat::from_path(&path))
+        .unwrap_or(requested_format);
+
+    let prepared = prepare_prompt(&app, &inputs, max_tokens, truncation_strategy)?;
+    let contents = render_export(&inputs.root, &prepared, format);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| ApplicationError {
+            code: codes::FILE_WRITE_ERROR,
+            message: Some(format!("Failed to create {}: {}", parent.display(), err)),
+        })?;
+    }
+
+    fs::write(&path, contents).map_err(|err| ApplicationError {
+        code: codes::FILE_WRITE_ERROR,
+        message: Some(format!("Failed to write {}: {}", path.display(), err)),
+    })?;
+
+    Ok(ExportResult {
+        path: path.to_string_lossy().into_owned(),
+        format,
+        redaction: prepared.redaction,
+        tokens: prepared.tokens,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn get_export_path(app: AppHandle<Wry>, directory_path: String) -> Option<String> {
+    read_project_value(&app, &directory_path, StoreDataKey::EXPORT_PATH)
+}
+
+/// Saves where `ExportDestination::Configured` writes for the project. A
+/// relative path is resolved against the project, `None` clears it.
+#[tauri::command]
+pub(crate) fn set_export_path(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    path: Option<String>,
+) -> Result<(), ApplicationError> {
+    let value = path
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+        .map(Value::String);
+
+    write_project_value(&app, &directory_path, StoreDataKey::EXPORT_PATH, value)
+}
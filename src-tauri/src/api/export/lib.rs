@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+use crate::api::clipboard::lib::PreparedPrompt;
+
+pub const PROJECT_EXPORT_DIRECTORY: &str = ".promptlab";
+pub const PROJECT_EXPORT_FILE_STEM: &str = "last-prompt";
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Md,
+    Txt,
+    Json,
+    Xml,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Md => "md",
+            ExportFormat::Txt => "txt",
+            ExportFormat::Json => "json",
+            ExportFormat::Xml => "xml",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())?
+            .to_lowercase()
+            .as_str()
+        {
+            "md" | "markdown" => Some(ExportFormat::Md),
+            "txt" => Some(ExportFormat::Txt),
+            "json" => Some(ExportFormat::Json),
+            "xml" => Some(ExportFormat::Xml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ExportDestination {
+    /// Ask for a location with the native save dialog.
+    #[default]
+    Dialog,
+    /// Write to `<root>/.promptlab/last-prompt.<ext>` so editors and CLIs can
+    /// pick it up.
+    Project,
+    /// Write to the path saved for the project with `set_export_path`.
+    Configured,
+    /// Write to a path chosen ahead of time.
+    Path { path: String },
+}
+
+pub fn project_export_path(root: &str, format: ExportFormat) -> PathBuf {
+    Path::new(root).join(PROJECT_EXPORT_DIRECTORY).join(format!(
+        "{}.{}",
+        PROJECT_EXPORT_FILE_STEM,
+        format.extension()
+    ))
+}
+
+/// Relative export paths are kept relative to the project.
+pub fn resolve_export_path(root: &str, path: &str) -> PathBuf {
+    Path::new(root).join(path)
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn render_xml(root: &str, prepared: &PreparedPrompt) -> String {
+    let sections = prepared
+        .tokens
+        .sections
+        .iter()
+        .map(|section| {
+            format!(
+                "    <section kind=\"{}\" label=\"{}\" tokens=\"{}\" truncatedTokens=\"{}\"/>",
+                serde_json::to_value(section.kind)
+                    .ok()
+                    .and_then(|v| v.as_str().map(|s| s.to_string()))
+                    .unwrap_or_default(),
+                escape_xml(&section.label),
+                section.token_count,
+                section.truncated_token_count,
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<prompt root=\"{}\" tokens=\"{}\">\n  <sections>\n{}\n  </sections>\n  <payload>{}</payload>\n</prompt>\n",
+        escape_xml(root),
+        prepared.tokens.total_token_count,
+        sections,
+        escape_xml(&prepared.payload),
+    )
+}
+
+pub fn render_export(root: &str, prepared: &PreparedPrompt, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Md | ExportFormat::Txt => prepared.payload.clone(),
+        ExportFormat::Json => serde_json::to_string_pretty(&json!({
+            "root": root,
+            "payload": prepared.payload,
+            "tokens": prepared.tokens,
+            "redaction": prepared.redaction,
+        }))
+        .unwrap_or_default(),
+        ExportFormat::Xml => render_xml(root, prepared),
+    }
+}
@@ -0,0 +1,258 @@
+use crate::api::git::log::format_git_date;
+use git2::{Oid, Repository};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use xxhash_rust::xxh3::xxh3_64;
+
+const MAX_CACHED_BLAMES: usize = 512;
+const SHORT_ID_LEN: usize = 7;
+const UNCOMMITTED_LABEL: &str = "uncommitted";
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum BlameGranularity {
+    /// Every line is prefixed with its commit, date and author.
+    #[default]
+    Line,
+    /// A marker line precedes each run of lines from the same commit.
+    Hunk,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct FileBlameOptions {
+    pub(crate) granularity: BlameGranularity,
+    /// Only lines changed after this ref are annotated. Uncommitted lines
+    /// always are.
+    pub(crate) since_ref: Option<String>,
+}
+
+/// A run of lines of the working copy last changed by the same commit.
+/// `commit` is `None` for lines that aren't committed yet.
+struct BlameHunk {
+    start_line: usize,
+    lines: usize,
+    commit: Option<Oid>,
+    label: String,
+}
+
+struct CachedBlame {
+    head: Oid,
+    content_hash: u64,
+    hunks: Arc<Vec<BlameHunk>>,
+}
+
+static BLAME_CACHE: OnceLock<Mutex<HashMap<PathBuf, CachedBlame>>> = OnceLock::new();
+
+fn blame_cache() -> &'static Mutex<HashMap<PathBuf, CachedBlame>> {
+    BLAME_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Prefixes `contents` with blame data. `Ok(None)` when the file isn't
+/// tracked by a repository, `Err` when `since_ref` doesn't resolve.
+pub(crate) fn annotate_with_blame(
+    path: &Path,
+    contents: &str,
+    options: &FileBlameOptions,
+) -> Result<Option<String>, String> {
+    let Some(dir) = path.parent() else {
+        return Ok(None);
+    };
+    let Ok(repo) = Repository::discover(dir) else {
+        return Ok(None);
+    };
+    let Some(hunks) = file_blame(&repo, path, contents) else {
+        return Ok(None);
+    };
+
+    let since = match options
+        .since_ref
+        .as_deref()
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+    {
+        Some(spec) => {
+            let base = repo
+                .revparse_single(spec)
+                .and_then(|object| object.peel_to_commit())
+                .map_err(|err| format!("{}: {}", spec, err.message()))?;
+            Some((spec, base.id()))
+        }
+        None => None,
+    };
+
+    let mut changed_since: HashMap<Oid, bool> = HashMap::new();
+    let mut is_shown = |commit: Option<Oid>| -> bool {
+        let (Some((_, base)), Some(commit)) = (since, commit) else {
+            return true;
+        };
+        *changed_since.entry(commit).or_insert_with(|| {
+            commit != base && !repo.graph_descendant_of(base, commit).unwrap_or(false)
+        })
+    };
+
+    let shown: Vec<bool> = hunks.iter().map(|hunk| is_shown(hunk.commit)).collect();
+
+    Ok(Some(match options.granularity {
+        BlameGranularity::Line => render_lines(contents, &hunks, &shown),
+        BlameGranularity::Hunk => render_hunks(contents, &hunks, &shown, since.map(|(s, _)| s)),
+    }))
+}
+
+/// Blame of the working copy, cached per file until HEAD or the contents
+/// change.
+fn file_blame(repo: &Repository, path: &Path, contents: &str) -> Option<Arc<Vec<BlameHunk>>> {
+    let workdir = repo.workdir()?;
+    let absolute = path.canonicalize().ok()?;
+    let relative = absolute
+        .strip_prefix(workdir.canonicalize().ok()?)
+        .ok()?
+        .to_path_buf();
+
+    let head = repo.head().ok()?.peel_to_commit().ok()?.id();
+    let content_hash = xxh3_64(contents.as_bytes());
+
+    if let Some(cached) = blame_cache().lock().ok().and_then(|cache| {
+        cache
+            .get(&absolute)
+            .filter(|c| c.head == head && c.content_hash == content_hash)
+            .map(|c| c.hunks.clone())
+    }) {
+        return Some(cached);
+    }
+
+    let committed = repo.blame_file(&relative, None).ok()?;
+    let blame = committed.blame_buffer(contents.as_bytes()).ok()?;
+
+    let mut labels: HashMap<Oid, String> = HashMap::new();
+    let hunks: Vec<BlameHunk> = blame
+        .iter()
+        .map(|hunk| {
+            let oid = hunk.final_commit_id();
+            let commit = (!oid.is_zero()).then_some(oid);
+            let label = match commit {
+                Some(oid) => labels
+                    .entry(oid)
+                    .or_insert_with(|| commit_label(repo, oid))
+                    .clone(),
+                None => UNCOMMITTED_LABEL.to_string(),
+            };
+
+            BlameHunk {
+                start_line: hunk.final_start_line(),
+                lines: hunk.lines_in_hunk(),
+                commit,
+                label,
+            }
+        })
+        .collect();
+
+    let hunks = Arc::new(hunks);
+
+    if let Ok(mut cache) = blame_cache().lock() {
+        if cache.len() >= MAX_CACHED_BLAMES {
+            cache.clear();
+        }
+        cache.insert(
+            absolute,
+            CachedBlame {
+                head,
+                content_hash,
+                hunks: hunks.clone(),
+            },
+        );
+    }
+
+    Some(hunks)
+}
+
+/// `a1b2c3d 2024-05-01 Jane Doe`
+fn commit_label(repo: &Repository, oid: Oid) -> String {
+    let short: String = oid.to_string().chars().take(SHORT_ID_LEN).collect();
+
+    match repo.find_commit(oid) {
+        Ok(commit) => {
+            let author = commit.author();
+            format!(
+                "{} {} {}",
+                short,
+                format_git_date(author.when()),
+                author.name().unwrap_or_default()
+            )
+        }
+        Err(_) => short,
+    }
+}
+
+/// Index of the hunk covering each line, 0-based.
+fn hunk_per_line(hunks: &[BlameHunk], line_count: usize) -> Vec<Option<usize>> {
+    let mut per_line = vec![None; line_count];
+
+    for (idx, hunk) in hunks.iter().enumerate() {
+        let start = hunk.start_line.saturating_sub(1);
+        let end = (start + hunk.lines).min(line_count);
+        for slot in per_line.iter_mut().take(end).skip(start) {
+            *slot = Some(idx);
+        }
+    }
+
+    per_line
+}
+
+fn render_lines(contents: &str, hunks: &[BlameHunk], shown: &[bool]) -> String {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let per_line = hunk_per_line(hunks, lines.len());
+    let width = hunks
+        .iter()
+        .zip(shown)
+        .filter(|(_, &shown)| shown)
+        .map(|(hunk, _)| hunk.label.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::with_capacity(contents.len() + lines.len() * (width + 3));
+
+    for (line, hunk) in lines.iter().zip(per_line) {
+        let label = match hunk {
+            Some(idx) if shown[idx] => hunks[idx].label.as_str(),
+            _ => "",
+        };
+        out.push_str(&format!("{:<width$} | {}", label, line, width = width));
+    }
+
+    out
+}
+
+fn render_hunks(
+    contents: &str,
+    hunks: &[BlameHunk],
+    shown: &[bool],
+    since: Option<&str>,
+) -> String {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let per_line = hunk_per_line(hunks, lines.len());
+
+    let mut out = String::with_capacity(contents.len());
+    let mut previous: Option<Option<usize>> = None;
+
+    for (line, hunk) in lines.iter().zip(per_line) {
+        let hunk = hunk.filter(|&idx| shown[idx]);
+
+        if previous != Some(hunk) {
+            match (hunk, since) {
+                (Some(idx), _) => out.push_str(&format!("[blame {}]\n", hunks[idx].label)),
+                (None, Some(since)) => {
+                    out.push_str(&format!("[blame unchanged since {}]\n", since))
+                }
+                (None, None) => {}
+            }
+            previous = Some(hunk);
+        }
+
+        out.push_str(line);
+    }
+
+    out
+}
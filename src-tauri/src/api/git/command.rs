@@ -1,34 +1,169 @@
 use crate::api::git::{
-    status::{compute_git_status, GitStatusComputation, GitStatusResults},
+    conflict::resolve_conflict,
+    event::emit_git_status_event,
+    repository::{repository_layout, GitRepositoryLayout},
+    settings::{
+        git_diff_options, git_status_limit, git_watch_debounce_ms, save_git_diff_options,
+        save_git_watch_debounce_ms, set_active_git_status_limit, set_git_status_limit_in_config,
+        GitDiffOptions, DEFAULT_GIT_STATUS_LIMIT,
+    },
+    stash::{list_stashes, GitStash},
+    status::{
+        compute_git_status, set_selected_diff, DiffScope, GitStatusComputation, GitStatusResults,
+        SelectedDiff,
+    },
     tokenize::spawn_git_token_count_task,
-    watch::ensure_git_watcher_started,
+    watch::{ensure_git_watcher_started, stop_git_watcher},
 };
+use crate::errors::{codes, ApplicationError};
+use crate::store::{open_store, save_store, StoreCategoryKey};
+use serde_json::{Map, Value};
 use tauri::{AppHandle, Wry};
 
 #[tauri::command]
 pub(crate) fn get_git_status(
     app: AppHandle<Wry>,
     directory_path: String,
-) -> Option<GitStatusResults> {
-    match compute_git_status(&app, &directory_path) {
+    base_ref: Option<String>,
+    scope: Option<DiffScope>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<Option<GitStatusResults>, ApplicationError> {
+    let selection = SelectedDiff {
+        base_ref,
+        scope: scope.unwrap_or_default(),
+        offset: offset.unwrap_or_default(),
+        limit,
+    };
+
+    let result = match compute_git_status(&app, &directory_path, &selection) {
         GitStatusComputation::NotRepository => None,
+        GitStatusComputation::InvalidBaseRef(message) => {
+            return Err(ApplicationError {
+                code: codes::GIT_REF_ERROR,
+                message: Some(message),
+            })
+        }
         GitStatusComputation::Finished {
-            changes,
+            results,
             work_items,
-            truncated,
         } => {
-            if !work_items.is_empty() {
-                spawn_git_token_count_task(app.clone(), directory_path.clone(), work_items);
-            }
-            Some(GitStatusResults {
-                results: changes,
-                truncated,
-            })
+            spawn_git_token_count_task(
+                app.clone(),
+                directory_path.clone(),
+                work_items,
+                results.totals.token_count,
+            );
+            Some(results)
         }
-    }
+    };
+
+    set_selected_diff(&directory_path, selection);
+
+    Ok(result)
+}
+
+#[tauri::command]
+pub(crate) fn get_git_status_limit() -> usize {
+    git_status_limit()
+}
+
+/// Sets how many changed files a git status page holds. `None` restores the
+/// default.
+#[tauri::command]
+pub(crate) fn set_git_status_limit(
+    app: AppHandle<Wry>,
+    limit: Option<usize>,
+) -> Result<(), ApplicationError> {
+    let limit = limit.filter(|&l| l > 0).unwrap_or(DEFAULT_GIT_STATUS_LIMIT);
+    let store = open_store(&app)?;
+
+    let mut config = store
+        .get(StoreCategoryKey::CONFIG)
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_else(Map::new);
+
+    set_git_status_limit_in_config(&mut config, limit);
+
+    store.set(StoreCategoryKey::CONFIG, Value::Object(config));
+    save_store(&store)?;
+    store.close_resource();
+
+    set_active_git_status_limit(limit);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn get_git_diff_options(app: AppHandle<Wry>, directory_path: String) -> GitDiffOptions {
+    git_diff_options(&app, &directory_path)
+}
+
+/// Saves the diff options of a project and refreshes its git status with
+/// them.
+#[tauri::command]
+pub(crate) fn set_git_diff_options(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    options: GitDiffOptions,
+) -> Result<(), ApplicationError> {
+    save_git_diff_options(&app, &directory_path, options)?;
+    emit_git_status_event(app, directory_path);
+
+    Ok(())
+}
+
+/// Writes the resolved `contents` of a conflicted file, when given, and marks
+/// it resolved in the index.
+#[tauri::command]
+pub(crate) fn resolve_git_conflict(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    path: String,
+    contents: Option<String>,
+) -> Result<(), ApplicationError> {
+    resolve_conflict(&directory_path, &path, contents.as_deref())?;
+    emit_git_status_event(app, directory_path);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn list_git_stashes(directory_path: String) -> Option<Vec<GitStash>> {
+    list_stashes(&directory_path)
+}
+
+/// Whether the directory is a linked worktree, and the worktrees and
+/// submodules of its repository. `None` outside a repository.
+#[tauri::command]
+pub(crate) fn get_git_repository_layout(directory_path: String) -> Option<GitRepositoryLayout> {
+    repository_layout(&directory_path)
 }
 
 #[tauri::command]
 pub(crate) fn watch_directory_for_git_changes(app: AppHandle<Wry>, directory_path: String) {
     ensure_git_watcher_started(app, directory_path);
 }
+
+/// Stops watching `directory_path` for git changes, e.g. when the project is
+/// closed. Returns whether it was watched.
+#[tauri::command]
+pub(crate) fn unwatch_directory(directory_path: String) -> bool {
+    stop_git_watcher(&directory_path)
+}
+
+#[tauri::command]
+pub(crate) fn get_git_watch_debounce(app: AppHandle<Wry>, directory_path: String) -> u64 {
+    git_watch_debounce_ms(&app, &directory_path)
+}
+
+/// Sets how long the watcher of a project waits for file events to settle, in
+/// milliseconds. `None` restores the default. Returns the delay in effect.
+#[tauri::command]
+pub(crate) fn set_git_watch_debounce(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    debounce_ms: Option<u64>,
+) -> Result<u64, ApplicationError> {
+    save_git_watch_debounce_ms(&app, &directory_path, debounce_ms)
+}
@@ -0,0 +1,134 @@
+use crate::{
+    api::clipboard::assemble::render_file_block,
+    errors::{codes, ApplicationError},
+};
+use git2::{Index, IndexConflict, IndexEntry, Repository};
+use std::fs;
+use std::path::Path;
+
+const SHORT_ID_LEN: usize = 7;
+
+/// Refs git leaves pointing at the commit being merged in, by operation.
+const THEIR_HEADS: &[&str] = &[
+    "MERGE_HEAD",
+    "CHERRY_PICK_HEAD",
+    "REVERT_HEAD",
+    "REBASE_HEAD",
+];
+
+/// The base, ours and theirs versions of a conflicted `path` from the index
+/// stages, headed by the commits of both sides. `None` when `path` isn't
+/// conflicted.
+pub(crate) fn conflict_sections(repo: &Repository, path: &str) -> Option<Vec<u8>> {
+    let index = repo.index().ok()?;
+    let conflict = find_conflict(&index, path)?;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(format!("Conflict in {}\n", path).as_bytes());
+    out.extend_from_slice(format!("Ours: {}\n", side_label(repo, "HEAD")).as_bytes());
+
+    if let Some(head) = THEIR_HEADS
+        .iter()
+        .find(|head| repo.revparse_single(head).is_ok())
+    {
+        out.extend_from_slice(format!("Theirs: {}\n", side_label(repo, head)).as_bytes());
+    }
+
+    for (label, entry) in [
+        ("base", &conflict.ancestor),
+        ("ours", &conflict.our),
+        ("theirs", &conflict.their),
+    ] {
+        match entry.as_ref().and_then(|entry| stage_contents(repo, entry)) {
+            Some(contents) => render_stage(&mut out, path, label, &contents),
+            None => out.extend_from_slice(format!("No {} version.\n", label).as_bytes()),
+        }
+    }
+
+    Some(out)
+}
+
+fn find_conflict(index: &Index, path: &str) -> Option<IndexConflict> {
+    index.conflicts().ok()?.flatten().find(|conflict| {
+        [&conflict.ancestor, &conflict.our, &conflict.their]
+            .into_iter()
+            .flatten()
+            .any(|entry| entry.path == path.as_bytes())
+    })
+}
+
+/// `HEAD a1b2c3d Fix the parser`
+fn side_label(repo: &Repository, head: &str) -> String {
+    match repo
+        .revparse_single(head)
+        .and_then(|object| object.peel_to_commit())
+    {
+        Ok(commit) => {
+            let short: String = commit.id().to_string().chars().take(SHORT_ID_LEN).collect();
+            format!(
+                "{} {} {}",
+                head,
+                short,
+                commit.summary().unwrap_or_default()
+            )
+        }
+        Err(_) => head.to_string(),
+    }
+}
+
+fn stage_contents(repo: &Repository, entry: &IndexEntry) -> Option<Vec<u8>> {
+    repo.find_blob(entry.id)
+        .ok()
+        .map(|blob| blob.content().to_vec())
+}
+
+fn render_stage(out: &mut Vec<u8>, path: &str, label: &str, contents: &[u8]) {
+    let block = render_file_block(path, Some(label), &String::from_utf8_lossy(contents));
+    out.extend_from_slice(block.as_bytes());
+    out.push(b'\n');
+}
+
+/// Marks a conflicted `path` resolved, first writing `contents` to it when
+/// given. Without contents the file is staged as it is on disk, or removed
+/// from the index when it was deleted.
+pub(crate) fn resolve_conflict(
+    root: &str,
+    path: &str,
+    contents: Option<&str>,
+) -> Result<(), ApplicationError> {
+    let index_error = |message: String| ApplicationError {
+        code: codes::GIT_INDEX_ERROR,
+        message: Some(message),
+    };
+
+    let repo = Repository::open(root).map_err(|err| index_error(err.message().to_string()))?;
+    let mut index = repo
+        .index()
+        .map_err(|err| index_error(err.message().to_string()))?;
+
+    if find_conflict(&index, path).is_none() {
+        return Err(index_error(format!("{} isn't conflicted", path)));
+    }
+
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| index_error("The repository has no working tree".to_string()))?;
+
+    if let Some(contents) = contents {
+        let file = workdir.join(path);
+        fs::write(&file, contents).map_err(|err| ApplicationError {
+            code: codes::FILE_WRITE_ERROR,
+            message: Some(format!("Failed to write {}: {}", file.display(), err)),
+        })?;
+    }
+
+    let staged = if workdir.join(path).exists() {
+        index.add_path(Path::new(path))
+    } else {
+        index.remove_path(Path::new(path))
+    };
+
+    staged
+        .and_then(|_| index.write())
+        .map_err(|err| index_error(format!("{}: {}", path, err.message())))
+}
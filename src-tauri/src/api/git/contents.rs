@@ -0,0 +1,91 @@
+use crate::api::{clipboard::assemble::render_file_block, git::settings::DiffFileContents};
+use git2::{Delta, DiffDelta, DiffFile, Repository};
+use std::fs;
+
+/// Files up to this many lines on both sides are shown before and after the
+/// change, longer ones only after it.
+const MAX_BEFORE_AND_AFTER_LINES: usize = 300;
+
+/// Appends the contents of the file changed by `delta` to its diff. Added and
+/// deleted files are left out since their diff already holds the whole file.
+/// `new_in_workdir` tells whether the new side is read from the working tree
+/// rather than from a tree or the index.
+pub(crate) fn append_file_contents(
+    out: &mut Vec<u8>,
+    repo: &Repository,
+    delta: &DiffDelta,
+    new_in_workdir: bool,
+    mode: DiffFileContents,
+) {
+    if mode == DiffFileContents::None
+        || delta.flags().is_binary()
+        || matches!(
+            delta.status(),
+            Delta::Added | Delta::Untracked | Delta::Deleted
+        )
+    {
+        return;
+    }
+
+    let Some(path) = delta.new_file().path() else {
+        return;
+    };
+
+    let after = if new_in_workdir {
+        repo.workdir()
+            .and_then(|workdir| fs::read(workdir.join(path)).ok())
+    } else {
+        blob_contents(repo, &delta.new_file())
+    };
+
+    let Some(after) = after.filter(|bytes| !bytes.contains(&0)) else {
+        return;
+    };
+
+    let before = match mode {
+        DiffFileContents::BeforeAndAfter if line_count(&after) <= MAX_BEFORE_AND_AFTER_LINES => {
+            blob_contents(repo, &delta.old_file()).filter(|bytes| {
+                !bytes.contains(&0) && line_count(bytes) <= MAX_BEFORE_AND_AFTER_LINES
+            })
+        }
+        _ => None,
+    };
+
+    let label = path.to_string_lossy();
+
+    match before {
+        Some(before) => {
+            let old_label = delta
+                .old_file()
+                .path()
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_else(|| label.to_string());
+            render_contents(out, &old_label, Some("before"), &before);
+            render_contents(out, &label, Some("after"), &after);
+        }
+        None => render_contents(out, &label, None, &after),
+    }
+}
+
+fn blob_contents(repo: &Repository, file: &DiffFile) -> Option<Vec<u8>> {
+    let oid = file.id();
+    if oid.is_zero() {
+        return None;
+    }
+
+    repo.find_blob(oid).ok().map(|blob| blob.content().to_vec())
+}
+
+fn line_count(bytes: &[u8]) -> usize {
+    bytes.split(|&b| b == b'\n').count()
+}
+
+/// Renders the contents the way file sections of the prompt are rendered.
+fn render_contents(out: &mut Vec<u8>, path: &str, label: Option<&str>, contents: &[u8]) {
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    let block = render_file_block(path, label, &String::from_utf8_lossy(contents));
+    out.extend_from_slice(block.as_bytes());
+    out.push(b'\n');
+}
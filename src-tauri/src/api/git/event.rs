@@ -1,5 +1,8 @@
 use crate::api::git::{
-    status::{compute_git_status, GitChange, GitStatusComputation},
+    status::{
+        compute_git_status, selected_diff, DiffScope, GitChange, GitStatusComputation,
+        GitStatusTotals,
+    },
     tokenize::spawn_git_token_count_task,
 };
 use serde::Serialize;
@@ -11,6 +14,8 @@ use tauri::{AppHandle, Emitter, Wry};
 pub(crate) struct GitTokenCountsEvent {
     pub(crate) root: String,
     pub(crate) files: HashMap<String, usize>,
+    /// Tokens of every diff in the change set, sent with the last batch.
+    pub(crate) total_token_count: Option<usize>,
 }
 
 #[derive(Clone, Serialize)]
@@ -19,26 +24,40 @@ pub(crate) struct GitStatusEvent {
     pub(crate) root: String,
     pub(crate) results: Vec<GitChange>,
     pub(crate) truncated: bool,
+    pub(crate) offset: usize,
+    pub(crate) limit: usize,
+    pub(crate) totals: GitStatusTotals,
+    pub(crate) base_ref: Option<String>,
+    pub(crate) scope: DiffScope,
 }
 
 pub(crate) fn emit_git_status_event(app: AppHandle<Wry>, directory_path: String) {
-    match compute_git_status(&app, &directory_path) {
-        GitStatusComputation::NotRepository => {}
+    let selection = selected_diff(&directory_path);
+
+    match compute_git_status(&app, &directory_path, &selection) {
+        GitStatusComputation::NotRepository | GitStatusComputation::InvalidBaseRef(_) => {}
         GitStatusComputation::Finished {
-            changes,
+            results,
             work_items,
-            truncated,
         } => {
-            if !work_items.is_empty() {
-                spawn_git_token_count_task(app.clone(), directory_path.clone(), work_items);
-            }
+            spawn_git_token_count_task(
+                app.clone(),
+                directory_path.clone(),
+                work_items,
+                results.totals.token_count,
+            );
 
             let _ = app.emit(
                 "git-status-updated",
                 GitStatusEvent {
                     root: directory_path,
-                    results: changes,
-                    truncated,
+                    results: results.results,
+                    truncated: results.truncated,
+                    offset: results.offset,
+                    limit: results.limit,
+                    totals: results.totals,
+                    base_ref: selection.base_ref,
+                    scope: selection.scope,
                 },
             );
         }
@@ -0,0 +1,361 @@
+use crate::api::profile::lib::{has_leading_prefix, item_keyword};
+use git2::Patch;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Keywords that declare a value rather than a function, so a closure
+/// assigned to one isn't taken for the enclosing function.
+const NON_FUNCTION_KEYWORDS: &[&str] = &["let", "var"];
+
+/// Words followed by parentheses that don't declare a method.
+const CONTROL_KEYWORDS: &[&str] = &[
+    "if",
+    "else",
+    "for",
+    "foreach",
+    "while",
+    "switch",
+    "catch",
+    "return",
+    "do",
+    "try",
+    "using",
+    "lock",
+    "with",
+    "await",
+    "new",
+    "throw",
+    "yield",
+    "synchronized",
+];
+
+/// Lines that close the block opened by the function signature.
+const CLOSING_PREFIXES: &[&str] = &["}", ")", "]", "end"];
+
+struct PatchLine {
+    origin: char,
+    old_lineno: Option<u32>,
+    new_lineno: Option<u32>,
+    content: Vec<u8>,
+}
+
+/// Renders a patch generated with the whole file as context, keeping
+/// `context_lines` around each change and the whole function enclosing it.
+pub fn function_context_patch(patch: &mut Patch, context_lines: u32) -> Vec<u8> {
+    let full = patch.to_buf().map(|buf| buf.to_vec()).unwrap_or_default();
+
+    let header_end = full
+        .windows(3)
+        .position(|w| w == b"\n@@")
+        .map(|pos| pos + 1)
+        .or_else(|| full.starts_with(b"@@").then_some(0));
+
+    let Some(header_end) = header_end else {
+        return full;
+    };
+
+    let lines = patch_lines(patch);
+    let keep = kept_lines(&lines, context_lines as usize);
+
+    let mut out = full[..header_end].to_vec();
+    let mut index = 0;
+
+    while index < lines.len() {
+        if !keep[index] {
+            index += 1;
+            continue;
+        }
+
+        let start = index;
+        while index < lines.len() && keep[index] {
+            index += 1;
+        }
+
+        render_hunk(&mut out, &lines, start..index);
+    }
+
+    out
+}
+
+fn patch_lines(patch: &Patch) -> Vec<PatchLine> {
+    let mut lines = Vec::new();
+
+    for hunk in 0..patch.num_hunks() {
+        let Ok(count) = patch.num_lines_in_hunk(hunk) else {
+            continue;
+        };
+
+        for idx in 0..count {
+            if let Ok(line) = patch.line_in_hunk(hunk, idx) {
+                lines.push(PatchLine {
+                    origin: line.origin(),
+                    old_lineno: line.old_lineno(),
+                    new_lineno: line.new_lineno(),
+                    content: line.content().to_vec(),
+                });
+            }
+        }
+    }
+
+    lines
+}
+
+fn is_change(line: &PatchLine) -> bool {
+    matches!(line.origin, '+' | '-')
+}
+
+/// Whether a line is the "no newline at end of file" marker of the line
+/// before it.
+fn is_eof_marker(line: &PatchLine) -> bool {
+    matches!(line.origin, '=' | '>' | '<')
+}
+
+fn kept_lines(lines: &[PatchLine], context_lines: usize) -> Vec<bool> {
+    let old_side = side_text(lines, |line| line.old_lineno);
+    let new_side = side_text(lines, |line| line.new_lineno);
+    let old_spans = function_spans(&old_side);
+    let new_spans = function_spans(&new_side);
+
+    let mut old_ranges: Vec<RangeInclusive<u32>> = Vec::new();
+    let mut new_ranges: Vec<RangeInclusive<u32>> = Vec::new();
+    let mut keep = vec![false; lines.len()];
+
+    for (idx, line) in lines.iter().enumerate() {
+        if !is_change(line) {
+            continue;
+        }
+
+        let from = idx.saturating_sub(context_lines);
+        let to = (idx + context_lines).min(lines.len() - 1);
+        keep[from..=to].iter_mut().for_each(|k| *k = true);
+
+        if let Some(range) = line
+            .new_lineno
+            .and_then(|n| enclosing_function(&new_side, &new_spans, n))
+        {
+            new_ranges.push(range);
+        }
+        if let Some(range) = line
+            .old_lineno
+            .and_then(|n| enclosing_function(&old_side, &old_spans, n))
+        {
+            old_ranges.push(range);
+        }
+    }
+
+    for (idx, line) in lines.iter().enumerate() {
+        let in_new = line
+            .new_lineno
+            .is_some_and(|n| new_ranges.iter().any(|r| r.contains(&n)));
+        let in_old = line
+            .old_lineno
+            .is_some_and(|n| old_ranges.iter().any(|r| r.contains(&n)));
+
+        if in_new || in_old {
+            keep[idx] = true;
+        }
+    }
+
+    for idx in 1..lines.len() {
+        if is_eof_marker(&lines[idx]) {
+            keep[idx] = keep[idx - 1];
+        }
+    }
+
+    keep
+}
+
+/// One side of the file, as 1-based line number to text.
+fn side_text(
+    lines: &[PatchLine],
+    lineno: impl Fn(&PatchLine) -> Option<u32>,
+) -> HashMap<u32, String> {
+    lines
+        .iter()
+        .filter(|line| !is_eof_marker(line))
+        .filter_map(|line| Some((lineno(line)?, String::from_utf8_lossy(&line.content).into())))
+        .collect()
+}
+
+fn indentation(text: &str) -> usize {
+    text.len() - text.trim_start().len()
+}
+
+/// Whether `text` looks like a method declared without a keyword: a name
+/// followed by parameters, as in Java and C# (`public void save(User user)`)
+/// or JavaScript and TypeScript classes (`save(user) {`). A single word before
+/// the parameters must be followed by a brace, or calls would match too.
+fn is_method_signature(text: &str) -> bool {
+    let text = text.trim();
+    let Some((head, _)) = text.split_once('(') else {
+        return false;
+    };
+
+    let words: Vec<&str> = head.split_whitespace().collect();
+    let Some(name) = words.last() else {
+        return false;
+    };
+
+    let is_name = name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    let is_declaration = !head.contains(['=', '.', '"', '\'', '!', '&', '|'])
+        && !words.iter().any(|word| CONTROL_KEYWORDS.contains(word));
+
+    is_name
+        && is_declaration
+        && !text.ends_with(';')
+        && (text.ends_with('{') || (words.len() > 1 && text.ends_with(')')))
+}
+
+fn is_function_start(text: &str) -> bool {
+    match item_keyword(text.trim_start()) {
+        Some(keyword) => !NON_FUNCTION_KEYWORDS.contains(&keyword),
+        None => is_method_signature(text),
+    }
+}
+
+/// A function on one side of the file, from its signature down to the line
+/// closing it. `first` includes the comments right above the signature.
+struct FunctionSpan {
+    start: u32,
+    first: u32,
+    end: u32,
+    indent: usize,
+}
+
+/// Finds every function of one side in a single pass: a function ends at the
+/// first line after it that is not more indented than its signature,
+/// including that line when it closes the block.
+fn function_spans(side: &HashMap<u32, String>) -> Vec<FunctionSpan> {
+    let mut numbers: Vec<u32> = side.keys().copied().collect();
+    numbers.sort_unstable();
+
+    let mut spans = Vec::new();
+    let mut open: Vec<(u32, usize)> = Vec::new();
+
+    for &n in &numbers {
+        let line = &side[&n];
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let indent = indentation(line);
+        let closes = CLOSING_PREFIXES
+            .iter()
+            .any(|p| line.trim_start().starts_with(p));
+
+        while let Some(&(start, start_indent)) = open.last() {
+            if indent > start_indent {
+                break;
+            }
+            open.pop();
+            spans.push(function_span(
+                side,
+                start,
+                start_indent,
+                if closes { n } else { n - 1 },
+            ));
+        }
+
+        if is_function_start(line) {
+            open.push((n, indent));
+        }
+    }
+
+    let last = numbers.last().copied().unwrap_or_default();
+    for (start, start_indent) in open {
+        spans.push(function_span(side, start, start_indent, last));
+    }
+
+    spans.sort_by_key(|span| span.start);
+    spans
+}
+
+fn function_span(side: &HashMap<u32, String>, start: u32, indent: usize, end: u32) -> FunctionSpan {
+    let mut end = end;
+    while end > start && side.get(&end).is_some_and(|line| line.trim().is_empty()) {
+        end -= 1;
+    }
+
+    let mut first = start;
+    while first > 1
+        && side.get(&(first - 1)).is_some_and(|line| {
+            indentation(line) == indent && has_leading_prefix(line.trim_start())
+        })
+    {
+        first -= 1;
+    }
+
+    FunctionSpan {
+        start,
+        first,
+        end,
+        indent,
+    }
+}
+
+/// The lines of the function around `lineno`: the closest declaration above
+/// it that is less indented, or whose block `lineno` closes, with the comments
+/// right above it, down to the line closing it.
+fn enclosing_function(
+    side: &HashMap<u32, String>,
+    spans: &[FunctionSpan],
+    lineno: u32,
+) -> Option<RangeInclusive<u32>> {
+    let text = side.get(&lineno)?;
+    let target_indent = if text.trim().is_empty() {
+        usize::MAX
+    } else {
+        indentation(text)
+    };
+
+    let above = spans.partition_point(|span| span.start <= lineno);
+    let span = spans[..above]
+        .iter()
+        .rev()
+        .find(|span| span.start == lineno || span.end == lineno || span.indent < target_indent)?;
+
+    (span.end >= lineno).then_some(span.first..=span.end)
+}
+
+fn render_hunk(out: &mut Vec<u8>, lines: &[PatchLine], range: std::ops::Range<usize>) {
+    let hunk = &lines[range.clone()];
+
+    let old_count = hunk
+        .iter()
+        .filter(|l| l.old_lineno.is_some() && !is_eof_marker(l))
+        .count();
+    let new_count = hunk
+        .iter()
+        .filter(|l| l.new_lineno.is_some() && !is_eof_marker(l))
+        .count();
+
+    // An empty side starts at the line before the hunk, as in git's output.
+    let old_start = hunk
+        .iter()
+        .find_map(|l| l.old_lineno)
+        .or_else(|| lines[..range.start].iter().rev().find_map(|l| l.old_lineno))
+        .unwrap_or(0);
+    let new_start = hunk
+        .iter()
+        .find_map(|l| l.new_lineno)
+        .or_else(|| lines[..range.start].iter().rev().find_map(|l| l.new_lineno))
+        .unwrap_or(0);
+
+    out.extend_from_slice(
+        format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_count, new_start, new_count
+        )
+        .as_bytes(),
+    );
+
+    for line in hunk {
+        if !is_eof_marker(line) {
+            out.push(line.origin as u8);
+        }
+        out.extend_from_slice(&line.content);
+    }
+}
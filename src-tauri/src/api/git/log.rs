@@ -0,0 +1,245 @@
+use git2::{Commit, Diff, DiffFormat, DiffOptions, DiffStatsFormat, ErrorCode, Repository, Sort};
+use serde::Deserialize;
+
+const DEFAULT_LOG_COUNT: usize = 20;
+const STAT_WIDTH: usize = 80;
+
+/// How much of each commit the log shows besides its message.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum GitLogDetail {
+    #[default]
+    Message,
+    /// The message followed by a diffstat.
+    Stat,
+    /// The message followed by the full patch.
+    Patch,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct GitLogOptions {
+    /// Commits to show, 20 when unset.
+    pub(crate) max_count: Option<usize>,
+    /// A ref (`main`) or range (`main..feature`, `main...HEAD`). HEAD when
+    /// unset.
+    pub(crate) range: Option<String>,
+    /// Only commits touching these paths are shown, and patches and stats are
+    /// limited to them.
+    pub(crate) paths: Vec<String>,
+    pub(crate) detail: GitLogDetail,
+}
+
+/// Renders the commit history of `root` in the format of `git log`. `None`
+/// when `root` isn't a repository; an error when the range doesn't resolve.
+pub(crate) fn git_log_text(root: &str, options: &GitLogOptions) -> Result<Option<String>, String> {
+    let repo = match Repository::open(root) {
+        Ok(r) => r,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.message().to_string()),
+    };
+
+    let max_count = options.max_count.unwrap_or(DEFAULT_LOG_COUNT);
+    let mut out = String::new();
+
+    let mut revwalk = repo.revwalk().map_err(|e| e.message().to_string())?;
+    let _ = revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME);
+
+    let range = options
+        .range
+        .as_deref()
+        .map(str::trim)
+        .filter(|r| !r.is_empty());
+
+    let pushed = match range {
+        None => match revwalk.push_head() {
+            // No commits yet.
+            Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => {
+                return Ok(Some(out))
+            }
+            pushed => pushed,
+        },
+        Some(range) if range.contains("..") => {
+            let revspec = repo
+                .revparse(range)
+                .map_err(|e| format!("{} isn't a valid range: {}", range, e.message()))?;
+            let (Some(from), Some(to)) = (revspec.from(), revspec.to()) else {
+                return Err(format!("{} isn't a valid range", range));
+            };
+
+            let hide = if revspec.mode().contains(git2::RevparseMode::MERGE_BASE) {
+                repo.merge_base(from.id(), to.id())
+            } else {
+                Ok(from.id())
+            };
+
+            hide.and_then(|hide| revwalk.hide(hide))
+                .and_then(|_| revwalk.push(to.id()))
+        }
+        Some(spec) => repo
+            .revparse_single(spec)
+            .and_then(|object| object.peel_to_commit())
+            .and_then(|commit| revwalk.push(commit.id())),
+    };
+
+    if let Err(e) = pushed {
+        return Err(match range {
+            Some(range) => format!("{} isn't a valid ref: {}", range, e.message()),
+            None => e.message().to_string(),
+        });
+    }
+
+    let mut shown = 0;
+
+    for oid in revwalk.flatten() {
+        if shown >= max_count {
+            break;
+        }
+
+        let Ok(commit) = repo.find_commit(oid) else {
+            continue;
+        };
+
+        let diff = match (options.paths.is_empty(), options.detail) {
+            (true, GitLogDetail::Message) => None,
+            _ => match commit_diff(&repo, &commit, &options.paths) {
+                Some(diff) => Some(diff),
+                None => continue,
+            },
+        };
+
+        if !options.paths.is_empty() && diff.as_ref().is_some_and(|d| d.deltas().len() == 0) {
+            continue;
+        }
+
+        if shown > 0 {
+            out.push('\n');
+        }
+        render_commit(&mut out, &commit);
+
+        if let Some(diff) = diff {
+            match options.detail {
+                GitLogDetail::Message => {}
+                GitLogDetail::Stat => render_stat(&mut out, &diff),
+                GitLogDetail::Patch => render_patch(&mut out, &diff),
+            }
+        }
+
+        shown += 1;
+    }
+
+    Ok(Some(out))
+}
+
+/// The commit against its first parent, restricted to `paths`.
+fn commit_diff<'r>(repo: &'r Repository, commit: &Commit, paths: &[String]) -> Option<Diff<'r>> {
+    let tree = commit.tree().ok()?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree().ok()?),
+        Err(_) => None,
+    };
+
+    let mut opts = DiffOptions::new();
+    for path in paths {
+        opts.pathspec(path);
+    }
+
+    repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))
+        .ok()
+}
+
+fn render_commit(out: &mut String, commit: &Commit) {
+    let author = commit.author();
+    let when = author.when();
+
+    out.push_str(&format!("commit {}\n", commit.id()));
+    out.push_str(&format!(
+        "Author: {} <{}>\n",
+        author.name().unwrap_or_default(),
+        author.email().unwrap_or_default()
+    ));
+    out.push_str(&format!(
+        "Date:   {}\n\n",
+        format_git_time(when.seconds(), when.offset_minutes())
+    ));
+
+    let message = String::from_utf8_lossy(commit.message_bytes());
+    for line in message.trim_end().lines() {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str("    ");
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+}
+
+fn render_stat(out: &mut String, diff: &Diff) {
+    let Ok(buf) = diff
+        .stats()
+        .and_then(|stats| stats.to_buf(DiffStatsFormat::FULL, STAT_WIDTH))
+    else {
+        return;
+    };
+
+    out.push('\n');
+    out.push_str(&String::from_utf8_lossy(&buf));
+}
+
+fn render_patch(out: &mut String, diff: &Diff) {
+    out.push('\n');
+    let _ = diff.print(DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            out.push(line.origin());
+        }
+        out.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    });
+}
+
+/// `2024-05-01 14:03:22 +0200`, in the author's own time zone.
+fn format_git_time(seconds: i64, offset_minutes: i32) -> String {
+    let local = seconds + i64::from(offset_minutes) * 60;
+    let (year, month, day) = civil_date(local.div_euclid(86_400));
+    let secs = local.rem_euclid(86_400);
+
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    let offset = offset_minutes.abs();
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60,
+        sign,
+        offset / 60,
+        offset % 60
+    )
+}
+
+/// `2024-05-01`, in the author's own time zone.
+pub(crate) fn format_git_date(time: git2::Time) -> String {
+    let local = time.seconds() + i64::from(time.offset_minutes()) * 60;
+    let (year, month, day) = civil_date(local.div_euclid(86_400));
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Days since the epoch to a civil date, after Howard Hinnant's algorithm.
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
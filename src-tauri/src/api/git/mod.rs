@@ -1,5 +1,15 @@
+pub(crate) mod blame;
 pub(crate) mod command;
+pub(crate) mod conflict;
+pub(crate) mod contents;
 pub(crate) mod event;
+pub mod function_context;
+pub(crate) mod log;
+pub(crate) mod repository;
+pub(crate) mod settings;
+pub mod snapshot;
+pub(crate) mod stash;
 pub(crate) mod status;
 pub(crate) mod tokenize;
 pub(crate) mod watch;
+pub(crate) mod watch_filter;
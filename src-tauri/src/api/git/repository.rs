@@ -0,0 +1,290 @@
+use crate::api::git::log::format_git_date;
+use git2::{Branch, ErrorCode, Oid, Repository, RepositoryState};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+const SHORT_ID_LEN: usize = 7;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitSubmodule {
+    pub(crate) name: String,
+    pub(crate) path: String,
+    pub(crate) url: Option<String>,
+    /// Commit checked out in the submodule, `None` when it isn't initialized.
+    pub(crate) commit: Option<String>,
+}
+
+/// How the opened directory sits among worktrees and submodules.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitRepositoryLayout {
+    /// The directory is a worktree added with `git worktree add` rather than
+    /// the main checkout.
+    pub(crate) is_linked_worktree: bool,
+    /// Working tree of the main checkout, set for linked worktrees.
+    pub(crate) main_worktree: Option<String>,
+    /// Paths of the linked worktrees of the repository.
+    pub(crate) worktrees: Vec<String>,
+    pub(crate) submodules: Vec<GitSubmodule>,
+}
+
+/// `None` when `root` isn't a repository.
+pub(crate) fn repository_layout(root: &str) -> Option<GitRepositoryLayout> {
+    let repo = Repository::open(root).ok()?;
+    Some(layout_of(&repo))
+}
+
+fn layout_of(repo: &Repository) -> GitRepositoryLayout {
+    let is_linked_worktree = repo.is_worktree();
+
+    let main_worktree = is_linked_worktree.then(|| main_worktree(repo)).flatten();
+
+    let worktrees = repo
+        .worktrees()
+        .map(|names| {
+            names
+                .iter()
+                .flatten()
+                .filter_map(|name| repo.find_worktree(name).ok())
+                .map(|worktree| display_path(worktree.path()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let submodules = repo
+        .submodules()
+        .map(|submodules| {
+            submodules
+                .iter()
+                .map(|submodule| GitSubmodule {
+                    name: submodule.name().unwrap_or_default().to_string(),
+                    path: submodule.path().to_string_lossy().into_owned(),
+                    url: submodule.url().map(strip_credentials),
+                    commit: submodule.workdir_id().map(|oid| oid.to_string()),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    GitRepositoryLayout {
+        is_linked_worktree,
+        main_worktree,
+        worktrees,
+        submodules,
+    }
+}
+
+/// Working tree of the main checkout of a linked worktree, found through the
+/// `commondir` file git keeps in the worktree's git directory.
+fn main_worktree(repo: &Repository) -> Option<String> {
+    let common = fs::read_to_string(repo.path().join("commondir")).ok()?;
+    let main = Repository::open(repo.path().join(common.trim())).ok()?;
+    main.workdir().map(display_path)
+}
+
+fn display_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .trim_end_matches(['/', '\\'])
+        .to_string()
+}
+
+/// Summarizes the state of the repository at `root`: branch, upstream,
+/// operation in progress, last commit, remotes, worktrees and submodules.
+/// `None` when `root` isn't a repository.
+pub(crate) fn repository_text(root: &str) -> Option<String> {
+    let repo = match Repository::open(root) {
+        Ok(r) => r,
+        Err(e) if e.code() == ErrorCode::NotFound => return None,
+        Err(_) => return Some(String::new()),
+    };
+
+    let mut out = String::new();
+
+    render_head(&mut out, &repo);
+
+    if let Some(operation) = operation_in_progress(&repo) {
+        out.push_str(&format!("In progress: {}\n", operation));
+    }
+
+    if let Ok(commit) = repo.head().and_then(|head| head.peel_to_commit()) {
+        let author = commit.author();
+        out.push_str(&format!(
+            "Last commit: {} {} {}: {}\n",
+            short_id(commit.id()),
+            format_git_date(author.when()),
+            author.name().unwrap_or_default(),
+            commit.summary().unwrap_or_default()
+        ));
+    }
+
+    render_remotes(&mut out, &repo);
+    render_layout(&mut out, &layout_of(&repo));
+
+    Some(out)
+}
+
+fn short_id(oid: Oid) -> String {
+    oid.to_string().chars().take(SHORT_ID_LEN).collect()
+}
+
+/// The branch line and, for a branch with an upstream, the tracking line.
+fn render_head(out: &mut String, repo: &Repository) {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(e) if e.code() == ErrorCode::UnbornBranch => {
+            let name = repo
+                .find_reference("HEAD")
+                .ok()
+                .and_then(|head| head.symbolic_target().map(str::to_string))
+                .map(|target| target.trim_start_matches("refs/heads/").to_string())
+                .unwrap_or_default();
+            out.push_str(&format!("Branch: {} (no commits yet)\n", name));
+            return;
+        }
+        Err(_) => return,
+    };
+
+    if !head.is_branch() {
+        let at = head.target().map(short_id).unwrap_or_default();
+        out.push_str(&format!("HEAD: detached at {}\n", at));
+        return;
+    }
+
+    let branch = Branch::wrap(head);
+    let name = branch.name().ok().flatten().unwrap_or_default().to_string();
+    out.push_str(&format!("Branch: {}\n", name));
+
+    let Ok(upstream) = branch.upstream() else {
+        out.push_str("Upstream: none\n");
+        return;
+    };
+
+    let upstream_name = upstream.name().ok().flatten().unwrap_or_default();
+
+    let counts = branch
+        .get()
+        .target()
+        .zip(upstream.get().target())
+        .and_then(|(local, remote)| repo.graph_ahead_behind(local, remote).ok());
+
+    match counts {
+        Some((ahead, behind)) => out.push_str(&format!(
+            "Upstream: {} ({} ahead, {} behind)\n",
+            upstream_name, ahead, behind
+        )),
+        None => out.push_str(&format!("Upstream: {}\n", upstream_name)),
+    }
+}
+
+/// The operation git is in the middle of, with the branch being rebased when
+/// there is one.
+fn operation_in_progress(repo: &Repository) -> Option<String> {
+    let operation = match repo.state() {
+        RepositoryState::Clean => return None,
+        RepositoryState::Merge => "merge",
+        RepositoryState::Revert | RepositoryState::RevertSequence => "revert",
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => "cherry-pick",
+        RepositoryState::Bisect => "bisect",
+        RepositoryState::ApplyMailbox => "am",
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge
+        | RepositoryState::ApplyMailboxOrRebase => "rebase",
+    };
+
+    if operation != "rebase" {
+        return Some(operation.to_string());
+    }
+
+    let branch = ["rebase-merge/head-name", "rebase-apply/head-name"]
+        .iter()
+        .find_map(|file| fs::read_to_string(repo.path().join(file)).ok())
+        .map(|name| name.trim().trim_start_matches("refs/heads/").to_string())
+        .filter(|name| !name.is_empty() && name != "detached HEAD");
+
+    Some(match branch {
+        Some(branch) => format!("rebase of {}", branch),
+        None => operation.to_string(),
+    })
+}
+
+fn render_remotes(out: &mut String, repo: &Repository) {
+    let Ok(names) = repo.remotes() else {
+        return;
+    };
+
+    let mut lines = Vec::new();
+
+    for name in names.iter().flatten() {
+        let Ok(remote) = repo.find_remote(name) else {
+            continue;
+        };
+
+        let url = remote.url().map(strip_credentials).unwrap_or_default();
+        lines.push(format!("  {} {}", name, url));
+
+        if let Some(push_url) = remote.pushurl().map(strip_credentials) {
+            if push_url != url {
+                lines.push(format!("  {} {} (push)", name, push_url));
+            }
+        }
+    }
+
+    if lines.is_empty() {
+        return;
+    }
+
+    out.push_str("Remotes:\n");
+    for line in lines {
+        out.push_str(&line);
+        out.push('\n');
+    }
+}
+
+fn render_layout(out: &mut String, layout: &GitRepositoryLayout) {
+    if let Some(main) = &layout.main_worktree {
+        out.push_str(&format!("Worktree: linked, main checkout at {}\n", main));
+    }
+
+    if !layout.worktrees.is_empty() {
+        out.push_str("Linked worktrees:\n");
+        for path in &layout.worktrees {
+            out.push_str(&format!("  {}\n", path));
+        }
+    }
+
+    if !layout.submodules.is_empty() {
+        out.push_str("Submodules:\n");
+        for submodule in &layout.submodules {
+            let commit = submodule
+                .commit
+                .as_deref()
+                .map(|id| id.chars().take(SHORT_ID_LEN).collect::<String>())
+                .unwrap_or_else(|| "not initialized".to_string());
+            out.push_str(&format!(
+                "  {} {} ({})\n",
+                submodule.path,
+                submodule.url.as_deref().unwrap_or_default(),
+                commit
+            ));
+        }
+    }
+}
+
+/// Drops the user info of URLs with a scheme, where tokens and passwords
+/// live. scp-like `git@host:path` remotes have no secret and are kept.
+fn strip_credentials(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(authority_end);
+
+    match authority.rsplit_once('@') {
+        Some((_, host)) => format!("{}://{}{}", scheme, host, path),
+        None => url.to_string(),
+    }
+}
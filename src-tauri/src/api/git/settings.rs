@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{OnceLock, RwLock};
+use tauri::{AppHandle, Wry};
+
+use crate::{
+    errors::ApplicationError,
+    store::{
+        open_store, read_project_value, write_project_value, StoreCategoryKey, StoreConfigKey,
+        StoreDataKey,
+    },
+};
+
+/// How many changed files a git status page holds unless the caller asks for
+/// a different page size.
+pub(crate) const DEFAULT_GIT_STATUS_LIMIT: usize = 100;
+
+static GIT_STATUS_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_GIT_STATUS_LIMIT);
+
+pub(crate) fn git_status_limit() -> usize {
+    GIT_STATUS_LIMIT.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set_active_git_status_limit(limit: usize) {
+    GIT_STATUS_LIMIT.store(limit.max(1), Ordering::Relaxed);
+}
+
+// Pure helper: set the git status limit into a config map.
+pub fn set_git_status_limit_in_config(config: &mut Map<String, Value>, limit: usize) {
+    config.insert(StoreConfigKey::GIT_STATUS_LIMIT.to_string(), json!(limit));
+}
+
+// Pure helper: read the git status limit from a config map.
+pub fn get_git_status_limit_from_config(config: &Map<String, Value>) -> Option<usize> {
+    config
+        .get(StoreConfigKey::GIT_STATUS_LIMIT)
+        .and_then(|v| v.as_u64())
+        .map(|limit| limit as usize)
+        .filter(|&limit| limit > 0)
+}
+
+/// Reads the configured git status limit into memory. Called once when the
+/// app starts.
+pub fn load_git_status_limit<R: tauri::Runtime>(app: &AppHandle<R>) {
+    let Ok(store) = open_store(app) else {
+        return;
+    };
+
+    let limit = store
+        .get(StoreCategoryKey::CONFIG)
+        .and_then(|v| v.as_object().cloned())
+        .and_then(|config| get_git_status_limit_from_config(&config))
+        .unwrap_or(DEFAULT_GIT_STATUS_LIMIT);
+
+    store.close_resource();
+    set_active_git_status_limit(limit);
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum DiffAlgorithm {
+    #[default]
+    Myers,
+    Patience,
+    Minimal,
+}
+
+/// What follows the diff of each changed file.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum DiffFileContents {
+    /// The diff alone.
+    #[default]
+    None,
+    /// The file as it is after the change.
+    Current,
+    /// The file before and after the change, for small files. Larger ones
+    /// get only the current version.
+    BeforeAndAfter,
+}
+
+/// How diffs of a project are generated. Stored per project.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct GitDiffOptions {
+    pub(crate) context_lines: u32,
+    pub(crate) ignore_whitespace_change: bool,
+    pub(crate) ignore_blank_lines: bool,
+    /// Percentage of similarity at which a delete and an add become a rename
+    /// or copy.
+    pub(crate) similarity_threshold: u16,
+    pub(crate) algorithm: DiffAlgorithm,
+    /// Expands every hunk to the function around it, like `git diff -W`.
+    pub(crate) function_context: bool,
+    pub(crate) file_contents: DiffFileContents,
+}
+
+impl Default for GitDiffOptions {
+    fn default() -> Self {
+        Self {
+            context_lines: 3,
+            ignore_whitespace_change: false,
+            ignore_blank_lines: false,
+            similarity_threshold: 50,
+            algorithm: DiffAlgorithm::default(),
+            function_context: false,
+            file_contents: DiffFileContents::default(),
+        }
+    }
+}
+
+impl GitDiffOptions {
+    /// Identifies the options in cache keys.
+    pub(crate) fn key(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{:?}:{}:{:?}",
+            self.context_lines,
+            u8::from(self.ignore_whitespace_change),
+            u8::from(self.ignore_blank_lines),
+            self.similarity_threshold,
+            self.algorithm,
+            u8::from(self.function_context),
+            self.file_contents
+        )
+    }
+}
+
+static GIT_DIFF_OPTIONS: OnceLock<RwLock<HashMap<String, GitDiffOptions>>> = OnceLock::new();
+
+fn diff_options_by_root() -> &'static RwLock<HashMap<String, GitDiffOptions>> {
+    GIT_DIFF_OPTIONS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// The diff options of `root`, read from the store the first time they're
+/// needed.
+pub(crate) fn git_diff_options(app: &AppHandle<Wry>, root: &str) -> GitDiffOptions {
+    if let Some(options) = diff_options_by_root()
+        .read()
+        .ok()
+        .and_then(|options| options.get(root).cloned())
+    {
+        return options;
+    }
+
+    let options: GitDiffOptions =
+        read_project_value(app, root, StoreDataKey::GIT_DIFF_OPTIONS).unwrap_or_default();
+
+    if let Ok(mut by_root) = diff_options_by_root().write() {
+        by_root.insert(root.to_string(), options.clone());
+    }
+
+    options
+}
+
+pub(crate) fn save_git_diff_options(
+    app: &AppHandle<Wry>,
+    root: &str,
+    options: GitDiffOptions,
+) -> Result<(), ApplicationError> {
+    let value = (options != GitDiffOptions::default())
+        .then(|| serde_json::to_value(&options).unwrap_or(Value::Null));
+    write_project_value(app, root, StoreDataKey::GIT_DIFF_OPTIONS, value)?;
+
+    if let Ok(mut by_root) = diff_options_by_root().write() {
+        by_root.insert(root.to_string(), options);
+    }
+
+    Ok(())
+}
+
+/// How long the git watcher waits for file events to settle before
+/// refreshing git status, unless a project sets its own delay.
+pub(crate) const DEFAULT_GIT_WATCH_DEBOUNCE_MS: u64 = 2000;
+const MIN_GIT_WATCH_DEBOUNCE_MS: u64 = 100;
+const MAX_GIT_WATCH_DEBOUNCE_MS: u64 = 60_000;
+
+static GIT_WATCH_DEBOUNCE: OnceLock<RwLock<HashMap<String, u64>>> = OnceLock::new();
+
+fn watch_debounce_by_root() -> &'static RwLock<HashMap<String, u64>> {
+    GIT_WATCH_DEBOUNCE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// The watcher delay of `root` in milliseconds, read from the store the first
+/// time it's needed.
+pub(crate) fn git_watch_debounce_ms(app: &AppHandle<Wry>, root: &str) -> u64 {
+    if let Some(debounce_ms) = watch_debounce_by_root()
+        .read()
+        .ok()
+        .and_then(|by_root| by_root.get(root).copied())
+    {
+        return debounce_ms;
+    }
+
+    let debounce_ms = read_project_value(app, root, StoreDataKey::GIT_WATCH_DEBOUNCE_MS)
+        .map(|ms: u64| ms.clamp(MIN_GIT_WATCH_DEBOUNCE_MS, MAX_GIT_WATCH_DEBOUNCE_MS))
+        .unwrap_or(DEFAULT_GIT_WATCH_DEBOUNCE_MS);
+
+    if let Ok(mut by_root) = watch_debounce_by_root().write() {
+        by_root.insert(root.to_string(), debounce_ms);
+    }
+
+    debounce_ms
+}
+
+/// Saves the watcher delay of `root`, `None` restoring the default. Returns
+/// the delay in effect, clamped to the supported range.
+pub(crate) fn save_git_watch_debounce_ms(
+    app: &AppHandle<Wry>,
+    root: &str,
+    debounce_ms: Option<u64>,
+) -> Result<u64, ApplicationError> {
+    let debounce_ms =
+        debounce_ms.map(|ms| ms.clamp(MIN_GIT_WATCH_DEBOUNCE_MS, MAX_GIT_WATCH_DEBOUNCE_MS));
+
+    write_project_value(
+        app,
+        root,
+        StoreDataKey::GIT_WATCH_DEBOUNCE_MS,
+        debounce_ms.map(|ms| json!(ms)),
+    )?;
+
+    let debounce_ms = debounce_ms.unwrap_or(DEFAULT_GIT_WATCH_DEBOUNCE_MS);
+
+    if let Ok(mut by_root) = watch_debounce_by_root().write() {
+        by_root.insert(root.to_string(), debounce_ms);
+    }
+
+    Ok(debounce_ms)
+}
@@ -0,0 +1,231 @@
+use crate::api::tree::index::DirectoryNode;
+use git2::{ObjectType, Oid, Repository, Tree, TreeEntry};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+/// Prefix of snapshot roots. Project roots are absolute paths so none starts
+/// with it, and paths that do are never looked up on disk.
+const SNAPSHOT_PREFIX: &str = "git-ref:";
+
+/// The tree of a commit, browsed under a root that doesn't exist on disk.
+/// Node ids below that root are resolved to blobs of `tree`.
+struct Snapshot {
+    /// Opened when the snapshot is registered and shared by every read.
+    repo: Arc<Mutex<Repository>>,
+    tree: Oid,
+}
+
+static SNAPSHOTS: OnceLock<RwLock<HashMap<PathBuf, Snapshot>>> = OnceLock::new();
+
+fn snapshots() -> &'static RwLock<HashMap<PathBuf, Snapshot>> {
+    SNAPSHOTS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Percent-encodes the characters of a ref that would split the root into
+/// path components or hide where the ref starts, so that every ref gets a
+/// root of its own: `feature/x` becomes `feature%2Fx`, apart from `feature-x`.
+fn escape_ref(git_ref: &str) -> String {
+    let mut escaped = String::with_capacity(git_ref.len());
+
+    for c in git_ref.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            '/' => escaped.push_str("%2F"),
+            '\\' => escaped.push_str("%5C"),
+            '@' => escaped.push_str("%40"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Root under which the tree of `git_ref` in `root` is listed, e.g.
+/// `git-ref:/code/app@v1.2.0`. The ref is escaped so the root stays a single
+/// path component and the last `@` always precedes the ref.
+pub fn snapshot_root(root: &str, git_ref: &str) -> String {
+    format!(
+        "{}{}@{}",
+        SNAPSHOT_PREFIX,
+        root.trim_end_matches(['/', '\\']),
+        escape_ref(git_ref.trim())
+    )
+}
+
+/// The project a snapshot root was listed from, e.g. `/code/app` for
+/// `git-ref:/code/app@v1.2.0`. `None` for roots that aren't snapshots.
+pub fn snapshot_source(root: &str) -> Option<&str> {
+    root.strip_prefix(SNAPSHOT_PREFIX)?
+        .rsplit_once('@')
+        .map(|(source, _)| source)
+}
+
+fn is_snapshot_path(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with(SNAPSHOT_PREFIX))
+}
+
+fn not_registered(path: &Path) -> String {
+    format!("{} isn't in a listed snapshot", path.display())
+}
+
+/// Resolves `git_ref` in the repository at `root` and registers its tree
+/// under `snapshot_root`. Returns the commit id and whether the snapshot
+/// root now points at a different tree than before.
+pub(crate) fn register_snapshot(
+    root: &str,
+    git_ref: &str,
+    snapshot_root: &str,
+) -> Result<(Oid, bool), String> {
+    let repo = Repository::open(root).map_err(|e| e.message().to_string())?;
+    let (commit, tree) = repo
+        .revparse_single(git_ref.trim())
+        .and_then(|object| object.peel_to_commit())
+        .map(|commit| (commit.id(), commit.tree_id()))
+        .map_err(|e| format!("{}: {}", git_ref, e.message()))?;
+
+    let mut registry = snapshots()
+        .write()
+        .map_err(|_| "snapshot registry poisoned".to_string())?;
+
+    let changed = registry
+        .insert(
+            PathBuf::from(snapshot_root),
+            Snapshot {
+                repo: Arc::new(Mutex::new(repo)),
+                tree,
+            },
+        )
+        .is_none_or(|previous| previous.tree != tree);
+
+    Ok((commit, changed))
+}
+
+/// Forgets the snapshot registered at `snapshot_root`. Returns whether there
+/// was one.
+pub(crate) fn unregister_snapshot(snapshot_root: &str) -> bool {
+    snapshots()
+        .write()
+        .map(|mut registry| registry.remove(Path::new(snapshot_root)).is_some())
+        .unwrap_or(false)
+}
+
+fn lock_repo(repo: &Mutex<Repository>) -> std::sync::MutexGuard<'_, Repository> {
+    repo.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The snapshot registered at exactly `root` as directory nodes, shaped like
+/// the output of `list_directory`. `None` when `root` isn't a snapshot root,
+/// an error when it is one that isn't registered.
+pub(crate) fn snapshot_tree(root: &str) -> Option<Result<Vec<DirectoryNode>, String>> {
+    let path = Path::new(root);
+    if !is_snapshot_path(path) {
+        return None;
+    }
+
+    let registered = snapshots().read().ok().and_then(|registry| {
+        let snapshot = registry.get(path)?;
+        Some((snapshot.repo.clone(), snapshot.tree))
+    });
+    let Some((repo, tree)) = registered else {
+        return Some(Err(not_registered(path)));
+    };
+
+    let repo = lock_repo(&repo);
+    Some(
+        repo.find_tree(tree)
+            .map(|tree| tree_nodes(&repo, &tree, path))
+            .map_err(|e| e.message().to_string()),
+    )
+}
+
+/// Directories first, then files, each sorted by name. Submodules are left
+/// out since their contents aren't in the repository.
+fn tree_nodes(repo: &Repository, tree: &Tree, dir: &Path) -> Vec<DirectoryNode> {
+    let mut nodes: Vec<DirectoryNode> = tree
+        .iter()
+        .filter_map(|entry| {
+            let title = entry.name()?.to_string();
+            let id = dir.join(&title);
+
+            let (node_type, children) = match entry.kind()? {
+                ObjectType::Tree => {
+                    let subtree = repo.find_tree(entry.id()).ok()?;
+                    ("directory", tree_nodes(repo, &subtree, &id))
+                }
+                ObjectType::Blob => ("file", Vec::new()),
+                _ => return None,
+            };
+
+            Some(DirectoryNode {
+                id: id.to_string_lossy().into_owned(),
+                title,
+                node_type: node_type.to_string(),
+                child_ids: children.iter().map(|child| child.id.clone()).collect(),
+                children,
+                parent: None,
+            })
+        })
+        .collect();
+
+    nodes.sort_by(|a, b| {
+        (b.node_type == "directory")
+            .cmp(&(a.node_type == "directory"))
+            .then_with(|| a.title.cmp(&b.title))
+    });
+
+    nodes
+}
+
+/// Applies `read` to the entry of `path` in the snapshot containing it.
+/// `None` when `path` isn't under a snapshot root.
+fn with_snapshot_entry<T>(
+    path: &Path,
+    read: impl FnOnce(&Repository, &TreeEntry) -> Result<T, git2::Error>,
+) -> Option<io::Result<T>> {
+    if !is_snapshot_path(path) {
+        return None;
+    }
+
+    let registered = snapshots().read().ok().and_then(|registry| {
+        registry.iter().find_map(|(root, snapshot)| {
+            let relative = path.strip_prefix(root).ok()?.to_path_buf();
+            Some((snapshot.repo.clone(), snapshot.tree, relative))
+        })
+    });
+    let Some((repo, tree, relative)) = registered else {
+        return Some(Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            not_registered(path),
+        )));
+    };
+
+    let repo = lock_repo(&repo);
+    Some(
+        repo.find_tree(tree)
+            .and_then(|tree| {
+                let entry = tree.get_path(&relative)?;
+                read(&repo, &entry)
+            })
+            .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e.message().to_string())),
+    )
+}
+
+/// Whether `path` is a file on disk or in a snapshot.
+pub(crate) fn is_file(path: &Path) -> bool {
+    match with_snapshot_entry(path, |_, entry| Ok(entry.kind() == Some(ObjectType::Blob))) {
+        Some(is_blob) => is_blob.unwrap_or(false),
+        None => path.is_file(),
+    }
+}
+
+/// Reads `path` from the snapshot containing it, or from disk.
+pub(crate) fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    with_snapshot_entry(path, |repo, entry| {
+        Ok(repo.find_blob(entry.id())?.content().to_vec())
+    })
+    .unwrap_or_else(|| fs::read(path))
+}
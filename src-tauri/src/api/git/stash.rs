@@ -0,0 +1,114 @@
+use crate::api::git::log::format_git_date;
+use crate::api::git::settings::GitDiffOptions;
+use crate::api::git::status::{find_renames, patch_bytes, DiffScope};
+use git2::{Diff, ErrorCode, Oid, Patch, Repository};
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitStash {
+    pub(crate) index: usize,
+    /// `stash@{0}`
+    pub(crate) name: String,
+    pub(crate) message: String,
+    pub(crate) commit: String,
+    pub(crate) date: String,
+}
+
+/// The stashes of `root`, newest first. `None` when `root` isn't a
+/// repository.
+pub(crate) fn list_stashes(root: &str) -> Option<Vec<GitStash>> {
+    let mut repo = match Repository::open(root) {
+        Ok(r) => r,
+        Err(e) if e.code() == ErrorCode::NotFound => return None,
+        Err(_) => return Some(Vec::new()),
+    };
+
+    let entries = stash_entries(&mut repo);
+
+    Some(
+        entries
+            .into_iter()
+            .map(|(index, message, oid)| GitStash {
+                index,
+                name: stash_name(index),
+                message,
+                commit: oid.to_string(),
+                date: repo
+                    .find_commit(oid)
+                    .map(|commit| format_git_date(commit.time()))
+                    .unwrap_or_default(),
+            })
+            .collect(),
+    )
+}
+
+fn stash_name(index: usize) -> String {
+    format!("stash@{{{}}}", index)
+}
+
+fn stash_entries(repo: &mut Repository) -> Vec<(usize, String, Oid)> {
+    let mut entries = Vec::new();
+    let _ = repo.stash_foreach(|index, message, oid| {
+        entries.push((index, message.to_string(), *oid));
+        true
+    });
+    entries
+}
+
+/// The changes saved in stash `index`, tracked and untracked, under a
+/// `stash@{n}: message` header. `Ok(None)` when `root` isn't a repository,
+/// `Err` when the stash doesn't exist.
+pub(crate) fn stash_diff_text(
+    root: &str,
+    index: usize,
+    options: &GitDiffOptions,
+) -> Result<Option<String>, String> {
+    let mut repo = match Repository::open(root) {
+        Ok(r) => r,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.message().to_string()),
+    };
+
+    let Some((_, message, oid)) = stash_entries(&mut repo)
+        .into_iter()
+        .find(|(i, _, _)| *i == index)
+    else {
+        return Err(format!("{} doesn't exist", stash_name(index)));
+    };
+
+    let stash = repo.find_commit(oid).map_err(|e| e.message().to_string())?;
+    let base_tree = stash
+        .parent(0)
+        .and_then(|base| base.tree())
+        .map_err(|e| e.message().to_string())?;
+    let stash_tree = stash.tree().map_err(|e| e.message().to_string())?;
+
+    let mut opts = DiffScope::All.diff_options(options);
+    let mut tracked = repo
+        .diff_tree_to_tree(Some(&base_tree), Some(&stash_tree), Some(&mut opts))
+        .map_err(|e| e.message().to_string())?;
+    find_renames(&mut tracked, options);
+
+    let mut out = format!("{}: {}\n", stash_name(index), message);
+    append_patches(&mut out, &tracked, options);
+
+    // `git stash -u` keeps untracked files in a third parent.
+    if let Ok(untracked_tree) = stash.parent(2).and_then(|parent| parent.tree()) {
+        let mut opts = DiffScope::All.diff_options(options);
+        if let Ok(untracked) = repo.diff_tree_to_tree(None, Some(&untracked_tree), Some(&mut opts))
+        {
+            append_patches(&mut out, &untracked, options);
+        }
+    }
+
+    Ok(Some(out))
+}
+
+fn append_patches(out: &mut String, diff: &Diff, options: &GitDiffOptions) {
+    for i in 0..diff.deltas().len() {
+        if let Ok(Some(mut patch)) = Patch::from_diff(diff, i) {
+            out.push_str(&String::from_utf8_lossy(&patch_bytes(&mut patch, options)));
+        }
+    }
+}
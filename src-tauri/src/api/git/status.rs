@@ -1,18 +1,30 @@
-use crate::api::git::tokenize::{ensure_git_cache_loaded_for_dir, get_git_cached_entry};
+use crate::api::git::conflict::conflict_sections;
+use crate::api::git::contents::append_file_contents;
+use crate::api::git::function_context::function_context_patch;
+use crate::api::git::settings::{
+    git_diff_options, git_status_limit, DiffAlgorithm, GitDiffOptions,
+};
+use crate::api::git::tokenize::{
+    ensure_git_cache_loaded_for_dir, get_git_cached_count, save_git_cache_batch_to_store,
+};
+use crate::api::tokenizer::lib::active_tokenizer;
 use git2::{
-    DiffFindOptions, DiffFormat, DiffOptions, ErrorCode, Patch, Repository, Status, StatusOptions,
-    StatusShow,
+    Commit, Delta, Diff, DiffDelta, DiffFindOptions, DiffOptions, ErrorCode, FileMode, Oid, Patch,
+    Repository, RevparseMode, Status, StatusOptions, StatusShow, Tree,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
 };
 use tauri::{AppHandle, Wry};
 
-const MAX_GIT_CHANGES: usize = 100;
+const MAX_CACHED_RANGES: usize = 16;
+/// Enough context to cover any file, trimmed afterwards in function context
+/// mode.
+const FULL_CONTEXT_LINES: u32 = 1 << 30;
 
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -22,19 +34,137 @@ pub(crate) struct GitChange {
     pub(crate) lines_added: i32,
     pub(crate) lines_deleted: i32,
     pub(crate) token_count: Option<usize>,
+    /// Lines changed between the base and the index. Only reported when
+    /// comparing against the working tree.
+    pub(crate) staged: Option<GitLineCounts>,
+    /// Lines changed between the index and the working tree, untracked
+    /// files included.
+    pub(crate) unstaged: Option<GitLineCounts>,
+}
+
+#[derive(Clone, Copy, Serialize, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitLineCounts {
+    pub(crate) lines_added: i32,
+    pub(crate) lines_deleted: i32,
 }
 
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct GitStatusResults {
     pub(crate) results: Vec<GitChange>,
+    /// Whether changes exist past this page.
     pub(crate) truncated: bool,
+    pub(crate) offset: usize,
+    pub(crate) limit: usize,
+    pub(crate) totals: GitStatusTotals,
+}
+
+/// Sums over every changed file, not only the returned page.
+#[derive(Clone, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitStatusTotals {
+    pub(crate) files: usize,
+    pub(crate) lines_added: i64,
+    pub(crate) lines_deleted: i64,
+    /// Tokens of the diffs counted so far.
+    pub(crate) token_count: usize,
+    /// Diffs still being counted. Their tokens arrive with
+    /// `git-token-counts` events.
+    pub(crate) pending_token_files: usize,
+}
+
+/// Which part of the working tree changes a diff covers. Ignored when
+/// comparing two commits.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum DiffScope {
+    /// Staged, unstaged and untracked changes together.
+    #[default]
+    All,
+    /// Changes between the base and the index.
+    Staged,
+    /// Changes between the index and the working tree of tracked files.
+    Unstaged,
+    /// Files not yet known to git.
+    Untracked,
+}
+
+impl DiffScope {
+    fn key(self) -> &'static str {
+        match self {
+            DiffScope::All => "all",
+            DiffScope::Staged => "staged",
+            DiffScope::Unstaged => "unstaged",
+            DiffScope::Untracked => "untracked",
+        }
+    }
+
+    fn includes_delta(self, delta: Delta) -> bool {
+        match self {
+            DiffScope::Untracked => delta == Delta::Untracked,
+            _ => true,
+        }
+    }
+
+    fn status_mask(self) -> Status {
+        match self {
+            DiffScope::All => Status::all(),
+            DiffScope::Staged => {
+                Status::INDEX_NEW
+                    | Status::INDEX_MODIFIED
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE
+            }
+            DiffScope::Unstaged => {
+                Status::WT_MODIFIED
+                    | Status::WT_DELETED
+                    | Status::WT_RENAMED
+                    | Status::WT_TYPECHANGE
+                    | Status::CONFLICTED
+            }
+            DiffScope::Untracked => Status::WT_NEW,
+        }
+    }
+
+    pub(crate) fn diff_options(self, options: &GitDiffOptions) -> DiffOptions {
+        let context_lines = if options.function_context {
+            FULL_CONTEXT_LINES
+        } else {
+            options.context_lines
+        };
+
+        let mut opts = DiffOptions::new();
+        opts.include_untracked(self != DiffScope::Unstaged)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(matches!(self, DiffScope::All | DiffScope::Untracked))
+            .include_typechange(true)
+            .context_lines(context_lines)
+            .ignore_whitespace_change(options.ignore_whitespace_change)
+            .ignore_blank_lines(options.ignore_blank_lines)
+            .patience(options.algorithm == DiffAlgorithm::Patience)
+            .minimal(options.algorithm == DiffAlgorithm::Minimal);
+        opts
+    }
+}
+
+/// The diff and page last requested for a project, reused by watcher
+/// updates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct SelectedDiff {
+    pub(crate) base_ref: Option<String>,
+    pub(crate) scope: DiffScope,
+    pub(crate) offset: usize,
+    /// Page size, the configured git status limit when unset.
+    pub(crate) limit: Option<usize>,
 }
 
 #[derive(Clone)]
 pub(crate) struct GitDiffData {
     pub(crate) lines_added: i32,
     pub(crate) lines_deleted: i32,
+    /// The diff as it is copied, file contents included.
     pub(crate) diff_bytes: Arc<Vec<u8>>,
     pub(crate) diff_hash: String,
 }
@@ -42,6 +172,8 @@ pub(crate) struct GitDiffData {
 #[derive(Clone)]
 pub(crate) struct GitDiffWorkItem {
     pub(crate) path: String,
+    /// Key of the diff in the git token cache, see `DiffRange::cache_id`.
+    pub(crate) cache_id: String,
     pub(crate) diff_bytes: Arc<Vec<u8>>,
     pub(crate) diff_hash: String,
 }
@@ -52,6 +184,26 @@ pub fn diff_hash(bytes: &[u8]) -> String {
     format!("{:016x}", hasher.finish())
 }
 
+pub fn classify_delta(delta: Delta) -> Option<String> {
+    match delta {
+        Delta::Conflicted => Some("conflicted".to_owned()),
+        Delta::Renamed => Some("renamed".to_owned()),
+        Delta::Deleted => Some("deleted".to_owned()),
+        Delta::Added | Delta::Copied | Delta::Untracked => Some("created".to_owned()),
+        Delta::Typechange => Some("typechange".to_owned()),
+        Delta::Modified => Some("modified".to_owned()),
+        _ => None,
+    }
+}
+
+/// Whether the delta moves a submodule, which is reported as its own change
+/// type whatever changed inside it.
+fn is_submodule_delta(delta: &DiffDelta) -> bool {
+    delta.new_file().mode() == FileMode::Commit || delta.old_file().mode() == FileMode::Commit
+}
+
+const SUBMODULE_CHANGE: &str = "submodule";
+
 pub fn classify_change(st: Status) -> Option<String> {
     use git2::Status as S;
 
@@ -66,135 +218,393 @@ pub fn classify_change(st: Status) -> Option<String> {
     }
 }
 
-pub fn git_diff_text(root: &str, paths: Vec<String>) -> Option<String> {
-    let repo = match Repository::open(root) {
-        Ok(r) => r,
-        Err(e) if e.code() == ErrorCode::NotFound => return None,
-        Err(_) => return Some(String::new()),
-    };
+/// The two sides of a diff. `new` is `None` when comparing against the
+/// working tree and index, which is what plain git status shows.
+pub(crate) struct DiffRange {
+    old: Option<Oid>,
+    new: Option<Oid>,
+    scope: DiffScope,
+    /// Identifies the resolved range, empty for the default HEAD range so
+    /// that cache ids stay plain paths there.
+    pub(crate) key: String,
+}
+
+impl DiffRange {
+    fn is_committed(&self) -> bool {
+        self.new.is_some()
+    }
+
+    /// Whether the new side of the diff is the working tree.
+    fn new_in_workdir(&self) -> bool {
+        self.new.is_none() && self.scope != DiffScope::Staged
+    }
+
+    /// Id under which a path's diff is cached for this range and scope.
+    pub(crate) fn cache_id(&self, path: &str) -> String {
+        match (self.key.is_empty(), self.scope) {
+            (true, DiffScope::All) => path.to_string(),
+            (false, DiffScope::All) => format!("{}/{}", self.key, path),
+            (true, scope) => format!("{}/{}", scope.key(), path),
+            (false, scope) => format!("{}:{}/{}", self.key, scope.key(), path),
+        }
+    }
+}
+
+static SELECTED_DIFFS: OnceLock<RwLock<HashMap<String, SelectedDiff>>> = OnceLock::new();
+static RANGE_CHANGES_CACHE: OnceLock<Mutex<HashMap<String, Arc<Vec<RangeChange>>>>> =
+    OnceLock::new();
+
+fn selected_diffs() -> &'static RwLock<HashMap<String, SelectedDiff>> {
+    SELECTED_DIFFS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn range_changes_cache() -> &'static Mutex<HashMap<String, Arc<Vec<RangeChange>>>> {
+    RANGE_CHANGES_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Remembers the diff last requested for `root`, so that watcher updates use
+/// the same comparison and page as the status shown. Copied diffs get their
+/// base and scope passed in instead.
+pub(crate) fn set_selected_diff(root: &str, mut selection: SelectedDiff) {
+    if let Ok(mut selected) = selected_diffs().write() {
+        selection.base_ref = selection.base_ref.filter(|r| !r.trim().is_empty());
+
+        if selection == SelectedDiff::default() {
+            selected.remove(root);
+        } else {
+            selected.insert(root.to_string(), selection);
+        }
+    }
+}
 
-    let head_tree = match repo.head().and_then(|h| h.peel_to_tree()) {
-        Ok(t) => Some(t),
-        Err(_) => match repo
-            .treebuilder(None)
+pub(crate) fn selected_diff(root: &str) -> SelectedDiff {
+    selected_diffs()
+        .read()
+        .ok()
+        .and_then(|selected| selected.get(root).cloned())
+        .unwrap_or_default()
+}
+
+fn head_tree_or_empty(repo: &Repository) -> Option<Tree<'_>> {
+    repo.head().and_then(|h| h.peel_to_tree()).ok().or_else(|| {
+        repo.treebuilder(None)
             .and_then(|tb| tb.write())
             .and_then(|oid| repo.find_tree(oid))
-        {
-            Ok(t) => Some(t),
-            Err(_) => None,
-        },
+            .ok()
+    })
+}
+
+fn tree_oid(repo: &Repository, spec: &str) -> Result<Oid, String> {
+    repo.revparse_single(spec)
+        .and_then(|object| object.peel_to_tree())
+        .map(|tree| tree.id())
+        .map_err(|err| format!("{}: {}", spec, err.message()))
+}
+
+fn range_commit<'r>(object: Option<&git2::Object<'r>>, spec: &str) -> Result<Commit<'r>, String> {
+    object
+        .and_then(|o| o.peel_to_commit().ok())
+        .ok_or_else(|| format!("{}: not a commit range", spec))
+}
+
+/// Resolves a base ref into the trees to diff. Accepts anything `git
+/// rev-parse` does: `main` compares the working tree against main,
+/// `origin/main..feature` compares two commits and `main...HEAD` compares
+/// HEAD against its merge base with main. An empty side means HEAD.
+pub(crate) fn resolve_diff_range(
+    repo: &Repository,
+    base_ref: Option<&str>,
+    scope: DiffScope,
+) -> Result<DiffRange, String> {
+    let Some(spec) = base_ref.map(str::trim).filter(|s| !s.is_empty()) else {
+        return Ok(DiffRange {
+            old: head_tree_or_empty(repo).map(|tree| tree.id()),
+            new: None,
+            scope,
+            key: String::new(),
+        });
     };
 
-    let mut opts = DiffOptions::new();
-    opts.include_untracked(true)
-        .recurse_untracked_dirs(true)
-        .ignore_submodules(true)
-        .include_typechange(true);
+    if !spec.contains("..") {
+        let old = tree_oid(repo, spec)?;
+        return Ok(DiffRange {
+            old: Some(old),
+            new: None,
+            scope,
+            key: format!("{}..workdir", old),
+        });
+    }
 
-    if !paths.is_empty() {
-        for path in &paths {
-            opts.pathspec(path);
-        }
+    let merge_base = spec.contains("...");
+    let separator = if merge_base { "..." } else { ".." };
+    let (from, to) = spec.split_once(separator).unwrap_or((spec, ""));
+    let from = if from.is_empty() { "HEAD" } else { from };
+    let to = if to.is_empty() { "HEAD" } else { to };
+
+    let revspec = repo
+        .revparse(&format!("{}{}{}", from, separator, to))
+        .map_err(|err| format!("{}: {}", spec, err.message()))?;
+
+    let from_commit = range_commit(revspec.from(), spec)?;
+    let to_commit = range_commit(revspec.to(), spec)?;
+
+    let old_commit = if revspec.mode().contains(RevparseMode::MERGE_BASE) {
+        let base = repo
+            .merge_base(from_commit.id(), to_commit.id())
+            .map_err(|err| format!("{}: {}", spec, err.message()))?;
+        repo.find_commit(base)
+            .map_err(|err| format!("{}: {}", spec, err.message()))?
+    } else {
+        from_commit
+    };
+
+    let old = old_commit.tree_id();
+    let new = to_commit.tree_id();
+
+    Ok(DiffRange {
+        old: Some(old),
+        new: Some(new),
+        scope: DiffScope::All,
+        key: format!("{}..{}", old, new),
+    })
+}
+
+fn diff_for_range<'r>(
+    repo: &'r Repository,
+    range: &DiffRange,
+    options: &GitDiffOptions,
+    paths: &[String],
+) -> Result<Diff<'r>, git2::Error> {
+    let mut opts = range.scope.diff_options(options);
+    for path in paths {
+        opts.pathspec(path);
     }
 
-    let mut diff = match repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut opts)) {
-        Ok(d) => d,
-        Err(_) => return Some(String::new()),
+    let old_tree = match range.old {
+        Some(oid) => Some(repo.find_tree(oid)?),
+        None => None,
+    };
+
+    let mut diff = match (range.new, range.scope) {
+        (Some(oid), _) => {
+            let new_tree = repo.find_tree(oid)?;
+            repo.diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), Some(&mut opts))?
+        }
+        (None, DiffScope::All) => {
+            repo.diff_tree_to_workdir_with_index(old_tree.as_ref(), Some(&mut opts))?
+        }
+        (None, DiffScope::Staged) => {
+            repo.diff_tree_to_index(old_tree.as_ref(), None, Some(&mut opts))?
+        }
+        (None, DiffScope::Unstaged | DiffScope::Untracked) => {
+            repo.diff_index_to_workdir(None, Some(&mut opts))?
+        }
     };
 
+    find_renames(&mut diff, options);
+
+    Ok(diff)
+}
+
+pub(crate) fn find_renames(diff: &mut Diff, options: &GitDiffOptions) {
+    let threshold = options.similarity_threshold.min(100);
+
     let mut find_opts = DiffFindOptions::new();
-    find_opts.renames(true).copies(true);
+    find_opts
+        .renames(true)
+        .copies(true)
+        .rename_threshold(threshold)
+        .copy_threshold(threshold);
     let _ = diff.find_similar(Some(&mut find_opts));
+}
 
-    let mut out = String::new();
-    let _ = diff.print(DiffFormat::Patch, |_, _, line| {
-        out.push_str(&String::from_utf8_lossy(line.content()));
-        true
-    });
+/// The patch text of one file, expanded to whole functions when the options
+/// ask for it.
+pub(crate) fn patch_bytes(patch: &mut Patch, options: &GitDiffOptions) -> Vec<u8> {
+    if options.function_context {
+        return function_context_patch(patch, options.context_lines);
+    }
 
-    Some(out)
+    patch
+        .to_buf()
+        .map(|buf| {
+            let slice: &[u8] = buf.as_ref();
+            slice.to_vec()
+        })
+        .unwrap_or_default()
 }
 
-pub(crate) enum GitStatusComputation {
-    NotRepository,
-    Finished {
-        changes: Vec<GitChange>,
-        work_items: Vec<GitDiffWorkItem>,
-        truncated: bool,
-    },
+/// The patch text of one file followed by its contents when the options ask
+/// for them, and by the versions of both sides when it is conflicted. Copied
+/// diffs and token counts both use it so their text matches.
+fn file_section_bytes(
+    repo: &Repository,
+    range: &DiffRange,
+    patch: &mut Patch,
+    options: &GitDiffOptions,
+) -> Vec<u8> {
+    let mut bytes = patch_bytes(patch, options);
+    append_file_contents(
+        &mut bytes,
+        repo,
+        &patch.delta(),
+        range.new_in_workdir(),
+        options.file_contents,
+    );
+
+    let delta = patch.delta();
+    if delta.status() == Delta::Conflicted && !range.is_committed() {
+        if let Some(sections) = delta
+            .new_file()
+            .path()
+            .and_then(|path| conflict_sections(repo, &path.to_string_lossy()))
+        {
+            bytes.extend_from_slice(&sections);
+        }
+    }
+
+    bytes
 }
 
-pub(crate) fn compute_git_status(app: &AppHandle<Wry>, root: &str) -> GitStatusComputation {
-    let repo = match Repository::open(root) {
-        Ok(r) => r,
-        Err(e) if e.code() == ErrorCode::NotFound => return GitStatusComputation::NotRepository,
-        Err(_) => {
-            return GitStatusComputation::Finished {
-                changes: Vec::new(),
-                work_items: Vec::new(),
-                truncated: false,
+fn line_counts(diff: &Diff) -> HashMap<String, GitLineCounts> {
+    let mut counts = HashMap::new();
+
+    for i in 0..diff.deltas().len() {
+        if let Ok(Some(patch)) = Patch::from_diff(diff, i) {
+            let (_ctx, adds, dels) = patch.line_stats().unwrap_or((0, 0, 0));
+            let delta = patch.delta();
+
+            if let Some(path) = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(|p| p.to_string_lossy().into_owned())
+            {
+                counts.insert(
+                    path,
+                    GitLineCounts {
+                        lines_added: adds as i32,
+                        lines_deleted: dels as i32,
+                    },
+                );
             }
         }
+    }
+
+    counts
+}
+
+type LineCountSplit = (
+    HashMap<String, GitLineCounts>,
+    HashMap<String, GitLineCounts>,
+);
+
+/// Line counts of the staged and unstaged halves of a working tree diff.
+fn staged_split(
+    repo: &Repository,
+    range: &DiffRange,
+    options: &GitDiffOptions,
+) -> Option<LineCountSplit> {
+    if range.is_committed() {
+        return None;
+    }
+
+    let old_tree = match range.old {
+        Some(oid) => Some(repo.find_tree(oid).ok()?),
+        None => None,
     };
 
-    ensure_git_cache_loaded_for_dir(app, root);
+    let mut staged_opts = DiffScope::Staged.diff_options(options);
+    let mut staged = repo
+        .diff_tree_to_index(old_tree.as_ref(), None, Some(&mut staged_opts))
+        .ok()?;
+    find_renames(&mut staged, options);
 
-    let head_tree = match repo.head().and_then(|h| h.peel_to_tree()) {
-        Ok(t) => Some(t),
-        Err(_) => match repo
-            .treebuilder(None)
-            .and_then(|tb| tb.write())
-            .and_then(|oid| repo.find_tree(oid))
-        {
-            Ok(t) => Some(t),
-            Err(_) => None,
-        },
+    let mut unstaged_opts = DiffScope::Untracked.diff_options(options);
+    let mut unstaged = repo
+        .diff_index_to_workdir(None, Some(&mut unstaged_opts))
+        .ok()?;
+    find_renames(&mut unstaged, options);
+
+    Some((line_counts(&staged), line_counts(&unstaged)))
+}
+
+/// Renders the diffs of `paths` against `base_ref` in `scope`. `None` when
+/// `root` isn't a repository; an error when `base_ref` doesn't resolve.
+pub fn git_diff_text(
+    root: &str,
+    paths: Vec<String>,
+    base_ref: Option<&str>,
+    scope: DiffScope,
+    options: &GitDiffOptions,
+) -> Result<Option<String>, String> {
+    let repo = match Repository::open(root) {
+        Ok(r) => r,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.message().to_string()),
     };
 
-    let mut diff_opts = DiffOptions::new();
-    diff_opts
-        .include_untracked(true)
-        .recurse_untracked_dirs(true)
-        .ignore_submodules(true)
-        .include_typechange(true);
-
-    let mut diff =
-        match repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut diff_opts)) {
-            Ok(d) => d,
-            Err(_) => {
-                return GitStatusComputation::Finished {
-                    changes: Vec::new(),
-                    work_items: Vec::new(),
-                    truncated: false,
-                }
+    let range = resolve_diff_range(&repo, base_ref, scope)?;
+    let diff =
+        diff_for_range(&repo, &range, options, &paths).map_err(|e| e.message().to_string())?;
+
+    let mut out = String::new();
+    for i in 0..diff.deltas().len() {
+        if let Ok(Some(mut patch)) = Patch::from_diff(&diff, i) {
+            if range.scope.includes_delta(patch.delta().status()) {
+                let bytes = file_section_bytes(&repo, &range, &mut patch, options);
+                out.push_str(&String::from_utf8_lossy(&bytes));
             }
-        };
+        }
+    }
 
-    let mut find_opts = DiffFindOptions::new();
-    find_opts.renames(true).copies(true);
-    let _ = diff.find_similar(Some(&mut find_opts));
+    Ok(Some(out))
+}
+
+pub(crate) enum GitStatusComputation {
+    NotRepository,
+    InvalidBaseRef(String),
+    Finished {
+        results: GitStatusResults,
+        /// Uncached diffs, the ones on the requested page first.
+        work_items: Vec<GitDiffWorkItem>,
+    },
+}
+
+/// A changed path with its change type and diff, before token counts.
+#[derive(Clone)]
+pub(crate) struct RangeChange {
+    path: String,
+    change_type: String,
+    data: Option<GitDiffData>,
+}
 
+fn collect_file_changes(
+    repo: &Repository,
+    diff: &Diff,
+    range: &DiffRange,
+    options: &GitDiffOptions,
+) -> HashMap<String, GitDiffData> {
+    let scope = range.scope;
     let mut file_changes: HashMap<String, GitDiffData> = HashMap::new();
 
     let deltas_len = diff.deltas().len();
     for i in 0..deltas_len {
-        if let Ok(Some(mut patch)) = Patch::from_diff(&diff, i) {
+        if let Ok(Some(mut patch)) = Patch::from_diff(diff, i) {
             let (_ctx, adds, dels) = patch.line_stats().unwrap_or((0, 0, 0));
 
             let delta = patch.delta();
+            if !scope.includes_delta(delta.status()) {
+                continue;
+            }
+
             if let Some(path) = delta
                 .new_file()
                 .path()
                 .or_else(|| delta.old_file().path())
                 .map(|p| p.to_string_lossy().into_owned())
             {
-                let diff_bytes = patch
-                    .to_buf()
-                    .map(|buf| {
-                        let slice: &[u8] = buf.as_ref();
-                        slice.to_vec()
-                    })
-                    .unwrap_or_default();
+                let diff_bytes = file_section_bytes(repo, range, &mut patch, options);
                 let diff_hash_value = if diff_bytes.is_empty() {
                     String::new()
                 } else {
@@ -214,40 +624,82 @@ pub(crate) fn compute_git_status(app: &AppHandle<Wry>, root: &str) -> GitStatusC
         }
     }
 
+    file_changes
+}
+
+/// Changes between two commits, taken from the diff deltas.
+fn range_changes(
+    repo: &Repository,
+    diff: &Diff,
+    range: &DiffRange,
+    options: &GitDiffOptions,
+) -> Vec<RangeChange> {
+    let scope = range.scope;
+    let file_changes = collect_file_changes(repo, diff, range, options);
+
+    diff.deltas()
+        .filter(|delta| scope.includes_delta(delta.status()))
+        .filter_map(|delta| {
+            let change_type = if is_submodule_delta(&delta) {
+                SUBMODULE_CHANGE.to_owned()
+            } else {
+                classify_delta(delta.status())?
+            };
+            let path = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(|p| p.to_string_lossy().into_owned())?;
+            let data = file_changes.get(&path).cloned();
+
+            Some(RangeChange {
+                path,
+                change_type,
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Changes of the working tree and index, taken from git status so that
+/// conflicts and index-only changes are classified the way git shows them.
+fn status_changes(
+    repo: &Repository,
+    diff: &Diff,
+    range: &DiffRange,
+    options: &GitDiffOptions,
+) -> Option<Vec<RangeChange>> {
+    let scope = range.scope;
+    let file_changes = collect_file_changes(repo, diff, range, options);
+
     let mut opts = StatusOptions::new();
     opts.show(StatusShow::IndexAndWorkdir)
         .include_untracked(true)
         .recurse_untracked_dirs(true)
         .include_ignored(false)
-        .exclude_submodules(true)
         .renames_head_to_index(true)
         .renames_index_to_workdir(true)
         .renames_from_rewrites(true);
 
-    let statuses = match repo.statuses(Some(&mut opts)) {
-        Ok(s) => s,
-        Err(_) => {
-            return GitStatusComputation::Finished {
-                changes: Vec::new(),
-                work_items: Vec::new(),
-                truncated: false,
-            }
-        }
-    };
-
-    let mut out: Vec<GitChange> = Vec::new();
-    let mut work_items: Vec<GitDiffWorkItem> = Vec::new();
-    let mut included_count = 0;
-    let mut truncated = false;
+    let statuses = repo.statuses(Some(&mut opts)).ok()?;
+    let mut changes = Vec::new();
 
     for entry in statuses.iter() {
-        let st = entry.status();
+        let st = entry.status() & scope.status_mask();
 
         if st.intersects(Status::IGNORED) {
             continue;
         }
 
         let change_type = match classify_change(st) {
+            Some(_)
+                if [entry.head_to_index(), entry.index_to_workdir()]
+                    .iter()
+                    .flatten()
+                    .any(is_submodule_delta) =>
+            {
+                SUBMODULE_CHANGE.to_owned()
+            }
             Some(ct) => ct,
             None => continue,
         };
@@ -274,50 +726,188 @@ pub(crate) fn compute_git_status(app: &AppHandle<Wry>, root: &str) -> GitStatusC
         };
 
         if let Some(path) = path {
-            if included_count >= MAX_GIT_CHANGES {
-                truncated = true;
-                continue;
-            }
+            let data = file_changes.get(&path).cloned();
+            changes.push(RangeChange {
+                path,
+                change_type,
+                data,
+            });
+        }
+    }
 
-            let data = file_changes.get(&path);
-            let lines_added = data.map(|d| d.lines_added).unwrap_or_default();
-            let lines_deleted = data.map(|d| d.lines_deleted).unwrap_or_default();
+    Some(changes)
+}
 
-            let mut token_count = None;
+/// Commit to commit diffs never change, so they are kept by resolved tree ids
+/// and only recomputed when a ref moves.
+fn cached_range_changes(
+    root: &str,
+    range: &DiffRange,
+    options: &GitDiffOptions,
+    compute: impl FnOnce() -> Option<Vec<RangeChange>>,
+) -> Option<Arc<Vec<RangeChange>>> {
+    if !range.is_committed() {
+        return compute().map(Arc::new);
+    }
 
-            if let Some(diff_data) = data {
-                if !diff_data.diff_hash.is_empty() {
-                    if let Some(entry) = get_git_cached_entry(root, &path) {
-                        if entry.diff_hash == diff_data.diff_hash {
-                            token_count = Some(entry.token_count);
-                        }
-                    }
+    let key = format!("{}\n{}\n{}", root, range.key, options.key());
+
+    if let Some(changes) = range_changes_cache()
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(&key).cloned())
+    {
+        return Some(changes);
+    }
+
+    let changes = Arc::new(compute()?);
+
+    if let Ok(mut cache) = range_changes_cache().lock() {
+        if cache.len() >= MAX_CACHED_RANGES {
+            cache.clear();
+        }
+        cache.insert(key, changes.clone());
+    }
+
+    Some(changes)
+}
+
+pub(crate) fn compute_git_status(
+    app: &AppHandle<Wry>,
+    root: &str,
+    selection: &SelectedDiff,
+) -> GitStatusComputation {
+    let offset = selection.offset;
+    let limit = selection.limit.unwrap_or_else(git_status_limit).max(1);
+
+    let empty = || GitStatusComputation::Finished {
+        results: GitStatusResults {
+            results: Vec::new(),
+            truncated: false,
+            offset,
+            limit,
+            totals: GitStatusTotals::default(),
+        },
+        work_items: Vec::new(),
+    };
+
+    let repo = match Repository::open(root) {
+        Ok(r) => r,
+        Err(e) if e.code() == ErrorCode::NotFound => return GitStatusComputation::NotRepository,
+        Err(_) => return empty(),
+    };
+
+    ensure_git_cache_loaded_for_dir(app, root);
+
+    let range = match resolve_diff_range(&repo, selection.base_ref.as_deref(), selection.scope) {
+        Ok(range) => range,
+        Err(message) => return GitStatusComputation::InvalidBaseRef(message),
+    };
+
+    let options = git_diff_options(app, root);
+
+    let changes = cached_range_changes(root, &range, &options, || {
+        let diff = diff_for_range(&repo, &range, &options, &[]).ok()?;
 
-                    if token_count.is_none() {
-                        work_items.push(GitDiffWorkItem {
-                            path: path.clone(),
+        if range.key.is_empty() {
+            status_changes(&repo, &diff, &range, &options)
+        } else {
+            Some(range_changes(&repo, &diff, &range, &options))
+        }
+    });
+
+    let Some(changes) = changes else {
+        return empty();
+    };
+
+    let split = staged_split(&repo, &range, &options);
+
+    let tokenizer = active_tokenizer();
+    let mut out: Vec<GitChange> = Vec::new();
+    let mut work_items: Vec<GitDiffWorkItem> = Vec::new();
+    let mut off_page_work_items: Vec<GitDiffWorkItem> = Vec::new();
+    let mut stamped = Vec::new();
+    let mut totals = GitStatusTotals {
+        files: changes.len(),
+        ..Default::default()
+    };
+
+    for (index, change) in changes.iter().enumerate() {
+        let on_page = index >= offset && index - offset < limit;
+
+        let data = change.data.as_ref();
+        let lines_added = data.map(|d| d.lines_added).unwrap_or_default();
+        let lines_deleted = data.map(|d| d.lines_deleted).unwrap_or_default();
+
+        totals.lines_added += i64::from(lines_added);
+        totals.lines_deleted += i64::from(lines_deleted);
+
+        let mut token_count = None;
+
+        if let Some(diff_data) = data {
+            if !diff_data.diff_hash.is_empty() {
+                let cache_id = range.cache_id(&change.path);
+
+                token_count = get_git_cached_count(
+                    root,
+                    tokenizer,
+                    &cache_id,
+                    &diff_data.diff_hash,
+                    &mut stamped,
+                );
+
+                match token_count {
+                    Some(count) => totals.token_count += count,
+                    None => {
+                        totals.pending_token_files += 1;
+
+                        let item = GitDiffWorkItem {
+                            path: change.path.clone(),
+                            cache_id,
                             diff_bytes: diff_data.diff_bytes.clone(),
                             diff_hash: diff_data.diff_hash.clone(),
-                        });
+                        };
+
+                        if on_page {
+                            work_items.push(item);
+                        } else {
+                            off_page_work_items.push(item);
+                        }
                     }
                 }
             }
+        }
 
-            out.push(GitChange {
-                path,
-                change_type,
-                lines_added,
-                lines_deleted,
-                token_count,
-            });
-
-            included_count += 1;
+        if !on_page {
+            continue;
         }
+
+        out.push(GitChange {
+            path: change.path.clone(),
+            change_type: change.change_type.clone(),
+            lines_added,
+            lines_deleted,
+            token_count,
+            staged: split
+                .as_ref()
+                .and_then(|(staged, _)| staged.get(&change.path).copied()),
+            unstaged: split
+                .as_ref()
+                .and_then(|(_, unstaged)| unstaged.get(&change.path).copied()),
+        });
     }
 
+    work_items.extend(off_page_work_items);
+    save_git_cache_batch_to_store(app, root, &stamped);
+
     GitStatusComputation::Finished {
-        changes: out,
+        results: GitStatusResults {
+            results: out,
+            truncated: offset.saturating_add(limit) < changes.len(),
+            offset,
+            limit,
+            totals,
+        },
         work_items,
-        truncated,
     }
 }
@@ -1,8 +1,10 @@
 use super::event::{self, GitTokenCountsEvent};
 use crate::{
+    api::cache::lib::{needs_use_stamp, now_ms, prune_store_cache, PruneStats},
     api::git::status::GitDiffWorkItem,
-    api::tokenize::count_tokens_for_text,
-    store::{save_store, StoreCategoryKey, StoreDataKey, STORE_FILE_NAME},
+    api::tokenizer::lib::{active_tokenizer, Tokenizer},
+    errors::ApplicationError,
+    store::{data_write_lock, save_store, StoreCategoryKey, StoreDataKey, STORE_FILE_NAME},
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
@@ -24,6 +26,9 @@ static GIT_LOADED_DIRS: OnceLock<RwLock<HashSet<String>>> = OnceLock::new();
 pub(crate) struct GitTokenCacheEntry {
     pub(crate) diff_hash: String,
     pub(crate) token_count: usize,
+    /// When the entry was last written or hit, for pruning unused entries.
+    #[serde(default)]
+    pub(crate) used_ms: u64,
 }
 
 fn git_cache() -> &'static RwLock<HashMap<String, HashMap<String, GitTokenCacheEntry>>> {
@@ -55,8 +60,9 @@ pub fn ensure_git_cache_loaded_for_dir(app: &AppHandle<Wry>, root: &str) {
                             {
                                 if let Ok(mut cache) = git_cache().write() {
                                     let entry = cache.entry(root.to_string()).or_default();
-                                    for (path, cache_entry) in map {
-                                        entry.insert(path, cache_entry);
+                                    for (key, cache_entry) in map {
+                                        let (tokenizer, path) = Tokenizer::split_cache_key(&key);
+                                        entry.insert(tokenizer.cache_key(path), cache_entry);
                                     }
                                 }
                             }
@@ -73,7 +79,7 @@ pub fn ensure_git_cache_loaded_for_dir(app: &AppHandle<Wry>, root: &str) {
     }
 }
 
-fn save_git_cache_batch_to_store(
+pub(crate) fn save_git_cache_batch_to_store(
     app: &AppHandle<Wry>,
     root: &str,
     batch: &[(String, GitTokenCacheEntry)],
@@ -82,6 +88,8 @@ fn save_git_cache_batch_to_store(
         return;
     }
 
+    let _guard = data_write_lock();
+
     if let Ok(store) = app.store(STORE_FILE_NAME) {
         let mut data: Map<String, Value> = store
             .get(StoreCategoryKey::DATA)
@@ -107,9 +115,9 @@ fn save_git_cache_batch_to_store(
         }
 
         if let Some(token_cache_object) = token_cache_entry.as_object_mut() {
-            for (path, entry) in batch {
+            for (cache_key, entry) in batch {
                 if let Ok(entry_value) = serde_json::to_value(entry.clone()) {
-                    token_cache_object.insert(path.clone(), entry_value);
+                    token_cache_object.insert(cache_key.clone(), entry_value);
                 }
             }
         }
@@ -121,26 +129,103 @@ fn save_git_cache_batch_to_store(
     }
 }
 
-pub fn get_git_cached_entry(root: &str, path: &str) -> Option<GitTokenCacheEntry> {
+/// Drops the diff counts of `root` that weren't used in `max_age_ms`. Paths
+/// aren't checked against the tree since deleted files still have diffs;
+/// a count that is still needed is simply made again.
+pub(crate) fn prune_git_token_cache(
+    app: &AppHandle<Wry>,
+    root: &str,
+    max_age_ms: u64,
+) -> Result<PruneStats, ApplicationError> {
+    let now = now_ms();
+
+    let stats = prune_store_cache(app, root, StoreDataKey::GIT_TOKEN_CACHE, |key, value| {
+        let (tokenizer, path) = Tokenizer::split_cache_key(key);
+        let mut entry = serde_json::from_value::<GitTokenCacheEntry>(value.clone()).ok()?;
+
+        if entry.used_ms == 0 {
+            entry.used_ms = now;
+        } else if now.saturating_sub(entry.used_ms) > max_age_ms {
+            return None;
+        }
+
+        Some((tokenizer.cache_key(path), serde_json::to_value(entry).ok()?))
+    })?;
+
+    if let Ok(mut cache) = git_cache().write() {
+        if let Some(directory) = cache.get_mut(root) {
+            for key in &stats.removed_keys {
+                let (tokenizer, path) = Tokenizer::split_cache_key(key);
+                directory.remove(&tokenizer.cache_key(path));
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+fn get_git_cached_entry(
+    root: &str,
+    tokenizer: Tokenizer,
+    path: &str,
+) -> Option<GitTokenCacheEntry> {
     let cache = git_cache().read().ok()?;
     cache
         .get(root)
-        .and_then(|directory| directory.get(path).cloned())
+        .and_then(|directory| directory.get(&tokenizer.cache_key(path)).cloned())
 }
 
-fn set_git_cache_entry(root: &str, path: &str, entry: GitTokenCacheEntry) {
+/// Returns the cached count of the diff of `path` if it is still the diff
+/// hashed as `diff_hash`. Like `count_file`, a hit whose use stamp is a day
+/// old is restamped in memory and pushed to `stamped`, to be saved with
+/// `save_git_cache_batch_to_store`.
+pub(crate) fn get_git_cached_count(
+    root: &str,
+    tokenizer: Tokenizer,
+    path: &str,
+    diff_hash: &str,
+    stamped: &mut Vec<(String, GitTokenCacheEntry)>,
+) -> Option<usize> {
+    let entry =
+        get_git_cached_entry(root, tokenizer, path).filter(|entry| entry.diff_hash == diff_hash)?;
+    let now = now_ms();
+
+    if needs_use_stamp(entry.used_ms, now) {
+        let cache_key = tokenizer.cache_key(path);
+        let entry = GitTokenCacheEntry {
+            used_ms: now,
+            ..entry.clone()
+        };
+        set_git_cache_entry(root, &cache_key, entry.clone());
+        stamped.push((cache_key, entry));
+    }
+
+    Some(entry.token_count)
+}
+
+fn set_git_cache_entry(root: &str, cache_key: &str, entry: GitTokenCacheEntry) {
     if let Ok(mut cache) = git_cache().write() {
         let directory = cache.entry(root.to_string()).or_default();
-        directory.insert(path.to_string(), entry);
+        directory.insert(cache_key.to_string(), entry);
     }
 }
 
-pub fn spawn_git_token_count_task(app: AppHandle<Wry>, root: String, items: Vec<GitDiffWorkItem>) {
+/// Counts the tokens of `items` in the background. The last event carries the
+/// total of the whole change set, `known_token_count` being the part that
+/// was already cached.
+pub fn spawn_git_token_count_task(
+    app: AppHandle<Wry>,
+    root: String,
+    items: Vec<GitDiffWorkItem>,
+    known_token_count: usize,
+) {
     if items.is_empty() {
         return;
     }
 
     std::thread::spawn(move || {
+        let tokenizer = active_tokenizer();
+        let mut total_token_count = known_token_count;
         let mut batch: HashMap<String, usize> = HashMap::new();
         let mut store_batch: Vec<(String, GitTokenCacheEntry)> = Vec::new();
 
@@ -149,29 +234,31 @@ pub fn spawn_git_token_count_task(app: AppHandle<Wry>, root: String, items: Vec<
                 continue;
             }
 
-            let cached_entry = get_git_cached_entry(&root, &item.path);
-            let mut token_count = None;
-
-            if let Some(entry) = cached_entry {
-                if entry.diff_hash == item.diff_hash {
-                    token_count = Some(entry.token_count);
-                }
-            }
+            let token_count = get_git_cached_count(
+                &root,
+                tokenizer,
+                &item.cache_id,
+                &item.diff_hash,
+                &mut store_batch,
+            );
 
             let token_count = if let Some(count) = token_count {
                 count
             } else {
                 let diff_text = String::from_utf8_lossy(item.diff_bytes.as_ref());
-                let count = count_tokens_for_text(&diff_text);
+                let count = tokenizer.count(&diff_text);
                 let entry = GitTokenCacheEntry {
                     diff_hash: item.diff_hash.clone(),
                     token_count: count,
+                    used_ms: now_ms(),
                 };
-                set_git_cache_entry(&root, &item.path, entry.clone());
-                store_batch.push((item.path.clone(), entry));
+                let cache_key = tokenizer.cache_key(&item.cache_id);
+                set_git_cache_entry(&root, &cache_key, entry.clone());
+                store_batch.push((cache_key, entry));
                 count
             };
 
+            total_token_count += token_count;
             batch.insert(item.path.clone(), token_count);
 
             if batch.len() >= GIT_TOKEN_BATCH_SIZE {
@@ -180,6 +267,7 @@ pub fn spawn_git_token_count_task(app: AppHandle<Wry>, root: String, items: Vec<
                     GitTokenCountsEvent {
                         root: root.clone(),
                         files: batch.clone(),
+                        total_token_count: None,
                     },
                 );
                 batch.clear();
@@ -195,8 +283,13 @@ pub fn spawn_git_token_count_task(app: AppHandle<Wry>, root: String, items: Vec<
             save_git_cache_batch_to_store(&app, &root, &store_batch);
         }
 
-        if !batch.is_empty() {
-            event::emit_git_token_counts_event(&app, GitTokenCountsEvent { root, files: batch });
-        }
+        event::emit_git_token_counts_event(
+            &app,
+            GitTokenCountsEvent {
+                root,
+                files: batch,
+                total_token_count: Some(total_token_count),
+            },
+        );
     });
 }
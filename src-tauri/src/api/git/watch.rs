@@ -1,17 +1,19 @@
+use crate::api::git::{
+    settings::git_watch_debounce_ms,
+    watch_filter::{is_rules_file, WatchFilter},
+};
 use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
 use std::{
     collections::HashMap,
     path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, Mutex, OnceLock,
+        Arc, Mutex, OnceLock, RwLock,
     },
     time::{Duration, Instant},
 };
 use tauri::{AppHandle, Wry};
 
-const DEBOUNCE_DURATION_MS: u64 = 2000;
-
 pub(crate) struct GitWatcherHandle {
     #[allow(dead_code)]
     pub(crate) watcher: RecommendedWatcher,
@@ -46,22 +48,57 @@ fn get_or_create_debounce_state(root: &str) -> Arc<DebounceState> {
         .clone()
 }
 
+fn is_watching(root: &str) -> bool {
+    git_watchers_registry()
+        .lock()
+        .map(|registry| registry.contains_key(root))
+        .unwrap_or(false)
+}
+
+/// Drops the watcher of `root`. Returns whether one was running.
+pub(crate) fn stop_git_watcher(root: &str) -> bool {
+    let removed = git_watchers_registry()
+        .lock()
+        .map(|mut registry| registry.remove(root).is_some())
+        .unwrap_or(false);
+
+    if let Ok(mut registry) = debounce_registry().lock() {
+        registry.remove(root);
+    }
+
+    removed
+}
+
 pub(crate) fn ensure_git_watcher_started(app: AppHandle<Wry>, root: String) {
     if !Path::new(&root).exists() {
         return;
     }
 
-    if let Ok(registry) = git_watchers_registry().lock() {
-        if registry.contains_key(&root) {
-            return;
-        }
+    if is_watching(&root) {
+        return;
     }
 
     let watch_root = root.clone();
     let app_handle = app.clone();
+    let filter = RwLock::new(WatchFilter::new(Path::new(&root)));
 
     let mut watcher = match notify::recommended_watcher(move |res: notify::Result<Event>| {
-        if res.is_err() {
+        let Ok(event) = res else {
+            return;
+        };
+
+        if event.paths.iter().any(|path| is_rules_file(path)) {
+            if let Ok(mut filter) = filter.write() {
+                *filter = WatchFilter::new(Path::new(&watch_root));
+            }
+        }
+
+        let relevant = event.paths.is_empty()
+            || filter.read().map_or(true, |filter| {
+                event.paths.iter().any(|path| filter.is_relevant(path))
+            });
+
+        if !relevant {
             return;
         }
 
@@ -82,7 +119,8 @@ pub(crate) fn ensure_git_watcher_started(app: AppHandle<Wry>, root: String) {
         let app = app_handle.clone();
         let root = watch_root.clone();
         let state = debounce_state.clone();
-        let debounce_duration = Duration::from_millis(DEBOUNCE_DURATION_MS);
+        let debounce_duration =
+            Duration::from_millis(git_watch_debounce_ms(&app_handle, &watch_root));
 
         std::thread::spawn(move || loop {
             std::thread::sleep(debounce_duration);
@@ -95,6 +133,9 @@ pub(crate) fn ensure_git_watcher_started(app: AppHandle<Wry>, root: String) {
 
             if elapsed >= debounce_duration {
                 state.pending.store(false, Ordering::SeqCst);
+                if !is_watching(&root) {
+                    break;
+                }
                 tauri::async_runtime::spawn(async move {
                     crate::api::git::event::emit_git_status_event(app.clone(), root.clone());
                 });
@@ -0,0 +1,145 @@
+use crate::api::directory::lib::{directory_walker, is_app_directory, IGNORE_FILES};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::cmp::Reverse;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+const GIT_DIR: &str = ".git";
+
+/// Files under `.git` whose changes alter what git status shows. Lock files
+/// and objects are left out, the former being written by every git command.
+const GIT_STATE_FILES: &[&str] = &[
+    "HEAD",
+    "index",
+    "packed-refs",
+    "MERGE_HEAD",
+    "CHERRY_PICK_HEAD",
+    "REVERT_HEAD",
+];
+/// Directories under `.git` where any change counts: the refs, and the state
+/// of a rebase in progress.
+const GIT_STATE_DIRS: &[&str] = &["refs", "rebase-merge", "rebase-apply"];
+
+/// Tells the file events that can change git status from the rest. Paths are
+/// ignored with the rules of `list_directory`, the app's own directories are
+/// skipped, and inside `.git` only HEAD, the index, refs and merge, rebase,
+/// cherry-pick and revert state count.
+pub(crate) struct WatchFilter {
+    root: PathBuf,
+    /// Matchers of each ignore and exclude file, deepest directory first and
+    /// in the order of `IGNORE_FILES` within a directory.
+    matchers: Vec<Gitignore>,
+}
+
+impl WatchFilter {
+    pub(crate) fn new(root: &Path) -> Self {
+        // Each matcher with its rank among the rule files of its directory.
+        let mut matchers: Vec<(Gitignore, usize)> = Vec::new();
+
+        let walker = directory_walker(root)
+            .filter_entry(|entry| {
+                entry.file_name() != GIT_DIR && !is_app_directory(entry.file_name())
+            })
+            .build();
+
+        for entry in walker.flatten() {
+            if let Some(rank) = ignore_file_rank(entry.file_name()) {
+                matchers.push((Gitignore::new(entry.path()).0, rank));
+            }
+        }
+
+        // Rules of parent directories apply up to the repository root.
+        for dir in root.ancestors() {
+            for (rank, name) in IGNORE_FILES.iter().enumerate() {
+                let file = dir.join(name);
+                if dir != root && file.is_file() {
+                    matchers.push((Gitignore::new(&file).0, rank));
+                }
+            }
+            if dir.join(GIT_DIR).exists() {
+                break;
+            }
+        }
+
+        if let Some(repo_root) = root.ancestors().find(|dir| dir.join(GIT_DIR).is_dir()) {
+            let exclude = repo_root.join(GIT_DIR).join("info").join("exclude");
+            let mut builder = GitignoreBuilder::new(repo_root);
+            if exclude.is_file() && builder.add(&exclude).is_none() {
+                if let Ok(matcher) = builder.build() {
+                    matchers.push((matcher, IGNORE_FILES.len()));
+                }
+            }
+        }
+
+        matchers
+            .sort_by_key(|(matcher, rank)| (Reverse(matcher.path().components().count()), *rank));
+
+        Self {
+            root: root.to_path_buf(),
+            matchers: matchers.into_iter().map(|(matcher, _)| matcher).collect(),
+        }
+    }
+
+    /// Whether an event on `path` can change git status.
+    pub(crate) fn is_relevant(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+
+        let mut components = relative.components();
+        match components.next() {
+            Some(Component::Normal(first)) if first == GIT_DIR => {
+                return is_git_state_file(components.as_path());
+            }
+            Some(Component::Normal(first)) if is_app_directory(first) => return false,
+            _ => {}
+        }
+
+        if relative.components().any(|c| c.as_os_str() == GIT_DIR) {
+            return false;
+        }
+
+        let is_dir = path.is_dir();
+
+        for matcher in &self.matchers {
+            if !path.starts_with(matcher.path()) {
+                continue;
+            }
+
+            match matcher.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return false,
+                Match::Whitelist(_) => return true,
+                Match::None => {}
+            }
+        }
+
+        true
+    }
+}
+
+fn ignore_file_rank(name: &OsStr) -> Option<usize> {
+    IGNORE_FILES.iter().position(|file| name == *file)
+}
+
+/// Whether `path` holds ignore rules, so the filter needs rebuilding when it
+/// changes.
+pub(crate) fn is_rules_file(path: &Path) -> bool {
+    path.file_name().and_then(ignore_file_rank).is_some()
+        || path.ends_with(Path::new(GIT_DIR).join("info").join("exclude"))
+}
+
+fn is_git_state_file(path: &Path) -> bool {
+    if path.extension().is_some_and(|ext| ext == "lock") {
+        return false;
+    }
+
+    let mut components = path.components();
+    match components.next() {
+        Some(Component::Normal(first)) if GIT_STATE_DIRS.iter().any(|d| first == *d) => true,
+        Some(Component::Normal(first)) => {
+            components.next().is_none() && GIT_STATE_FILES.iter().any(|f| first == *f)
+        }
+        _ => false,
+    }
+}
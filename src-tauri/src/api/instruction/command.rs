@@ -10,9 +10,10 @@ use crate::{
     api::{
         instruction::lib::{get_saved_instructions, ContentLengthMode, SavedInstruction},
         tokenize::count_tokens_for_text,
+        tokenizer::lib::active_tokenizer,
     },
     errors::{codes, ApplicationError},
-    store::{open_store, save_store, StoreCategoryKey, StoreDataKey},
+    store::{data_write_lock, open_store, save_store, StoreCategoryKey, StoreDataKey},
 };
 
 #[tauri::command]
@@ -30,7 +31,9 @@ pub fn upsert_instruction(
         Err(_) => 0,
     };
 
-    let token_count = count_tokens_for_text(&content);
+    let tokenizer = active_tokenizer();
+    let token_count = tokenizer.count(&content);
+    let _guard = data_write_lock();
     let mut data: Map<String, Value> = store
         .get(StoreCategoryKey::DATA)
         .and_then(|value| value.as_object().cloned())
@@ -70,6 +73,7 @@ pub fn upsert_instruction(
                         "name": name.clone(),
                         "content": content.clone(),
                         "tokenCount": token_count,
+                        "tokenizer": tokenizer.key(),
                         "addedAt": added_at,
                         "updatedAt": now,
                     }),
@@ -125,6 +129,7 @@ pub fn upsert_instruction(
             "name": name,
             "content": content,
             "tokenCount": token_count,
+            "tokenizer": tokenizer.key(),
             "addedAt": now,
             "updatedAt": now,
         }),
@@ -143,6 +148,7 @@ pub fn delete_instructions(
     directory_path: String,
     instructions_ids: Vec<String>,
 ) -> Result<(), ApplicationError> {
+    let _guard = data_write_lock();
     let store = open_store(&app)?;
 
     let mut data: Map<String, Value> = store
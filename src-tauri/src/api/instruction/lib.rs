@@ -1,3 +1,4 @@
+use crate::api::tokenizer::lib::stored_token_count;
 use crate::store::StoreDataKey;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -73,14 +74,11 @@ pub fn get_saved_instructions(
             let obj = entry.as_object()?;
             let name = obj.get("name")?.as_str()?.to_string();
             let content_value = obj.get("content")?.as_str()?.to_string();
+            let token_count = stored_token_count(obj, &content_value);
             let content = match display_mode {
                 ContentLengthMode::Full => content_value,
                 ContentLengthMode::Truncated => content_value.chars().take(256).collect(),
             };
-            let token_count = obj
-                .get("tokenCount")
-                .and_then(|v| v.as_u64())
-                .map(|v| v as usize);
             let added_at = obj.get("addedAt").and_then(|v| v.as_u64());
             let updated_at = obj.get("updatedAt").and_then(|v| v.as_u64());
 
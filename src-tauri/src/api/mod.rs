@@ -1,8 +1,15 @@
-pub(crate) mod clipboard;
+pub(crate) mod cache;
+pub mod clipboard;
 pub mod directory;
 pub mod editor;
-pub(crate) mod git;
+pub(crate) mod export;
+pub mod git;
 pub(crate) mod instruction;
-pub(crate) mod tokenize;
+pub(crate) mod model;
+pub mod patch;
+pub mod profile;
+pub mod redact;
+pub mod tokenize;
+pub mod tokenizer;
 pub mod tree;
 pub(crate) mod web;
@@ -0,0 +1,174 @@
+use serde_json::{json, Map, Value};
+use tauri::AppHandle;
+
+use crate::api::model::lib::{
+    active_model_profile, all_profiles, find_profile, get_user_profiles_from_config,
+    set_active_model_profile, set_user_profiles_in_config, ModelProfile,
+};
+use crate::api::tokenizer::lib::{set_active_tokenizer, set_tokenizer_in_config};
+use crate::errors::{codes, ApplicationError};
+use crate::store::{open_store, save_store, StoreCategoryKey, StoreConfigKey};
+
+fn read_config<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+) -> Result<Map<String, Value>, ApplicationError> {
+    let store = open_store(app)?;
+
+    let config = store
+        .get(StoreCategoryKey::CONFIG)
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_else(Map::new);
+
+    store.close_resource();
+
+    Ok(config)
+}
+
+fn write_config<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    config: Map<String, Value>,
+) -> Result<(), ApplicationError> {
+    let store = open_store(app)?;
+
+    store.set(StoreCategoryKey::CONFIG, Value::Object(config));
+    save_store(&store)?;
+    store.close_resource();
+
+    Ok(())
+}
+
+fn profile_not_found(id: &str) -> ApplicationError {
+    ApplicationError {
+        code: codes::MODEL_PROFILE_NOT_FOUND_ERROR,
+        message: Some(format!("No model profile with id {}", id)),
+    }
+}
+
+/// Writes the config after a profile edit, then keeps the in-memory profile in
+/// step with it. When the edit changed the active profile's tokenizer, the
+/// active tokenizer is switched too. Nothing in memory changes if the write
+/// fails.
+fn write_profiles<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    mut config: Map<String, Value>,
+) -> Result<(), ApplicationError> {
+    let Some(active) = active_model_profile() else {
+        return write_config(app, config);
+    };
+
+    let updated = find_profile(&config, &active.id);
+    let tokenizer = updated
+        .as_ref()
+        .map(|profile| profile.tokenizer)
+        .filter(|tokenizer| *tokenizer != active.tokenizer);
+
+    if let Some(tokenizer) = tokenizer {
+        set_tokenizer_in_config(&mut config, tokenizer);
+    }
+
+    write_config(app, config)?;
+
+    if let Some(tokenizer) = tokenizer {
+        set_active_tokenizer(tokenizer);
+    }
+    set_active_model_profile(updated);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn list_model_profiles<R: tauri::Runtime>(
+    app: AppHandle<R>,
+) -> Result<Vec<ModelProfile>, ApplicationError> {
+    Ok(all_profiles(&read_config(&app)?))
+}
+
+#[tauri::command]
+pub(crate) fn get_model_profile() -> Option<ModelProfile> {
+    active_model_profile()
+}
+
+/// Selects the profile used for usage figures and switches the tokenizer to
+/// the one the profile uses. `None` clears the selection.
+#[tauri::command]
+pub(crate) fn set_model_profile<R: tauri::Runtime>(
+    app: AppHandle<R>,
+    profile_id: Option<String>,
+) -> Result<Option<ModelProfile>, ApplicationError> {
+    let mut config = read_config(&app)?;
+
+    let profile = match &profile_id {
+        Some(id) => Some(find_profile(&config, id).ok_or_else(|| profile_not_found(id))?),
+        None => None,
+    };
+
+    match &profile {
+        Some(profile) => {
+            config.insert(StoreConfigKey::MODEL_PROFILE.to_string(), json!(profile.id));
+            set_tokenizer_in_config(&mut config, profile.tokenizer);
+        }
+        None => {
+            config.remove(StoreConfigKey::MODEL_PROFILE);
+        }
+    }
+
+    write_config(&app, config)?;
+
+    if let Some(profile) = &profile {
+        set_active_tokenizer(profile.tokenizer);
+    }
+    set_active_model_profile(profile.clone());
+
+    Ok(profile)
+}
+
+/// Adds a user-defined profile, or replaces the one with the same id.
+#[tauri::command]
+pub(crate) fn save_model_profile<R: tauri::Runtime>(
+    app: AppHandle<R>,
+    profile: ModelProfile,
+) -> Result<(), ApplicationError> {
+    let mut config = read_config(&app)?;
+    let mut profiles = get_user_profiles_from_config(&config);
+
+    let profile = ModelProfile {
+        builtin: false,
+        ..profile
+    };
+
+    match profiles.iter_mut().find(|p| p.id == profile.id) {
+        Some(existing) => *existing = profile,
+        None => profiles.push(profile),
+    }
+
+    set_user_profiles_in_config(&mut config, &profiles);
+    write_profiles(&app, config)
+}
+
+/// Removes a user-defined profile. Built-in profiles can't be deleted, but a
+/// user profile that overrides one falls back to the built-in values.
+#[tauri::command]
+pub(crate) fn delete_model_profile<R: tauri::Runtime>(
+    app: AppHandle<R>,
+    profile_id: String,
+) -> Result<(), ApplicationError> {
+    let mut config = read_config(&app)?;
+    let mut profiles = get_user_profiles_from_config(&config);
+    let count = profiles.len();
+
+    profiles.retain(|p| p.id != profile_id);
+
+    if profiles.len() == count {
+        return Err(profile_not_found(&profile_id));
+    }
+
+    set_user_profiles_in_config(&mut config, &profiles);
+
+    if find_profile(&config, &profile_id).is_none()
+        && active_model_profile().is_some_and(|active| active.id == profile_id)
+    {
+        config.remove(StoreConfigKey::MODEL_PROFILE);
+    }
+
+    write_profiles(&app, config)
+}
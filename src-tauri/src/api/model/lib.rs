@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::{OnceLock, RwLock};
+use tauri::AppHandle;
+
+use crate::{
+    api::tokenizer::lib::Tokenizer,
+    store::{open_store, StoreCategoryKey, StoreConfigKey},
+};
+
+const TOKENS_PER_MILLION: f64 = 1_000_000.0;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelProfile {
+    pub id: String,
+    pub name: String,
+    pub tokenizer: Tokenizer,
+    pub context_window: usize,
+    /// Price in USD per million input tokens.
+    pub input_price_per_million: f64,
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+/// How much of the active model's context window a token count takes up, and
+/// what sending it would cost.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextUsage {
+    pub model_id: String,
+    pub context_window: usize,
+    pub window_percentage: f64,
+    pub estimated_cost: f64,
+}
+
+fn builtin(
+    id: &str,
+    name: &str,
+    tokenizer: Tokenizer,
+    context_window: usize,
+    input_price_per_million: f64,
+) -> ModelProfile {
+    ModelProfile {
+        id: id.to_string(),
+        name: name.to_string(),
+        tokenizer,
+        context_window,
+        input_price_per_million,
+        builtin: true,
+    }
+}
+
+/// Models without a public tokenizer are counted with the char/4 estimate.
+pub fn builtin_profiles() -> Vec<ModelProfile> {
+    vec![
+        builtin("gpt-4o", "GPT-4o", Tokenizer::O200kBase, 128_000, 2.5),
+        builtin(
+            "gpt-4o-mini",
+            "GPT-4o mini",
+            Tokenizer::O200kBase,
+            128_000,
+            0.15,
+        ),
+        builtin("gpt-4.1", "GPT-4.1", Tokenizer::O200kBase, 1_047_576, 2.0),
+        builtin("o3", "o3", Tokenizer::O200kBase, 200_000, 2.0),
+        builtin(
+            "gpt-4-turbo",
+            "GPT-4 Turbo",
+            Tokenizer::Cl100kBase,
+            128_000,
+            10.0,
+        ),
+        builtin(
+            "gpt-3.5-turbo",
+            "GPT-3.5 Turbo",
+            Tokenizer::Cl100kBase,
+            16_385,
+            0.5,
+        ),
+        builtin(
+            "claude-sonnet",
+            "Claude Sonnet",
+            Tokenizer::CharEstimate,
+            200_000,
+            3.0,
+        ),
+        builtin(
+            "claude-opus",
+            "Claude Opus",
+            Tokenizer::CharEstimate,
+            200_000,
+            15.0,
+        ),
+        builtin(
+            "gemini-2.5-pro",
+            "Gemini 2.5 Pro",
+            Tokenizer::CharEstimate,
+            1_048_576,
+            1.25,
+        ),
+    ]
+}
+
+impl ModelProfile {
+    pub fn usage_for(&self, token_count: usize) -> ContextUsage {
+        let window_percentage = if self.context_window > 0 {
+            (token_count as f64 / self.context_window as f64) * 100.0
+        } else {
+            0.0
+        };
+
+        ContextUsage {
+            model_id: self.id.clone(),
+            context_window: self.context_window,
+            window_percentage,
+            estimated_cost: token_count as f64 / TOKENS_PER_MILLION * self.input_price_per_million,
+        }
+    }
+}
+
+// Pure helper: read the user-defined profiles from a config map.
+pub fn get_user_profiles_from_config(config: &Map<String, Value>) -> Vec<ModelProfile> {
+    config
+        .get(StoreConfigKey::MODEL_PROFILES)
+        .and_then(|v| serde_json::from_value::<Vec<ModelProfile>>(v.clone()).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|profile| ModelProfile {
+            builtin: false,
+            ..profile
+        })
+        .collect()
+}
+
+// Pure helper: write the user-defined profiles into a config map.
+pub fn set_user_profiles_in_config(config: &mut Map<String, Value>, profiles: &[ModelProfile]) {
+    if profiles.is_empty() {
+        config.remove(StoreConfigKey::MODEL_PROFILES);
+    } else {
+        config.insert(StoreConfigKey::MODEL_PROFILES.to_string(), json!(profiles));
+    }
+}
+
+/// Built-in profiles followed by the user's, where a user profile with the
+/// same id replaces the built-in one.
+pub fn all_profiles(config: &Map<String, Value>) -> Vec<ModelProfile> {
+    let user_profiles = get_user_profiles_from_config(config);
+
+    let mut profiles: Vec<ModelProfile> = builtin_profiles()
+        .into_iter()
+        .filter(|profile| !user_profiles.iter().any(|user| user.id == profile.id))
+        .collect();
+
+    profiles.extend(user_profiles);
+    profiles
+}
+
+pub fn find_profile(config: &Map<String, Value>, id: &str) -> Option<ModelProfile> {
+    all_profiles(config)
+        .into_iter()
+        .find(|profile| profile.id == id)
+}
+
+static ACTIVE_PROFILE: OnceLock<RwLock<Option<ModelProfile>>> = OnceLock::new();
+
+fn active() -> &'static RwLock<Option<ModelProfile>> {
+    ACTIVE_PROFILE.get_or_init(|| RwLock::new(None))
+}
+
+pub fn active_model_profile() -> Option<ModelProfile> {
+    active().read().ok().and_then(|profile| profile.clone())
+}
+
+pub fn set_active_model_profile(profile: Option<ModelProfile>) {
+    if let Ok(mut active) = active().write() {
+        *active = profile;
+    }
+}
+
+/// Usage figures for `token_count` against the active profile, if any.
+pub fn context_usage(token_count: usize) -> Option<ContextUsage> {
+    active_model_profile().map(|profile| profile.usage_for(token_count))
+}
+
+/// Reads the selected profile into memory. Called once when the app starts.
+pub fn load_active_model_profile<R: tauri::Runtime>(app: &AppHandle<R>) {
+    let Ok(store) = open_store(app) else {
+        return;
+    };
+
+    let config = store
+        .get(StoreCategoryKey::CONFIG)
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_default();
+
+    store.close_resource();
+
+    let profile = config
+        .get(StoreConfigKey::MODEL_PROFILE)
+        .and_then(|v| v.as_str())
+        .and_then(|id| find_profile(&config, id));
+
+    set_active_model_profile(profile);
+}
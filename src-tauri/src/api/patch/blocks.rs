@@ -0,0 +1,569 @@
+use git2::Patch;
+use serde::Serialize;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
+
+use crate::api::{
+    patch::lib::{resolve_project_path, FileAction, PlannedChange},
+    tree::index::TreeIndex,
+};
+
+const SEARCH_MARKER: &str = "SEARCH";
+const REPLACE_MARKER: &str = "REPLACE";
+const MAX_PATH_LENGTH: usize = 260;
+const MAX_SUGGESTED_PATHS: usize = 3;
+const SNIPPET_SKIPPED: &str =
+    "Skipped: the block may be part of the file. Name the file in the fence info to replace it whole";
+
+/// Words in a path heading that say the following fence holds the whole file
+/// rather than a snippet of it.
+const WHOLE_FILE_WORDS: &[&str] = &[
+    "full",
+    "whole",
+    "complete",
+    "entire",
+    "new file",
+    "updated file",
+    "create",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EditBlock {
+    WholeFile {
+        path: String,
+        contents: String,
+    },
+    SearchReplace {
+        path: String,
+        search: String,
+        replace: String,
+    },
+    /// A fence under a path heading that doesn't say it holds the whole file.
+    /// It may be a partial snippet, so it's reported but never written.
+    Snippet {
+        path: String,
+    },
+}
+
+impl EditBlock {
+    fn path(&self) -> &str {
+        match self {
+            EditBlock::WholeFile { path, .. }
+            | EditBlock::SearchReplace { path, .. }
+            | EditBlock::Snippet { path } => path,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum EditBlockKind {
+    WholeFile,
+    SearchReplace,
+    Snippet,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EditBlockReport {
+    /// Position of the block in the response, shared across all files.
+    pub index: usize,
+    pub kind: EditBlockKind,
+    pub applied: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileEditReport {
+    pub path: String,
+    pub requested_path: String,
+    pub action: FileAction,
+    pub lines_added: usize,
+    pub lines_deleted: usize,
+    pub preview_diff: String,
+    pub blocks: Vec<EditBlockReport>,
+    pub error: Option<String>,
+}
+
+fn looks_like_path(candidate: &str) -> bool {
+    if candidate.is_empty()
+        || candidate.len() > MAX_PATH_LENGTH
+        || candidate.contains(char::is_whitespace)
+        || candidate.contains("://")
+    {
+        return false;
+    }
+
+    let file_name = candidate.rsplit(['/', '\\']).next().unwrap_or(candidate);
+
+    candidate.contains('/')
+        || file_name
+            .rsplit_once('.')
+            .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty())
+}
+
+/// Reads a path out of a heading line such as `src/main.rs`, `File: src/main.rs`,
+/// `### src/main.rs` or ``**`src/main.rs`**``, or a prose line ending with a
+/// colon that mentions a single backticked path.
+fn path_from_heading(line: &str) -> Option<String> {
+    let mut text = line.trim();
+
+    for prefix in ["#", "*", "-", ">"] {
+        text = text.trim_start_matches(prefix).trim_start();
+    }
+
+    for label in ["File:", "file:", "Path:", "path:", "Filename:", "filename:"] {
+        if let Some(rest) = text.strip_prefix(label) {
+            text = rest.trim();
+        }
+    }
+
+    let bare = text
+        .trim_end_matches(':')
+        .trim_matches(|c| c == '*' || c == '`' || c == '"' || c == '\'');
+
+    if looks_like_path(bare) {
+        return Some(bare.to_string());
+    }
+
+    if !text.ends_with(':') {
+        return None;
+    }
+
+    let quoted: Vec<&str> = text
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .filter(|part| looks_like_path(part))
+        .collect();
+
+    match quoted.as_slice() {
+        [path] => Some(path.to_string()),
+        _ => None,
+    }
+}
+
+fn says_whole_file(heading: &str) -> bool {
+    let heading = heading.to_lowercase();
+    WHOLE_FILE_WORDS.iter().any(|word| heading.contains(word))
+}
+
+/// Reads the language and path from a fence info string such as `rust`,
+/// `src/main.rs`, `rust src/main.rs` or `rust:src/main.rs`.
+fn parse_fence_info(info: &str) -> (Option<String>, Option<String>) {
+    let mut language = None;
+    let mut path = None;
+
+    for token in info.split(|c: char| c.is_whitespace() || c == ':') {
+        let token = token
+            .trim_start_matches("title=")
+            .trim_start_matches("path=")
+            .trim_matches('"');
+
+        if token.is_empty() {
+            continue;
+        }
+
+        if looks_like_path(token) {
+            path = Some(token.to_string());
+        } else if language.is_none() {
+            language = Some(token.to_lowercase());
+        }
+    }
+
+    (language, path)
+}
+
+fn fence_marker(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = trimmed.chars().take_while(|c| *c == fence_char).count();
+
+    if length < 3 {
+        return None;
+    }
+
+    Some((fence_char.to_string().repeat(length), &trimmed[length..]))
+}
+
+fn is_marker(line: &str, marker_char: char, word: &str) -> bool {
+    let trimmed = line.trim();
+    let length = trimmed.chars().take_while(|c| *c == marker_char).count();
+
+    (5..=9).contains(&length) && trimmed[length..].trim() == word
+}
+
+fn join_block_lines(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// Reads the SEARCH/REPLACE block whose opening marker is at `idx`, returning
+/// both sides and the line after the closing marker.
+fn parse_search_replace_block(
+    lines: &[&str],
+    idx: usize,
+    end: usize,
+) -> Option<(String, String, usize)> {
+    let search_start = idx + 1;
+    let divider = (search_start..end).find(|&i| is_marker(lines[i], '=', ""))?;
+    let replace_end = (divider + 1..end).find(|&i| is_marker(lines[i], '>', REPLACE_MARKER))?;
+
+    Some((
+        join_block_lines(&lines[search_start..divider]),
+        join_block_lines(&lines[divider + 1..replace_end]),
+        replace_end + 1,
+    ))
+}
+
+/// Parses every SEARCH/REPLACE block in a fence body. A path heading right
+/// before a block switches the file the following blocks apply to.
+fn parse_fenced_search_replace(
+    lines: &[&str],
+    mut idx: usize,
+    end: usize,
+    mut path: Option<String>,
+    blocks: &mut Vec<EditBlock>,
+) {
+    while idx < end {
+        if !is_marker(lines[idx], '<', SEARCH_MARKER) {
+            if let Some(heading) = path_from_heading(lines[idx]) {
+                path = Some(heading);
+            }
+            idx += 1;
+            continue;
+        }
+
+        let Some((search, replace, next)) = parse_search_replace_block(lines, idx, end) else {
+            return;
+        };
+
+        if let Some(path) = &path {
+            blocks.push(EditBlock::SearchReplace {
+                path: path.clone(),
+                search,
+                replace,
+            });
+        }
+
+        idx = next;
+    }
+}
+
+/// Extracts whole-file blocks and SEARCH/REPLACE blocks from a model response.
+/// Fenced blocks only count as whole files when the path is given in the fence
+/// info, or on the line before it along with words like "full file". Other
+/// fences under a path heading are kept as snippets; diff fences are left to
+/// the unified-diff parser.
+pub fn parse_edit_blocks(text: &str) -> Vec<EditBlock> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut blocks = Vec::new();
+    // The path of the last heading and whether it said the whole file follows.
+    let mut pending_path: Option<(String, bool)> = None;
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let line = lines[idx];
+
+        if let Some((marker, info)) = fence_marker(line) {
+            let (language, info_path) = parse_fence_info(info);
+            let body_start = idx + 1;
+            let body_end = (body_start..lines.len())
+                .find(|&i| lines[i].trim() == marker)
+                .unwrap_or(lines.len());
+            let body = &lines[body_start..body_end];
+            let path = info_path
+                .map(|path| (path, true))
+                .or_else(|| pending_path.take());
+
+            if body.iter().any(|l| is_marker(l, '<', SEARCH_MARKER)) {
+                let path = path.map(|(path, _)| path);
+                parse_fenced_search_replace(&lines, body_start, body_end, path, &mut blocks);
+            } else if matches!(language.as_deref(), Some("diff" | "patch")) {
+                // Handled by `parse_unified_diff`.
+            } else if let Some((path, whole_file)) = path {
+                blocks.push(if whole_file {
+                    EditBlock::WholeFile {
+                        path,
+                        contents: join_block_lines(body),
+                    }
+                } else {
+                    EditBlock::Snippet { path }
+                });
+            }
+
+            pending_path = None;
+            idx = body_end + 1;
+            continue;
+        }
+
+        if is_marker(line, '<', SEARCH_MARKER) {
+            if let Some((search, replace, next)) =
+                parse_search_replace_block(&lines, idx, lines.len())
+            {
+                if let Some((path, _)) = &pending_path {
+                    blocks.push(EditBlock::SearchReplace {
+                        path: path.clone(),
+                        search,
+                        replace,
+                    });
+                }
+
+                idx = next;
+                continue;
+            }
+        }
+
+        if let Some(path) = path_from_heading(line) {
+            pending_path = Some((path, says_whole_file(line)));
+        } else if !line.trim().is_empty() {
+            pending_path = None;
+        }
+
+        idx += 1;
+    }
+
+    blocks
+}
+
+/// Maps a path from a model response onto the project. The path must be
+/// relative to the root: one that matches no file but ends like existing ones,
+/// such as `main.rs` for `src/main.rs`, is refused with those files named
+/// rather than guessed at or created next to them.
+pub fn resolve_block_path(
+    root: &str,
+    path: &str,
+    tree_index: Option<&TreeIndex>,
+) -> Result<PathBuf, String> {
+    let path = path.trim_start_matches("./");
+    let resolved = resolve_project_path(root, path)
+        .ok_or_else(|| format!("Path is outside the project: {}", path))?;
+
+    let Some(tree_index) = tree_index else {
+        return Ok(resolved);
+    };
+
+    if tree_index
+        .nodes
+        .contains_key(resolved.to_string_lossy().as_ref())
+    {
+        return Ok(resolved);
+    }
+
+    let suffix = format!(
+        "{}{}",
+        MAIN_SEPARATOR,
+        path.replace('/', MAIN_SEPARATOR_STR)
+    );
+    let mut candidates: Vec<String> = tree_index
+        .nodes
+        .values()
+        .filter(|node| node.node_type == "file" && node.id.ends_with(&suffix))
+        .map(|node| relative_display(root, Path::new(&node.id)))
+        .collect();
+
+    if candidates.is_empty() {
+        return Ok(resolved);
+    }
+
+    candidates.sort();
+    candidates.truncate(MAX_SUGGESTED_PATHS);
+
+    Err(format!(
+        "{} isn't a path in the project, did you mean {}?",
+        path,
+        candidates.join(" or ")
+    ))
+}
+
+fn relative_display(root: &str, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Applies one SEARCH/REPLACE block to `contents`, replacing its only exact
+/// occurrence. SEARCH text found more than once is refused, since there is no
+/// telling which one was meant. An empty SEARCH on an empty file creates it.
+fn apply_search_replace(contents: &str, search: &str, replace: &str) -> Result<String, String> {
+    let eol = if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let search = search.replace('\n', eol);
+    let replace = replace.replace('\n', eol);
+
+    if search.is_empty() {
+        return if contents.is_empty() {
+            Ok(replace)
+        } else {
+            Err("SEARCH is empty but the file already has content".to_string())
+        };
+    }
+
+    let mut matches = contents.match_indices(&search).map(|(at, _)| at);
+
+    match (matches.next(), matches.count()) {
+        (Some(at), 0) => Ok(format!(
+            "{}{}{}",
+            &contents[..at],
+            replace,
+            &contents[at + search.len()..]
+        )),
+        (Some(_), others) => Err(format!(
+            "SEARCH text matches {} places in the file, add lines around it to pick one",
+            others + 1
+        )),
+        (None, _) => Err("SEARCH text not found in the file".to_string()),
+    }
+}
+
+fn preview_diff(display_path: &str, old: &str, new: &str) -> (String, usize, usize) {
+    let path = Path::new(display_path);
+
+    Patch::from_buffers(old.as_bytes(), Some(path), new.as_bytes(), Some(path), None)
+        .and_then(|mut patch| {
+            let (_, added, deleted) = patch.line_stats()?;
+            let text = patch.to_buf()?.as_str().unwrap_or_default().to_string();
+            Ok((text, added, deleted))
+        })
+        .unwrap_or_default()
+}
+
+type BlocksForFile<'a> = (String, Result<PathBuf, String>, Vec<(usize, &'a EditBlock)>);
+
+/// Applies the blocks to the files they target, in memory, and builds a report
+/// with a preview diff per file. A file's change is only returned when every
+/// block for it applied; snippets are reported as skipped and never written.
+pub fn plan_edit_blocks(
+    root: &str,
+    blocks: &[EditBlock],
+    tree_index: Option<&TreeIndex>,
+) -> Vec<(FileEditReport, Option<PlannedChange>)> {
+    let mut files: Vec<BlocksForFile> = Vec::new();
+
+    for (index, block) in blocks.iter().enumerate() {
+        let resolved = resolve_block_path(root, block.path(), tree_index);
+
+        match files.iter_mut().find(|(requested, path, _)| {
+            (path.is_ok() && *path == resolved) || *requested == block.path()
+        }) {
+            Some((_, _, file_blocks)) => file_blocks.push((index, block)),
+            None => files.push((block.path().to_string(), resolved, vec![(index, block)])),
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|(requested_path, resolved, file_blocks)| {
+            let kind_of = |block: &EditBlock| match block {
+                EditBlock::WholeFile { .. } => EditBlockKind::WholeFile,
+                EditBlock::SearchReplace { .. } => EditBlockKind::SearchReplace,
+                EditBlock::Snippet { .. } => EditBlockKind::Snippet,
+            };
+
+            let path = match resolved {
+                Ok(path) => path,
+                Err(error) => {
+                    return (
+                        FileEditReport {
+                            path: requested_path.clone(),
+                            requested_path,
+                            action: FileAction::Modify,
+                            lines_added: 0,
+                            lines_deleted: 0,
+                            preview_diff: String::new(),
+                            blocks: file_blocks
+                                .iter()
+                                .map(|(index, block)| EditBlockReport {
+                                    index: *index,
+                                    kind: kind_of(block),
+                                    applied: false,
+                                    error: Some(error.clone()),
+                                })
+                                .collect(),
+                            error: Some(error),
+                        },
+                        None,
+                    )
+                }
+            };
+
+            let original = std::fs::read(&path)
+                .ok()
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+            let action = if original.is_some() {
+                FileAction::Modify
+            } else {
+                FileAction::Create
+            };
+            let original = original.unwrap_or_default();
+
+            let mut contents = original.clone();
+            let mut reports = Vec::new();
+            let mut skipped = 0;
+
+            for (index, block) in file_blocks {
+                let result = match block {
+                    EditBlock::WholeFile { contents, .. } => Ok(contents.clone()),
+                    EditBlock::SearchReplace {
+                        search, replace, ..
+                    } => apply_search_replace(&contents, search, replace),
+                    EditBlock::Snippet { .. } => {
+                        skipped += 1;
+                        Err(SNIPPET_SKIPPED.to_string())
+                    }
+                };
+
+                let error = match result {
+                    Ok(updated) => {
+                        contents = updated;
+                        None
+                    }
+                    Err(message) => Some(message),
+                };
+
+                reports.push(EditBlockReport {
+                    index,
+                    kind: kind_of(block),
+                    applied: error.is_none(),
+                    error,
+                });
+            }
+
+            let display_path = relative_display(root, &path);
+            let (preview_diff, lines_added, lines_deleted) =
+                preview_diff(&display_path, &original, &contents);
+
+            let failed = reports.iter().filter(|r| !r.applied).count() - skipped;
+            let error = (failed > 0).then(|| {
+                format!(
+                    "{} of {} blocks did not match the file",
+                    failed,
+                    reports.len() - skipped
+                )
+            });
+
+            let writes = reports.len() > skipped;
+            let change = (writes && error.is_none()).then(|| PlannedChange::Write {
+                path: path.clone(),
+                contents,
+            });
+
+            (
+                FileEditReport {
+                    path: display_path,
+                    requested_path,
+                    action,
+                    lines_added,
+                    lines_deleted,
+                    preview_diff,
+                    blocks: reports,
+                    error,
+                },
+                change,
+            )
+        })
+        .collect()
+}
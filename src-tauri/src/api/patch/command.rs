@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use tauri::{AppHandle, Wry};
+
+use crate::{
+    api::{
+        git::event::emit_git_status_event,
+        patch::{
+            blocks::{parse_edit_blocks, plan_edit_blocks, FileEditReport},
+            lib::{parse_unified_diff, plan_patches, write_changes_atomically, FilePatchReport},
+        },
+        tree::{cache::cache, index::ensure_index},
+    },
+    errors::{codes, ApplicationError},
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PatchReport {
+    pub(crate) applied: bool,
+    pub(crate) can_apply: bool,
+    pub(crate) files: Vec<FilePatchReport>,
+}
+
+/// Parses unified diffs out of `text` and checks them against the working
+/// tree. Nothing is written unless `confirm` is set and every hunk matched.
+#[tauri::command]
+pub(crate) fn apply_patch_from_text(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    text: String,
+    confirm: Option<bool>,
+) -> Result<PatchReport, ApplicationError> {
+    let patches = parse_unified_diff(&text);
+
+    if patches.is_empty() {
+        return Err(ApplicationError {
+            code: codes::PATCH_PARSE_ERROR,
+            message: Some("No unified diff found in the text".to_string()),
+        });
+    }
+
+    let (files, changes) = plan_patches(&directory_path, &patches);
+    let can_apply = files.iter().all(|file| file.error.is_none());
+
+    if !confirm.unwrap_or(false) || !can_apply {
+        return Ok(PatchReport {
+            applied: false,
+            can_apply,
+            files,
+        });
+    }
+
+    write_changes_atomically(&changes).map_err(|message| ApplicationError {
+        code: codes::FILE_WRITE_ERROR,
+        message: Some(message),
+    })?;
+
+    emit_git_status_event(app, directory_path);
+
+    Ok(PatchReport {
+        applied: true,
+        can_apply,
+        files,
+    })
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EditBlocksReport {
+    pub(crate) applied: bool,
+    pub(crate) can_apply: bool,
+    pub(crate) files: Vec<FileEditReport>,
+}
+
+/// Parses whole-file and SEARCH/REPLACE blocks out of `text` and previews them
+/// per file. With `confirm`, the files in `accepted_paths` (all files when
+/// omitted) are written, as long as every block for them matched.
+#[tauri::command]
+pub(crate) fn apply_edit_blocks_from_text(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    text: String,
+    accepted_paths: Option<Vec<String>>,
+    confirm: Option<bool>,
+) -> Result<EditBlocksReport, ApplicationError> {
+    let blocks = parse_edit_blocks(&text);
+
+    if blocks.is_empty() {
+        return Err(ApplicationError {
+            code: codes::PATCH_PARSE_ERROR,
+            message: Some("No file or SEARCH/REPLACE blocks found in the text".to_string()),
+        });
+    }
+
+    ensure_index(&directory_path, false)?;
+    let planned = {
+        let guard = cache().read().expect("cache read poisoned");
+        plan_edit_blocks(&directory_path, &blocks, guard.get(&directory_path))
+    };
+
+    let accepted: Option<HashSet<String>> = accepted_paths.map(|paths| paths.into_iter().collect());
+    let is_accepted = |report: &FileEditReport| {
+        accepted
+            .as_ref()
+            .is_none_or(|paths| paths.contains(&report.path))
+    };
+
+    let can_apply = planned
+        .iter()
+        .filter(|(report, _)| is_accepted(report))
+        .all(|(report, _)| report.error.is_none());
+
+    let changes: Vec<_> = planned
+        .iter()
+        .filter(|(report, _)| is_accepted(report))
+        .filter_map(|(_, change)| change.as_ref())
+        .collect();
+    let should_write = confirm.unwrap_or(false) && can_apply && !changes.is_empty();
+
+    if should_write {
+        write_changes_atomically(changes).map_err(|message| ApplicationError {
+            code: codes::FILE_WRITE_ERROR,
+            message: Some(message),
+        })?;
+
+        emit_git_status_event(app, directory_path);
+    }
+
+    Ok(EditBlocksReport {
+        applied: should_write,
+        can_apply,
+        files: planned.into_iter().map(|(report, _)| report).collect(),
+    })
+}
@@ -0,0 +1,751 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub lines: Vec<HunkLine>,
+    /// The file had no newline at the end before the patch.
+    pub old_no_newline_at_end: bool,
+    /// The file has no newline at the end after the patch.
+    pub new_no_newline_at_end: bool,
+}
+
+impl Hunk {
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Removed(text) => Some(text.as_str()),
+                HunkLine::Added(_) => None,
+            })
+            .collect()
+    }
+
+    /// Records a `\ No newline at end of file` marker against the side of
+    /// the line it follows.
+    fn mark_no_newline(&mut self) {
+        match self.lines.last() {
+            Some(HunkLine::Removed(_)) => self.old_no_newline_at_end = true,
+            Some(HunkLine::Added(_)) => self.new_no_newline_at_end = true,
+            Some(HunkLine::Context(_)) => {
+                self.old_no_newline_at_end = true;
+                self.new_no_newline_at_end = true;
+            }
+            None => {}
+        }
+    }
+
+    fn new_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Added(text) => Some(text.as_str()),
+                HunkLine::Removed(_) => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FilePatch {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FileAction {
+    Modify,
+    Create,
+    Delete,
+    Rename,
+}
+
+impl FilePatch {
+    pub fn action(&self) -> FileAction {
+        match (&self.old_path, &self.new_path) {
+            (None, Some(_)) => FileAction::Create,
+            (Some(_), None) => FileAction::Delete,
+            (Some(old), Some(new)) if old != new => FileAction::Rename,
+            _ => FileAction::Modify,
+        }
+    }
+
+    pub fn display_path(&self) -> String {
+        self.new_path
+            .clone()
+            .or_else(|| self.old_path.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum HunkStatus {
+    /// Matched at the line given in the hunk header.
+    Exact,
+    /// Matched, but at a different line than the header says.
+    Offset,
+    /// Matched only when ignoring trailing whitespace.
+    Whitespace,
+    /// The removed and context lines were not found in the file.
+    Failed,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HunkReport {
+    pub index: usize,
+    pub expected_line: usize,
+    pub applied_line: Option<usize>,
+    pub offset: isize,
+    pub status: HunkStatus,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FilePatchReport {
+    pub path: String,
+    pub old_path: Option<String>,
+    pub action: FileAction,
+    pub lines_added: usize,
+    pub lines_deleted: usize,
+    pub hunks: Vec<HunkReport>,
+    pub error: Option<String>,
+}
+
+fn strip_diff_prefix(path: &str) -> Option<String> {
+    let path = path.split('\t').next().unwrap_or(path).trim();
+
+    if path == "/dev/null" {
+        return None;
+    }
+
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+
+    Some(path.trim_matches('"').to_string())
+}
+
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let rest = line.strip_prefix("@@ ")?;
+    let end = rest.find(" @@")?;
+    let mut ranges = rest[..end].split_whitespace();
+
+    let old = ranges.next()?.strip_prefix('-')?;
+    let new = ranges.next()?.strip_prefix('+')?;
+
+    // A range without a length covers one line, as in `@@ -3 +3 @@`.
+    let parse_range = |range: &str| -> (usize, usize) {
+        let mut parts = range.splitn(2, ',');
+        let start = parts.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+        let len = parts.next().and_then(|s| s.parse().ok()).unwrap_or(1);
+        (start, len)
+    };
+
+    let (old_start, old_len) = parse_range(old);
+    let (_, new_len) = parse_range(new);
+
+    Some((old_start, old_len, new_len))
+}
+
+/// Extracts every file patch from free-form text such as a model response.
+/// Prose and code fences around the diffs are skipped.
+pub fn parse_unified_diff(text: &str) -> Vec<FilePatch> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut current: Option<FilePatch> = None;
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let line = lines[idx];
+
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            if let Some(patch) = current.take() {
+                patches.push(patch);
+            }
+
+            let mut paths = rest.splitn(2, " b/");
+            let old = paths.next().and_then(strip_diff_prefix);
+            let new = paths.next().map(|p| p.to_string());
+
+            current = Some(FilePatch {
+                old_path: old,
+                new_path: new,
+                hunks: Vec::new(),
+            });
+            idx += 1;
+            continue;
+        }
+
+        if line.starts_with("--- ") && lines.get(idx + 1).is_some_and(|l| l.starts_with("+++ ")) {
+            let old_path = strip_diff_prefix(&line[4..]);
+            let new_path = strip_diff_prefix(&lines[idx + 1][4..]);
+
+            let reuse_git_header = current.as_ref().is_some_and(|patch| patch.hunks.is_empty());
+
+            if !reuse_git_header {
+                if let Some(patch) = current.take() {
+                    patches.push(patch);
+                }
+            }
+
+            let patch = current.get_or_insert_with(FilePatch::default);
+            patch.old_path = old_path;
+            patch.new_path = new_path;
+
+            idx += 2;
+            continue;
+        }
+
+        if let Some(patch) = current.as_mut() {
+            if let Some(from) = line.strip_prefix("rename from ") {
+                patch.old_path = Some(from.to_string());
+            } else if let Some(to) = line.strip_prefix("rename to ") {
+                patch.new_path = Some(to.to_string());
+            } else if line.starts_with("new file mode") {
+                patch.old_path = None;
+            } else if line.starts_with("deleted file mode") {
+                patch.new_path = None;
+            }
+        }
+
+        if let Some((old_start, old_len, new_len)) = parse_hunk_header(line) {
+            let mut hunk = Hunk {
+                old_start,
+                ..Default::default()
+            };
+            let mut old_remaining = old_len;
+            let mut new_remaining = new_len;
+            idx += 1;
+
+            while idx < lines.len() && (old_remaining > 0 || new_remaining > 0) {
+                let hunk_line = lines[idx];
+
+                if let Some(text) = hunk_line.strip_prefix('+') {
+                    hunk.lines.push(HunkLine::Added(text.to_string()));
+                    new_remaining = new_remaining.saturating_sub(1);
+                } else if let Some(text) = hunk_line.strip_prefix('-') {
+                    hunk.lines.push(HunkLine::Removed(text.to_string()));
+                    old_remaining = old_remaining.saturating_sub(1);
+                } else if let Some(text) = hunk_line.strip_prefix(' ') {
+                    hunk.lines.push(HunkLine::Context(text.to_string()));
+                    old_remaining = old_remaining.saturating_sub(1);
+                    new_remaining = new_remaining.saturating_sub(1);
+                } else if hunk_line.is_empty() && old_remaining > 0 && new_remaining > 0 {
+                    hunk.lines.push(HunkLine::Context(String::new()));
+                    old_remaining -= 1;
+                    new_remaining -= 1;
+                } else if hunk_line.starts_with('\\') {
+                    hunk.mark_no_newline();
+                } else {
+                    break;
+                }
+
+                idx += 1;
+            }
+
+            if lines.get(idx).is_some_and(|l| l.starts_with('\\')) {
+                hunk.mark_no_newline();
+                idx += 1;
+            }
+
+            current
+                .get_or_insert_with(FilePatch::default)
+                .hunks
+                .push(hunk);
+            continue;
+        }
+
+        idx += 1;
+    }
+
+    if let Some(patch) = current.take() {
+        patches.push(patch);
+    }
+
+    patches
+        .into_iter()
+        .filter(|patch| {
+            !patch.hunks.is_empty()
+                || patch.action() == FileAction::Delete
+                || patch.action() == FileAction::Rename
+        })
+        .collect()
+}
+
+/// Resolves a path from a patch or model response against the project root,
+/// refusing absolute paths, anything that climbs out of the root and paths
+/// that leave it through a symlink.
+pub fn resolve_project_path(root: &str, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative);
+
+    let root_path = Path::new(root);
+    let relative = relative.strip_prefix(root_path).unwrap_or(relative);
+
+    let mut resolved = PathBuf::from(root);
+
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    if resolved == root_path || !stays_in_root(root_path, &resolved) {
+        return None;
+    }
+
+    Some(resolved)
+}
+
+/// Whether the deepest part of `path` that exists, symlinks followed, is
+/// still under `root`. A dangling symlink doesn't count as staying inside.
+fn stays_in_root(root: &Path, path: &Path) -> bool {
+    let Ok(root) = root.canonicalize() else {
+        return false;
+    };
+
+    path.ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok())
+        .and_then(|existing| existing.canonicalize().ok())
+        .is_some_and(|existing| existing.starts_with(&root))
+}
+
+/// A file split into lines, remembering its line ending and whether it ends
+/// with a newline so it can be written back unchanged apart from the edits.
+pub struct TextLines {
+    pub lines: Vec<String>,
+    pub eol: &'static str,
+    pub trailing_newline: bool,
+}
+
+impl TextLines {
+    pub fn parse(text: &str) -> Self {
+        let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let trailing_newline = text.is_empty() || text.ends_with('\n');
+
+        Self {
+            lines: text
+                .lines()
+                .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+                .collect(),
+            eol,
+            trailing_newline,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = self.lines.join(self.eol);
+
+        if self.trailing_newline && !self.lines.is_empty() {
+            out.push_str(self.eol);
+        }
+
+        out
+    }
+}
+
+fn block_matches(lines: &[String], at: usize, block: &[&str], loose: bool) -> bool {
+    if at + block.len() > lines.len() {
+        return false;
+    }
+
+    block.iter().enumerate().all(|(i, expected)| {
+        if loose {
+            lines[at + i].trim_end() == expected.trim_end()
+        } else {
+            lines[at + i] == *expected
+        }
+    })
+}
+
+/// Finds `block` in `lines` at or after `min`, preferring the position closest
+/// to `expected`.
+fn find_block(
+    lines: &[String],
+    block: &[&str],
+    expected: usize,
+    min: usize,
+    loose: bool,
+) -> Option<usize> {
+    let max = lines.len().saturating_sub(block.len());
+    let expected = expected.clamp(min, max.max(min));
+
+    for distance in 0..=lines.len() {
+        let after = expected + distance;
+        if after <= max && block_matches(lines, after, block, loose) {
+            return Some(after);
+        }
+
+        if distance > 0 && expected >= distance + min {
+            let before = expected - distance;
+            if block_matches(lines, before, block, loose) {
+                return Some(before);
+            }
+        }
+
+        if after > max && expected < distance + min {
+            break;
+        }
+    }
+
+    None
+}
+
+/// Applies the hunks to `text` in order. Each hunk is looked up at its header
+/// line first, then at the nearest offset, then ignoring trailing whitespace.
+/// Returns the new text only when every hunk matched.
+pub fn apply_hunks(text: &str, hunks: &[Hunk]) -> (Option<String>, Vec<HunkReport>) {
+    let mut file = TextLines::parse(text);
+    let mut reports = Vec::new();
+    let mut delta: isize = 0;
+    let mut min = 0;
+    let mut failed = false;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let old_lines = hunk.old_lines();
+        let new_lines = hunk.new_lines();
+
+        let header_line = if old_lines.is_empty() {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = (header_line as isize + delta).max(0) as usize;
+
+        let (found, loose) = if old_lines.is_empty() {
+            (Some(expected.clamp(min, file.lines.len())), false)
+        } else {
+            match find_block(&file.lines, &old_lines, expected, min, false) {
+                Some(at) => (Some(at), false),
+                None => (
+                    find_block(&file.lines, &old_lines, expected, min, true),
+                    true,
+                ),
+            }
+        };
+
+        let Some(at) = found else {
+            failed = true;
+            reports.push(HunkReport {
+                index,
+                expected_line: expected + 1,
+                applied_line: None,
+                offset: 0,
+                status: HunkStatus::Failed,
+            });
+            continue;
+        };
+
+        let offset = at as isize - expected as isize;
+        let status = if loose {
+            HunkStatus::Whitespace
+        } else if offset != 0 {
+            HunkStatus::Offset
+        } else {
+            HunkStatus::Exact
+        };
+
+        file.lines.splice(
+            at..at + old_lines.len(),
+            new_lines.iter().map(|line| line.to_string()),
+        );
+
+        if at + new_lines.len() == file.lines.len() {
+            if hunk.new_no_newline_at_end {
+                file.trailing_newline = false;
+            } else if hunk.old_no_newline_at_end {
+                file.trailing_newline = true;
+            }
+        }
+
+        delta += offset + new_lines.len() as isize - old_lines.len() as isize;
+        min = at + new_lines.len();
+
+        reports.push(HunkReport {
+            index,
+            expected_line: expected + 1,
+            applied_line: Some(at + 1),
+            offset,
+            status,
+        });
+    }
+
+    if failed {
+        (None, reports)
+    } else {
+        (Some(file.render()), reports)
+    }
+}
+
+pub fn count_changed_lines(hunks: &[Hunk]) -> (usize, usize) {
+    hunks.iter().flat_map(|hunk| hunk.lines.iter()).fold(
+        (0, 0),
+        |(added, deleted), line| match line {
+            HunkLine::Added(_) => (added + 1, deleted),
+            HunkLine::Removed(_) => (added, deleted + 1),
+            HunkLine::Context(_) => (added, deleted),
+        },
+    )
+}
+
+pub enum PlannedChange {
+    Write { path: PathBuf, contents: String },
+    Remove { path: PathBuf },
+}
+
+impl PlannedChange {
+    fn path(&self) -> &Path {
+        match self {
+            PlannedChange::Write { path, .. } | PlannedChange::Remove { path } => path,
+        }
+    }
+}
+
+fn failed_report(patch: &FilePatch, error: String) -> FilePatchReport {
+    let (lines_added, lines_deleted) = count_changed_lines(&patch.hunks);
+
+    FilePatchReport {
+        path: patch.display_path(),
+        old_path: patch.old_path.clone(),
+        action: patch.action(),
+        lines_added,
+        lines_deleted,
+        hunks: Vec::new(),
+        error: Some(error),
+    }
+}
+
+/// What the patches planned so far leave at each path they touched, in the
+/// order the paths were first touched. `None` marks a removed file.
+#[derive(Default)]
+struct PlannedFiles {
+    order: Vec<PathBuf>,
+    contents: HashMap<PathBuf, Option<String>>,
+}
+
+impl PlannedFiles {
+    /// The contents an earlier patch left at `path`, `Some(None)` when it
+    /// removed the file, `None` when no patch touched it.
+    fn get(&self, path: &Path) -> Option<Option<&str>> {
+        self.contents.get(path).map(|contents| contents.as_deref())
+    }
+
+    fn set(&mut self, path: &Path, contents: Option<String>) {
+        if !self.contents.contains_key(path) {
+            self.order.push(path.to_path_buf());
+        }
+        self.contents.insert(path.to_path_buf(), contents);
+    }
+
+    /// One change per path. Removals of files that never existed on disk, such
+    /// as a file created and then deleted by the same response, are dropped.
+    fn into_changes(mut self) -> Vec<PlannedChange> {
+        self.order
+            .into_iter()
+            .filter_map(|path| match self.contents.remove(&path).flatten() {
+                Some(contents) => Some(PlannedChange::Write { path, contents }),
+                None => path.exists().then_some(PlannedChange::Remove { path }),
+            })
+            .collect()
+    }
+}
+
+/// Checks every patch against the working tree without touching it. Patches
+/// for a path an earlier patch touched apply on top of its result, so several
+/// diffs of one file add up to a single write. The returned changes are only
+/// meant to be written when no report has an error.
+pub fn plan_patches(
+    root: &str,
+    patches: &[FilePatch],
+) -> (Vec<FilePatchReport>, Vec<PlannedChange>) {
+    let mut reports = Vec::new();
+    let mut planned = PlannedFiles::default();
+
+    for patch in patches {
+        let action = patch.action();
+
+        let old_path = match &patch.old_path {
+            Some(path) => match resolve_project_path(root, path) {
+                Some(resolved) => Some(resolved),
+                None => {
+                    reports.push(failed_report(
+                        patch,
+                        format!("Path is outside the project: {}", path),
+                    ));
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        let new_path = match &patch.new_path {
+            Some(path) => match resolve_project_path(root, path) {
+                Some(resolved) => Some(resolved),
+                None => {
+                    reports.push(failed_report(
+                        patch,
+                        format!("Path is outside the project: {}", path),
+                    ));
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        let original = match &old_path {
+            Some(path) => match planned.get(path) {
+                Some(Some(contents)) => contents.to_string(),
+                Some(None) => {
+                    reports.push(failed_report(
+                        patch,
+                        format!("File was removed by an earlier patch: {}", path.display()),
+                    ));
+                    continue;
+                }
+                None => match std::fs::read(path) {
+                    Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                    Err(_) => {
+                        reports.push(failed_report(
+                            patch,
+                            format!("File not found: {}", path.display()),
+                        ));
+                        continue;
+                    }
+                },
+            },
+            None => {
+                let exists = new_path
+                    .as_ref()
+                    .is_some_and(|path| match planned.get(path) {
+                        Some(contents) => contents.is_some(),
+                        None => path.exists(),
+                    });
+
+                if exists {
+                    reports.push(failed_report(patch, "File already exists".to_string()));
+                    continue;
+                }
+                String::new()
+            }
+        };
+
+        let (result, hunks) = apply_hunks(&original, &patch.hunks);
+        let (lines_added, lines_deleted) = count_changed_lines(&patch.hunks);
+
+        let error = if result.is_none() {
+            Some("Some hunks did not match the file".to_string())
+        } else {
+            None
+        };
+
+        if let Some(contents) = result {
+            match (action, &old_path, &new_path) {
+                (FileAction::Delete, Some(old), _) => planned.set(old, None),
+                (FileAction::Rename, Some(old), Some(new)) => {
+                    planned.set(new, Some(contents));
+                    planned.set(old, None);
+                }
+                (_, _, Some(new)) => planned.set(new, Some(contents)),
+                _ => {}
+            }
+        }
+
+        reports.push(FilePatchReport {
+            path: patch.display_path(),
+            old_path: patch.old_path.clone(),
+            action,
+            lines_added,
+            lines_deleted,
+            hunks,
+            error,
+        });
+    }
+
+    (reports, planned.into_changes())
+}
+
+/// The directories above `path` that don't exist yet, outermost first.
+fn missing_parent_dirs(path: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+
+    missing.reverse();
+    missing
+}
+
+fn restore(backups: &[(PathBuf, Option<Vec<u8>>)], created_dirs: &[PathBuf]) {
+    for (path, original) in backups.iter().rev() {
+        let _ = match original {
+            Some(bytes) => std::fs::write(path, bytes),
+            None => std::fs::remove_file(path),
+        };
+    }
+
+    for dir in created_dirs.iter().rev() {
+        let _ = std::fs::remove_dir(dir);
+    }
+}
+
+/// Writes all changes or none: every file is written through a temporary
+/// sibling and renamed into place, and the originals are restored if any
+/// step fails, removing the directories created on the way.
+pub fn write_changes_atomically<'a>(
+    changes: impl IntoIterator<Item = &'a PlannedChange>,
+) -> Result<(), String> {
+    let mut backups: Vec<(PathBuf, Option<Vec<u8>>)> = Vec::new();
+    let mut created_dirs: Vec<PathBuf> = Vec::new();
+
+    for change in changes {
+        let path = change.path();
+        backups.push((path.to_path_buf(), std::fs::read(path).ok()));
+
+        let result = match change {
+            PlannedChange::Write { path, contents } => {
+                let temp_path = path.with_file_name(format!(
+                    ".{}.promptlab-tmp",
+                    path.file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                ));
+
+                created_dirs.extend(missing_parent_dirs(path));
+
+                path.parent()
+                    .map(std::fs::create_dir_all)
+                    .unwrap_or(Ok(()))
+                    .and_then(|_| std::fs::write(&temp_path, contents))
+                    .and_then(|_| std::fs::rename(&temp_path, path))
+                    .inspect_err(|_| {
+                        let _ = std::fs::remove_file(&temp_path);
+                    })
+            }
+            PlannedChange::Remove { path } => std::fs::remove_file(path),
+        };
+
+        if let Err(err) = result {
+            restore(&backups, &created_dirs);
+            return Err(format!("Failed to write {}: {}", path.display(), err));
+        }
+    }
+
+    Ok(())
+}
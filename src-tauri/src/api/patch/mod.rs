@@ -0,0 +1,3 @@
+pub mod blocks;
+pub(crate) mod command;
+pub mod lib;
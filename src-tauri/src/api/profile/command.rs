@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use crate::{
+    api::{
+        git::snapshot::read_file,
+        profile::lib::{profile_text, FileTokenProfile, ProfileGranularity, DEFAULT_BLOCK_LINES},
+        tokenize::count_tokens_per_line,
+    },
+    errors::{codes, ApplicationError},
+};
+
+/// Token counts per line, per block of `block_lines` lines or per top-level
+/// item of a file, to find out what makes a large file expensive.
+#[tauri::command]
+pub(crate) fn file_token_profile(
+    path: String,
+    granularity: Option<ProfileGranularity>,
+    block_lines: Option<usize>,
+) -> Result<FileTokenProfile, ApplicationError> {
+    let bytes = read_file(Path::new(&path)).map_err(|_| ApplicationError {
+        code: codes::FILE_READ_ERROR,
+        message: Some(path.clone()),
+    })?;
+    let text = String::from_utf8_lossy(&bytes);
+    let line_counts = count_tokens_per_line(&text);
+
+    Ok(profile_text(
+        &path,
+        &text,
+        &line_counts,
+        granularity.unwrap_or_default(),
+        block_lines.unwrap_or(DEFAULT_BLOCK_LINES),
+    ))
+}
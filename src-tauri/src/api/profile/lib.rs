@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_BLOCK_LINES: usize = 50;
+const MAX_LABEL_LENGTH: usize = 80;
+
+/// Words that start a top-level item in the languages we usually see, after
+/// visibility and async-style modifiers have been skipped.
+const ITEM_KEYWORDS: &[&str] = &[
+    "fn",
+    "struct",
+    "enum",
+    "trait",
+    "impl",
+    "mod",
+    "macro_rules!",
+    "def",
+    "class",
+    "function",
+    "interface",
+    "type",
+    "const",
+    "let",
+    "var",
+    "static",
+    "func",
+    "namespace",
+    "module",
+    "describe",
+    "it",
+    "test",
+];
+
+const ITEM_MODIFIERS: &[&str] = &[
+    "pub",
+    "pub(crate)",
+    "pub(super)",
+    "export",
+    "default",
+    "async",
+    "unsafe",
+    "extern",
+    "abstract",
+    "public",
+    "private",
+    "protected",
+    "final",
+    "declare",
+];
+
+/// Lines that belong to the item that follows them.
+const LEADING_PREFIXES: &[&str] = &["#[", "@", "//", "#", "/*", "*", "--", "\"\"\""];
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProfileGranularity {
+    #[default]
+    Line,
+    /// Fixed runs of `block_lines` lines.
+    Block,
+    /// Top-level items such as functions and classes.
+    Item,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenRegion {
+    /// 1-based, inclusive.
+    pub start_line: usize,
+    /// 1-based, inclusive.
+    pub end_line: usize,
+    pub token_count: usize,
+    pub percentage: f64,
+    pub label: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileTokenProfile {
+    pub path: String,
+    pub total_token_count: usize,
+    pub line_count: usize,
+    pub regions: Vec<TokenRegion>,
+}
+
+/// The keyword starting the item declared on `line`, ignoring indentation
+/// and modifiers.
+pub fn item_keyword(line: &str) -> Option<&'static str> {
+    let mut rest = line.trim_start();
+
+    // Modifiers are matched whole before splitting, as `pub(crate)` holds one
+    // of the separators.
+    while let Some(after) = ITEM_MODIFIERS.iter().find_map(|modifier| {
+        rest.strip_prefix(modifier)
+            .filter(|after| after.starts_with(char::is_whitespace))
+    }) {
+        rest = after.trim_start();
+    }
+
+    let keyword = rest
+        .split(|c: char| c.is_whitespace() || c == '(' || c == '<' || c == '{' || c == ':')
+        .next()?;
+
+    ITEM_KEYWORDS.iter().copied().find(|&k| k == keyword)
+}
+
+/// Whether `text` is a comment or attribute, which belongs to the item below
+/// it.
+pub fn has_leading_prefix(text: &str) -> bool {
+    LEADING_PREFIXES
+        .iter()
+        .any(|prefix| text.starts_with(prefix))
+}
+
+fn item_label(line: &str) -> Option<String> {
+    if line.is_empty() || line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    item_keyword(line)?;
+
+    let label: String = line.trim_end().chars().take(MAX_LABEL_LENGTH).collect();
+    Some(label)
+}
+
+fn is_leading_line(line: &str) -> bool {
+    !line.starts_with(char::is_whitespace) && has_leading_prefix(line)
+}
+
+/// Splits the file into regions starting at each top-level item, with the
+/// comments and attributes right above an item moved into it. Whatever
+/// precedes the first item (imports, headers) gets a region without a label.
+fn item_regions(lines: &[&str]) -> Vec<(usize, usize, Option<String>)> {
+    let mut starts: Vec<(usize, String)> = Vec::new();
+
+    for (idx, line) in lines.iter().enumerate() {
+        let Some(label) = item_label(line) else {
+            continue;
+        };
+
+        let mut start = idx;
+        while start > 0 && is_leading_line(lines[start - 1]) {
+            start -= 1;
+        }
+
+        let previous_start = starts.last().map(|(s, _)| *s + 1).unwrap_or(0);
+        starts.push((start.max(previous_start), label));
+    }
+
+    let mut regions = Vec::new();
+    let first_start = starts.first().map(|(s, _)| *s).unwrap_or(lines.len());
+
+    if first_start > 0 {
+        regions.push((0, first_start, None));
+    }
+
+    for (i, (start, label)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map(|(s, _)| *s).unwrap_or(lines.len());
+        regions.push((*start, end, Some(label.clone())));
+    }
+
+    regions
+}
+
+/// Sums per-line token counts into regions of the requested granularity.
+pub fn profile_text(
+    path: &str,
+    text: &str,
+    line_counts: &[usize],
+    granularity: ProfileGranularity,
+    block_lines: usize,
+) -> FileTokenProfile {
+    let lines: Vec<&str> = text.lines().collect();
+    let line_count = line_counts.len();
+    let total_token_count: usize = line_counts.iter().sum();
+
+    let ranges: Vec<(usize, usize, Option<String>)> = match granularity {
+        ProfileGranularity::Line => (0..line_count).map(|i| (i, i + 1, None)).collect(),
+        ProfileGranularity::Block => {
+            let block_lines = block_lines.max(1);
+            (0..line_count)
+                .step_by(block_lines)
+                .map(|start| (start, (start + block_lines).min(line_count), None))
+                .collect()
+        }
+        ProfileGranularity::Item => item_regions(&lines),
+    };
+
+    let regions = ranges
+        .into_iter()
+        .filter(|(start, end, _)| start < end && *start < line_count)
+        .map(|(start, end, label)| {
+            let token_count: usize = line_counts[start..end.min(line_count)].iter().sum();
+            let percentage = if total_token_count > 0 {
+                (token_count as f64 / total_token_count as f64) * 100.0
+            } else {
+                0.0
+            };
+
+            TokenRegion {
+                start_line: start + 1,
+                end_line: end.min(line_count),
+                token_count,
+                percentage,
+                label,
+            }
+        })
+        .collect();
+
+    FileTokenProfile {
+        path: path.to_string(),
+        total_token_count,
+        line_count,
+        regions,
+    }
+}
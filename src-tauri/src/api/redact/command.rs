@@ -0,0 +1,87 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Wry};
+
+use crate::{
+    api::redact::lib::{get_redaction_patterns, RedactionPattern},
+    errors::{codes, ApplicationError},
+    store::{data_write_lock, open_store, save_store, StoreCategoryKey, StoreDataKey},
+};
+
+pub(crate) fn load_redaction_patterns(
+    app: &AppHandle<Wry>,
+    directory_path: &str,
+) -> Result<Vec<RedactionPattern>, ApplicationError> {
+    let store = open_store(app)?;
+
+    let patterns = store
+        .get(StoreCategoryKey::DATA)
+        .and_then(|data| data.as_object().cloned())
+        .and_then(|data_map| data_map.get(directory_path).cloned())
+        .map(|directory_value| get_redaction_patterns(&directory_value))
+        .unwrap_or_default();
+
+    store.close_resource();
+
+    Ok(patterns)
+}
+
+#[tauri::command]
+pub(crate) fn list_redaction_patterns(
+    app: AppHandle<Wry>,
+    directory_path: String,
+) -> Result<Vec<RedactionPattern>, ApplicationError> {
+    load_redaction_patterns(&app, &directory_path)
+}
+
+/// Saves the custom patterns of a project. A match is redacted whole unless
+/// the pattern has a `(?P<secret>...)` group, in which case only that group
+/// is.
+#[tauri::command]
+pub(crate) fn set_redaction_patterns(
+    app: AppHandle<Wry>,
+    directory_path: String,
+    patterns: Vec<RedactionPattern>,
+) -> Result<(), ApplicationError> {
+    for pattern in &patterns {
+        if let Err(err) = Regex::new(&pattern.pattern) {
+            return Err(ApplicationError {
+                code: codes::INVALID_PATTERN_ERROR,
+                message: Some(format!("Invalid pattern \"{}\": {}", pattern.name, err)),
+            });
+        }
+    }
+
+    let _guard = data_write_lock();
+    let store = open_store(&app)?;
+
+    let mut data: Map<String, Value> = store
+        .get(StoreCategoryKey::DATA)
+        .and_then(|value| value.as_object().cloned())
+        .unwrap_or_else(Map::new);
+
+    let directory_entry = data
+        .entry(directory_path)
+        .or_insert_with(|| Value::Object(Map::new()));
+
+    if !directory_entry.is_object() {
+        *directory_entry = Value::Object(Map::new());
+    }
+
+    let directory_object = directory_entry.as_object_mut().unwrap();
+
+    if patterns.is_empty() {
+        directory_object.remove(StoreDataKey::REDACTION_PATTERNS);
+    } else {
+        directory_object.insert(
+            StoreDataKey::REDACTION_PATTERNS.to_string(),
+            serde_json::to_value(patterns).unwrap_or_else(|_| Value::Array(Vec::new())),
+        );
+    }
+
+    store.set(StoreCategoryKey::DATA, Value::Object(data));
+    save_store(&store)?;
+    store.close_resource();
+
+    Ok(())
+}
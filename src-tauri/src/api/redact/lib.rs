@@ -0,0 +1,264 @@
+use crate::store::StoreDataKey;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, sync::OnceLock};
+
+const HIGH_ENTROPY_MIN_LENGTH: usize = 24;
+const HIGH_ENTROPY_THRESHOLD: f64 = 4.2;
+
+/// Capture group that narrows a match to the part that is redacted.
+pub const SECRET_GROUP: &str = "secret";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionPattern {
+    pub name: String,
+    pub pattern: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionHit {
+    pub detector: String,
+    pub placeholder: String,
+    /// 1-based line in the redacted text, which for copied prompts is the
+    /// section the hit is in.
+    pub line: usize,
+    /// The file, diff or page the line belongs to.
+    pub source: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionReport {
+    pub redacted_count: usize,
+    pub hits: Vec<RedactionHit>,
+}
+
+struct Detector {
+    name: String,
+    regex: Regex,
+}
+
+fn builtin_detectors() -> &'static Vec<Detector> {
+    static DETECTORS: OnceLock<Vec<Detector>> = OnceLock::new();
+
+    DETECTORS.get_or_init(|| {
+        [
+            (
+                "private_key",
+                r"-----BEGIN [A-Z0-9 ]*PRIVATE KEY( BLOCK)?-----[\s\S]*?-----END [A-Z0-9 ]*PRIVATE KEY( BLOCK)?-----",
+            ),
+            ("aws_access_key", r"\b(?:AKIA|ASIA|AGPA|AIDA|AROA)[0-9A-Z]{16}\b"),
+            (
+                "aws_secret_key",
+                r#"(?i)aws_?secret_?(?:access_?)?key["']?\s*[:=]\s*["']?(?P<secret>[A-Za-z0-9/+=]{40})"#,
+            ),
+            ("github_token", r"\b(?:ghp|gho|ghu|ghs|ghr)_[A-Za-z0-9]{36,255}\b"),
+            ("github_token", r"\bgithub_pat_[A-Za-z0-9_]{22,255}\b"),
+            ("slack_token", r"\bxox[abposr]-[A-Za-z0-9-]{10,}\b"),
+            (
+                "slack_webhook",
+                r"https://hooks\.slack\.com/services/[A-Za-z0-9_/]+",
+            ),
+            (
+                "jwt",
+                r"\beyJ[A-Za-z0-9_-]{8,}\.eyJ[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, pattern)| {
+            Regex::new(pattern).ok().map(|regex| Detector {
+                name: name.to_string(),
+                regex,
+            })
+        })
+        .collect()
+    })
+}
+
+fn high_entropy_candidates() -> &'static Regex {
+    static CANDIDATES: OnceLock<Regex> = OnceLock::new();
+    CANDIDATES.get_or_init(|| Regex::new(r"[A-Za-z0-9+/_\-]{24,}={0,2}").expect("valid regex"))
+}
+
+pub fn get_redaction_patterns(directory_object: &Value) -> Vec<RedactionPattern> {
+    directory_object
+        .as_object()
+        .and_then(|directory| directory.get(StoreDataKey::REDACTION_PATTERNS))
+        .and_then(|value| serde_json::from_value::<Vec<RedactionPattern>>(value.clone()).ok())
+        .unwrap_or_default()
+}
+
+pub fn shannon_entropy(text: &str) -> f64 {
+    let mut frequencies: HashMap<char, usize> = HashMap::new();
+    let mut total = 0usize;
+
+    for c in text.chars() {
+        *frequencies.entry(c).or_default() += 1;
+        total += 1;
+    }
+
+    if total == 0 {
+        return 0.0;
+    }
+
+    frequencies
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn looks_like_secret(candidate: &str) -> bool {
+    if candidate.len() < HIGH_ENTROPY_MIN_LENGTH {
+        return false;
+    }
+
+    let has_digit = candidate.chars().any(|c| c.is_ascii_digit());
+    let has_upper = candidate.chars().any(|c| c.is_ascii_uppercase());
+    let has_lower = candidate.chars().any(|c| c.is_ascii_lowercase());
+
+    has_digit && has_upper && has_lower && shannon_entropy(candidate) >= HIGH_ENTROPY_THRESHOLD
+}
+
+/// Splits a high-entropy candidate at its slashes, returning the byte range of
+/// each part in the payload. Paths like `icons/Square142x142Logo` mix cases
+/// and digits over enough distinct characters to pass as a secret when scored
+/// whole, so each segment is scored on its own.
+fn candidate_segments(offset: usize, candidate: &str) -> Vec<(usize, usize)> {
+    let mut segments = Vec::new();
+    let mut start = offset;
+
+    for segment in candidate.split('/') {
+        let end = start + segment.len();
+        if !segment.is_empty() {
+            segments.push((start, end));
+        }
+        start = end + 1;
+    }
+
+    segments
+}
+
+/// Maps every line of the payload to the file, diff or web page it belongs
+/// to, so that hits can be reported against something the user recognizes.
+/// Lines before any of them have no source.
+fn line_sources(payload: &str) -> Vec<Option<String>> {
+    let mut sources = Vec::new();
+    let mut current: Option<String> = None;
+
+    for line in payload.lines() {
+        if let Some(path) = line.strip_prefix("File: ") {
+            current = Some(path.to_string());
+        } else if let Some(rest) = line.strip_prefix("diff --git a/") {
+            current = rest.split(" b/").next().map(|p| p.to_string());
+        } else if let Some(url) = line.strip_prefix("The following content was fetched from: ") {
+            current = Some(url.to_string());
+        }
+
+        sources.push(current.clone());
+    }
+
+    sources
+}
+
+fn line_number_at(line_starts: &[usize], offset: usize) -> usize {
+    match line_starts.binary_search(&offset) {
+        Ok(idx) => idx + 1,
+        Err(idx) => idx,
+    }
+}
+
+/// Replaces every detected secret in `payload` with a placeholder naming the
+/// detector that matched, and reports where each replacement happened. A
+/// pattern with a group named `secret` redacts only that group, so context
+/// like `password=` can be matched and kept.
+pub fn redact_payload(
+    payload: &str,
+    custom_patterns: &[RedactionPattern],
+) -> (String, RedactionReport) {
+    let custom_detectors: Vec<Detector> = custom_patterns
+        .iter()
+        .filter_map(|p| {
+            Regex::new(&p.pattern).ok().map(|regex| Detector {
+                name: p.name.clone(),
+                regex,
+            })
+        })
+        .collect();
+
+    let mut matches: Vec<(usize, usize, String)> = Vec::new();
+
+    for detector in builtin_detectors().iter().chain(custom_detectors.iter()) {
+        for captures in detector.regex.captures_iter(payload) {
+            let Some(m) = captures.name(SECRET_GROUP).or_else(|| captures.get(0)) else {
+                continue;
+            };
+
+            if m.start() < m.end() {
+                matches.push((m.start(), m.end(), detector.name.clone()));
+            }
+        }
+    }
+
+    let named_matches = matches.clone();
+
+    for m in high_entropy_candidates().find_iter(payload) {
+        for (start, end) in candidate_segments(m.start(), m.as_str()) {
+            let covered = named_matches.iter().any(|(s, e, _)| start < *e && *s < end);
+
+            if !covered && looks_like_secret(&payload[start..end]) {
+                matches.push((start, end, "high_entropy".to_string()));
+            }
+        }
+    }
+
+    if matches.is_empty() {
+        return (payload.to_string(), RedactionReport::default());
+    }
+
+    matches.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(payload.match_indices('\n').map(|(idx, _)| idx + 1))
+        .collect();
+    let sources = line_sources(payload);
+
+    let mut redacted = String::with_capacity(payload.len());
+    let mut hits = Vec::new();
+    let mut cursor = 0;
+
+    for (start, end, detector) in matches {
+        if start < cursor {
+            continue;
+        }
+
+        let placeholder = format!("[REDACTED:{}]", detector);
+        let line = line_number_at(&line_starts, start);
+
+        redacted.push_str(&payload[cursor..start]);
+        redacted.push_str(&placeholder);
+        cursor = end;
+
+        hits.push(RedactionHit {
+            detector,
+            placeholder,
+            line,
+            source: sources.get(line.saturating_sub(1)).cloned().flatten(),
+        });
+    }
+
+    redacted.push_str(&payload[cursor..]);
+
+    (
+        redacted,
+        RedactionReport {
+            redacted_count: hits.len(),
+            hits,
+        },
+    )
+}
@@ -1,6 +1,15 @@
+use crate::api::cache::lib::{
+    needs_use_stamp, now_ms, prune_store_cache, spawn_cache_compaction, PruneStats,
+};
 use crate::api::clipboard::get_rendered_tree;
-use crate::api::tree::index::DirectoryNode;
-use crate::store::{save_store, StoreCategoryKey, StoreDataKey, STORE_FILE_NAME};
+use crate::api::git::snapshot::read_file;
+use crate::api::model::lib::{context_usage, ContextUsage};
+use crate::api::tokenizer::lib::{active_tokenizer, Tokenizer};
+use crate::api::tree::index::{DirectoryNode, TreeIndex};
+use crate::errors::ApplicationError;
+use crate::store::{data_write_lock, save_store, StoreCategoryKey, StoreDataKey, STORE_FILE_NAME};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use std::collections::hash_map::DefaultHasher;
@@ -8,19 +17,28 @@ use std::hash::{Hash, Hasher};
 use std::{
     collections::{HashMap, HashSet},
     fs,
-    sync::{OnceLock, RwLock},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock, RwLock,
+    },
     time::UNIX_EPOCH,
 };
 use tauri::{AppHandle, Emitter, Wry};
 use tauri_plugin_store::StoreExt;
-use tiktoken_rs::cl100k_base;
+use xxhash_rust::xxh3::xxh3_64;
 
-pub(crate) fn count_tokens_for_text(text: &str) -> usize {
-    if let Ok(enc) = cl100k_base() {
-        enc.encode_with_special_tokens(text).len()
-    } else {
-        0
-    }
+pub fn count_tokens_for_text(text: &str) -> usize {
+    active_tokenizer().count(text)
+}
+
+/// Token counts for each line of `text`, newline included, so that callers can
+/// cut a text at line boundaries without re-encoding it.
+pub(crate) fn count_tokens_per_line(text: &str) -> Vec<usize> {
+    let tokenizer = active_tokenizer();
+    text.split_inclusive('\n')
+        .map(|line| tokenizer.count(line))
+        .collect()
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -28,15 +46,53 @@ struct CacheEntry {
     mtime_ms: u128,
     size: u64,
     count: usize,
+    /// xxh3 of the file contents. Entries saved before it existed have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    /// When the entry was last written or hit, for pruning unused entries.
+    #[serde(default)]
+    used_ms: u64,
 }
 
+const MAX_CONTENT_COUNTS: usize = 100_000;
+
 static TOKEN_CACHE: OnceLock<RwLock<HashMap<String, CacheEntry>>> = OnceLock::new();
+static CONTENT_COUNTS: OnceLock<RwLock<HashMap<String, usize>>> = OnceLock::new();
 static LOADED_DIRS: OnceLock<RwLock<HashSet<String>>> = OnceLock::new();
 
 fn cache() -> &'static RwLock<HashMap<String, CacheEntry>> {
     TOKEN_CACHE.get_or_init(|| RwLock::new(HashMap::new()))
 }
 
+/// Counts keyed by tokenizer and content hash rather than by path, so a file
+/// that was touched, checked out again or copied into another root is not
+/// tokenized twice. It's cleared when it reaches `MAX_CONTENT_COUNTS` and
+/// whenever a token cache is compacted.
+fn content_counts() -> &'static RwLock<HashMap<String, usize>> {
+    CONTENT_COUNTS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn content_hash(bytes: &[u8]) -> String {
+    format!("{:016x}", xxh3_64(bytes))
+}
+
+fn get_content_count(tokenizer: Tokenizer, hash: &str) -> Option<usize> {
+    content_counts()
+        .read()
+        .ok()?
+        .get(&tokenizer.cache_key(hash))
+        .copied()
+}
+
+fn remember_content_count(tokenizer: Tokenizer, hash: &str, count: usize) {
+    if let Ok(mut counts) = content_counts().write() {
+        if counts.len() >= MAX_CONTENT_COUNTS {
+            counts.clear();
+        }
+        counts.insert(tokenizer.cache_key(hash), count);
+    }
+}
+
 fn loaded_dirs() -> &'static RwLock<HashSet<String>> {
     LOADED_DIRS.get_or_init(|| RwLock::new(HashSet::new()))
 }
@@ -55,27 +111,25 @@ fn file_sig(path: &str) -> Option<(u128, u64)> {
 }
 
 pub fn get_cached_count(path: &str) -> Option<usize> {
+    get_cached_count_for(active_tokenizer(), path)
+}
+
+fn get_cached_count_for(tokenizer: Tokenizer, path: &str) -> Option<usize> {
+    get_cached_entry_for(tokenizer, path).map(|e| e.count)
+}
+
+fn get_cached_entry_for(tokenizer: Tokenizer, path: &str) -> Option<CacheEntry> {
     let (mtime_ms, size) = file_sig(path)?;
     let cache = cache().read().ok()?;
-    cache.get(path).and_then(|e| {
-        if e.mtime_ms == mtime_ms && e.size == size {
-            Some(e.count)
-        } else {
-            None
-        }
-    })
+    cache
+        .get(&tokenizer.cache_key(path))
+        .filter(|e| e.mtime_ms == mtime_ms && e.size == size)
+        .cloned()
 }
 
-fn set_cache(path: &str, mtime_ms: u128, size: u64, count: usize) {
+fn set_cache(cache_key: &str, entry: CacheEntry) {
     let mut cache = cache().write().expect("cache write poisoned");
-    cache.insert(
-        path.to_string(),
-        CacheEntry {
-            mtime_ms,
-            size,
-            count,
-        },
-    );
+    cache.insert(cache_key.to_string(), entry);
 }
 
 #[derive(Clone, Serialize)]
@@ -92,10 +146,12 @@ struct TokenCountsEvent {
     selection_id: String,
     total_files_token_count: usize,
     total_tree_token_count: Option<usize>,
+    usage: Option<ContextUsage>,
     files: Vec<TokenCountResult>,
 }
 
 const BATCH_SIZE: usize = 25;
+const MAX_TOKEN_WORKERS: usize = 8;
 
 fn selection_id_for(ids: &[String]) -> String {
     let mut sorted = ids.to_vec();
@@ -124,8 +180,12 @@ pub fn ensure_cache_loaded_for_dir(app: &AppHandle<Wry>, root: &str) {
                                 serde_json::from_value::<HashMap<String, CacheEntry>>(value.clone())
                             {
                                 if let Ok(mut c) = cache().write() {
-                                    for (path, entry) in map {
-                                        c.insert(path, entry);
+                                    for (key, entry) in map {
+                                        let (tokenizer, path) = Tokenizer::split_cache_key(&key);
+                                        if let Some(hash) = &entry.hash {
+                                            remember_content_count(tokenizer, hash, entry.count);
+                                        }
+                                        c.insert(tokenizer.cache_key(path), entry);
                                     }
                                 }
                             }
@@ -140,6 +200,51 @@ pub fn ensure_cache_loaded_for_dir(app: &AppHandle<Wry>, root: &str) {
     if let Ok(mut loaded) = loaded_dirs().write() {
         loaded.insert(root.to_string());
     }
+
+    spawn_cache_compaction(app.clone(), root.to_string());
+}
+
+/// Drops the entries of `root` for files that are no longer in the tree or
+/// weren't used in `max_age_ms`, from the store and from memory. Entries
+/// saved before use stamps existed are stamped now instead. Counts by content
+/// are dropped too, since they can't tell which root still uses them.
+pub(crate) fn prune_token_cache(
+    app: &AppHandle<Wry>,
+    root: &str,
+    tree_index: &TreeIndex,
+    max_age_ms: u64,
+) -> Result<PruneStats, ApplicationError> {
+    let now = now_ms();
+
+    let stats = prune_store_cache(app, root, StoreDataKey::TOKEN_CACHE, |key, value| {
+        let (tokenizer, path) = Tokenizer::split_cache_key(key);
+        let mut entry = serde_json::from_value::<CacheEntry>(value.clone()).ok()?;
+
+        if !tree_index.nodes.contains_key(path) {
+            return None;
+        }
+
+        if entry.used_ms == 0 {
+            entry.used_ms = now;
+        } else if now.saturating_sub(entry.used_ms) > max_age_ms {
+            return None;
+        }
+
+        Some((tokenizer.cache_key(path), serde_json::to_value(entry).ok()?))
+    })?;
+
+    if let Ok(mut c) = cache().write() {
+        for key in &stats.removed_keys {
+            let (tokenizer, path) = Tokenizer::split_cache_key(key);
+            c.remove(&tokenizer.cache_key(path));
+        }
+    }
+
+    if let Ok(mut counts) = content_counts().write() {
+        counts.clear();
+    }
+
+    Ok(stats)
 }
 
 fn save_cache_batch_to_store(app: &AppHandle<Wry>, root: &str, batch: &[(String, CacheEntry)]) {
@@ -147,6 +252,8 @@ fn save_cache_batch_to_store(app: &AppHandle<Wry>, root: &str, batch: &[(String,
         return;
     }
 
+    let _guard = data_write_lock();
+
     if let Ok(store) = app.store(STORE_FILE_NAME) {
         let mut data: Map<String, Value> = store
             .get(StoreCategoryKey::DATA)
@@ -172,9 +279,9 @@ fn save_cache_batch_to_store(app: &AppHandle<Wry>, root: &str, batch: &[(String,
         }
 
         if let Some(token_cache_object) = token_cache_entry.as_object_mut() {
-            for (path, entry) in batch {
+            for (cache_key, entry) in batch {
                 if let Ok(entry_value) = serde_json::to_value(entry.clone()) {
-                    token_cache_object.insert(path.clone(), entry_value);
+                    token_cache_object.insert(cache_key.clone(), entry_value);
                 }
             }
         }
@@ -186,6 +293,124 @@ fn save_cache_batch_to_store(app: &AppHandle<Wry>, root: &str, batch: &[(String,
     }
 }
 
+/// Identifies one counting request. A newer request for the same root
+/// supersedes it, after which its workers skip the remaining files and it
+/// never emits.
+struct TokenCountTask {
+    root: String,
+    generation: u64,
+}
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+static LATEST_TASKS: OnceLock<Mutex<HashMap<String, u64>>> = OnceLock::new();
+static TOKEN_POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+fn latest_tasks() -> &'static Mutex<HashMap<String, u64>> {
+    LATEST_TASKS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Long-lived pool shared by every counting request, so toggling a selection
+/// doesn't spawn a thread and tokenizer state is set up once per worker.
+fn token_pool() -> &'static ThreadPool {
+    TOKEN_POOL.get_or_init(|| {
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+            .clamp(2, MAX_TOKEN_WORKERS);
+
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("token-count-{}", i))
+            .build()
+            .expect("failed to build token counting pool")
+    })
+}
+
+impl TokenCountTask {
+    fn start(root: &str) -> Self {
+        let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+
+        if let Ok(mut latest) = latest_tasks().lock() {
+            latest.insert(root.to_string(), generation);
+        }
+
+        Self {
+            root: root.to_string(),
+            generation,
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        latest_tasks()
+            .lock()
+            .map(|latest| latest.get(&self.root) != Some(&self.generation))
+            .unwrap_or(false)
+    }
+}
+
+struct FileCount {
+    count: usize,
+    /// Set when the count wasn't cached yet and should be saved to the store.
+    new_entry: Option<(String, CacheEntry)>,
+}
+
+fn count_file(tokenizer: Tokenizer, path: &str) -> FileCount {
+    let now = now_ms();
+
+    if let Some(entry) = get_cached_entry_for(tokenizer, path) {
+        let count = entry.count;
+        let new_entry = needs_use_stamp(entry.used_ms, now).then(|| {
+            let cache_key = tokenizer.cache_key(path);
+            let entry = CacheEntry {
+                used_ms: now,
+                ..entry
+            };
+            set_cache(&cache_key, entry.clone());
+            (cache_key, entry)
+        });
+
+        return FileCount { count, new_entry };
+    }
+
+    let (mtime_ms, size) = file_sig(path).unwrap_or((0, 0));
+
+    let Ok(bytes) = read_file(Path::new(path)) else {
+        return FileCount {
+            count: 0,
+            new_entry: None,
+        };
+    };
+
+    // The signature changed or the file was never seen, but the contents
+    // may still match something already counted.
+    let hash = content_hash(&bytes);
+    let token_count = get_content_count(tokenizer, &hash)
+        .unwrap_or_else(|| tokenizer.count(&String::from_utf8_lossy(&bytes)));
+    remember_content_count(tokenizer, &hash, token_count);
+
+    if mtime_ms == 0 {
+        return FileCount {
+            count: token_count,
+            new_entry: None,
+        };
+    }
+
+    let cache_key = tokenizer.cache_key(path);
+    let entry = CacheEntry {
+        mtime_ms,
+        size,
+        count: token_count,
+        hash: Some(hash),
+        used_ms: now,
+    };
+    set_cache(&cache_key, entry.clone());
+
+    FileCount {
+        count: token_count,
+        new_entry: Some((cache_key, entry)),
+    }
+}
+
 pub fn spawn_token_count_task(
     app: AppHandle<Wry>,
     root: String,
@@ -194,17 +419,23 @@ pub fn spawn_token_count_task(
     full_tree: Vec<DirectoryNode>,
     selected_set: HashSet<String>,
 ) {
-    std::thread::spawn(move || {
-        let bpe = cl100k_base().ok();
+    let task = TokenCountTask::start(&root);
+
+    token_pool().spawn(move || {
+        let tokenizer = active_tokenizer();
         let sid = selection_id_for(&selection_ids);
 
         let tree_token_count = if tree_display_mode == "none" {
             None
         } else {
             let rendered_tree = get_rendered_tree(&tree_display_mode, &full_tree, &selected_set);
-            Some(count_tokens_for_text(&rendered_tree))
+            Some(tokenizer.count(&rendered_tree))
         };
 
+        if task.is_cancelled() {
+            return;
+        }
+
         if selection_ids.is_empty() {
             let _ = app.emit(
                 "file-token-counts",
@@ -212,95 +443,74 @@ pub fn spawn_token_count_task(
                     selection_id: sid,
                     total_files_token_count: 0,
                     total_tree_token_count: tree_token_count,
+                    usage: context_usage(tree_token_count.unwrap_or(0)),
                     files: Vec::new(),
                 },
             );
             return;
         }
 
-        let mut counts: Vec<(String, usize)> = Vec::with_capacity(selection_ids.len());
-        let mut store_batch: Vec<(String, CacheEntry)> = Vec::new();
-
-        for id in selection_ids {
-            if let Some(count) = get_cached_count(&id) {
-                counts.push((id, count));
-            } else {
-                let (mtime_ms, size) = file_sig(&id).unwrap_or((0, 0));
-                let token_count = fs::read(&id)
-                    .ok()
-                    .map(|bytes| {
-                        let text = String::from_utf8_lossy(&bytes);
-                        if let Some(enc) = &bpe {
-                            enc.encode_with_special_tokens(&text).len()
-                        } else {
-                            0
-                        }
-                    })
-                    .unwrap_or(0);
-
-                if mtime_ms != 0 {
-                    set_cache(&id, mtime_ms, size, token_count);
-                    store_batch.push((
-                        id.clone(),
-                        CacheEntry {
-                            mtime_ms,
-                            size,
-                            count: token_count,
-                        },
-                    ));
-
-                    if store_batch.len() >= BATCH_SIZE {
-                        save_cache_batch_to_store(&app, &root, &store_batch);
-                        store_batch.clear();
-                    }
+        let results: Vec<Option<FileCount>> = selection_ids
+            .par_iter()
+            .map(|id| {
+                if task.is_cancelled() {
+                    None
+                } else {
+                    Some(count_file(tokenizer, id))
                 }
-
-                counts.push((id, token_count));
-            }
+            })
+            .collect();
+
+        // Counts made before a cancellation are still worth keeping.
+        let store_batch: Vec<(String, CacheEntry)> = results
+            .iter()
+            .flatten()
+            .filter_map(|file| file.new_entry.clone())
+            .collect();
+
+        for chunk in store_batch.chunks(BATCH_SIZE) {
+            save_cache_batch_to_store(&app, &root, chunk);
         }
 
-        if !store_batch.is_empty() {
-            save_cache_batch_to_store(&app, &root, &store_batch);
+        if task.is_cancelled() || results.iter().any(|result| result.is_none()) {
+            return;
         }
 
+        let counts: Vec<(String, usize)> = selection_ids
+            .into_iter()
+            .zip(results.into_iter().flatten())
+            .map(|(id, file)| (id, file.count))
+            .collect();
+
         let total: usize = counts.iter().map(|(_, c)| *c).sum();
+        let usage = context_usage(total + tree_token_count.unwrap_or(0));
 
-        let mut batch: Vec<TokenCountResult> = Vec::new();
-        for (id, token_count) in counts {
-            let token_percentage = if total > 0 {
-                (token_count as f64 / total as f64) * 100.0
-            } else {
-                0.0
-            };
+        for chunk in counts.chunks(BATCH_SIZE) {
+            if task.is_cancelled() {
+                return;
+            }
 
-            batch.push(TokenCountResult {
-                id,
-                token_count,
-                token_percentage,
-            });
-
-            if batch.len() >= BATCH_SIZE {
-                let _ = app.emit(
-                    "file-token-counts",
-                    TokenCountsEvent {
-                        selection_id: sid.clone(),
-                        total_files_token_count: total,
-                        total_tree_token_count: tree_token_count,
-                        files: batch.clone(),
+            let files = chunk
+                .iter()
+                .map(|(id, token_count)| TokenCountResult {
+                    id: id.clone(),
+                    token_count: *token_count,
+                    token_percentage: if total > 0 {
+                        (*token_count as f64 / total as f64) * 100.0
+                    } else {
+                        0.0
                     },
-                );
-                batch.clear();
-            }
-        }
+                })
+                .collect();
 
-        if !batch.is_empty() {
             let _ = app.emit(
                 "file-token-counts",
                 TokenCountsEvent {
-                    selection_id: sid,
+                    selection_id: sid.clone(),
                     total_files_token_count: total,
                     total_tree_token_count: tree_token_count,
-                    files: batch,
+                    usage: usage.clone(),
+                    files,
                 },
             );
         }
@@ -0,0 +1,41 @@
+use serde_json::{Map, Value};
+use tauri::AppHandle;
+
+use crate::api::tokenizer::lib::{
+    active_tokenizer, set_active_tokenizer, set_tokenizer_in_config, Tokenizer,
+};
+use crate::errors::ApplicationError;
+use crate::store::{open_store, save_store, StoreCategoryKey};
+
+#[tauri::command]
+pub(crate) fn list_tokenizers() -> Vec<Tokenizer> {
+    Tokenizer::ALL.to_vec()
+}
+
+#[tauri::command]
+pub(crate) fn get_tokenizer() -> Tokenizer {
+    active_tokenizer()
+}
+
+#[tauri::command]
+pub(crate) fn set_tokenizer<R: tauri::Runtime>(
+    app: AppHandle<R>,
+    tokenizer: Tokenizer,
+) -> Result<(), ApplicationError> {
+    let store = open_store(&app)?;
+
+    let mut config = store
+        .get(StoreCategoryKey::CONFIG)
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_else(Map::new);
+
+    set_tokenizer_in_config(&mut config, tokenizer);
+
+    store.set(StoreCategoryKey::CONFIG, Value::Object(config));
+    save_store(&store)?;
+    store.close_resource();
+
+    set_active_tokenizer(tokenizer);
+
+    Ok(())
+}
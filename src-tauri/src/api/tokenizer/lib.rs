@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::{OnceLock, RwLock};
+use tauri::AppHandle;
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, p50k_base_singleton, CoreBPE};
+
+use crate::store::{open_store, StoreCategoryKey, StoreConfigKey};
+
+const CHARS_PER_TOKEN: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Tokenizer {
+    /// GPT-4 and GPT-3.5 class models.
+    #[default]
+    Cl100kBase,
+    /// GPT-4o class models.
+    O200kBase,
+    /// Codex and older completion models.
+    P50kBase,
+    /// One token per four characters, for when speed matters more than accuracy.
+    CharEstimate,
+}
+
+impl Tokenizer {
+    pub const ALL: [Tokenizer; 4] = [
+        Tokenizer::Cl100kBase,
+        Tokenizer::O200kBase,
+        Tokenizer::P50kBase,
+        Tokenizer::CharEstimate,
+    ];
+
+    /// Stable identifier, used in the config and to key token caches.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Tokenizer::Cl100kBase => "cl100k_base",
+            Tokenizer::O200kBase => "o200k_base",
+            Tokenizer::P50kBase => "p50k_base",
+            Tokenizer::CharEstimate => "char_estimate",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Tokenizer> {
+        Tokenizer::ALL.into_iter().find(|t| t.key() == key)
+    }
+
+    fn bpe(&self) -> Option<&'static CoreBPE> {
+        match self {
+            Tokenizer::Cl100kBase => Some(cl100k_base_singleton()),
+            Tokenizer::O200kBase => Some(o200k_base_singleton()),
+            Tokenizer::P50kBase => Some(p50k_base_singleton()),
+            Tokenizer::CharEstimate => None,
+        }
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match self.bpe() {
+            Some(bpe) => bpe.encode_with_special_tokens(text).len(),
+            None => text.chars().count().div_ceil(CHARS_PER_TOKEN),
+        }
+    }
+
+    /// Prefixes `id` with the tokenizer key so counts made by different
+    /// tokenizers can live side by side in the same cache.
+    pub fn cache_key(&self, id: &str) -> String {
+        format!("{}:{}", self.key(), id)
+    }
+
+    /// Splits a cache key back into its tokenizer and id. Keys written before
+    /// tokenizers were selectable have no prefix and were counted with
+    /// `cl100k_base`.
+    pub fn split_cache_key(key: &str) -> (Tokenizer, &str) {
+        key.split_once(':')
+            .and_then(|(prefix, id)| Tokenizer::from_key(prefix).map(|t| (t, id)))
+            .unwrap_or((Tokenizer::Cl100kBase, key))
+    }
+}
+
+static ACTIVE_TOKENIZER: OnceLock<RwLock<Tokenizer>> = OnceLock::new();
+
+fn active() -> &'static RwLock<Tokenizer> {
+    ACTIVE_TOKENIZER.get_or_init(|| RwLock::new(Tokenizer::default()))
+}
+
+pub fn active_tokenizer() -> Tokenizer {
+    active().read().map(|t| *t).unwrap_or_default()
+}
+
+pub fn set_active_tokenizer(tokenizer: Tokenizer) {
+    if let Ok(mut active) = active().write() {
+        *active = tokenizer;
+    }
+}
+
+// Pure helper: set the tokenizer into a config map.
+pub fn set_tokenizer_in_config(config: &mut Map<String, Value>, tokenizer: Tokenizer) {
+    config.insert(
+        StoreConfigKey::TOKENIZER.to_string(),
+        json!(tokenizer.key()),
+    );
+}
+
+// Pure helper: read the tokenizer from a config map.
+pub fn get_tokenizer_from_config(config: &Map<String, Value>) -> Option<Tokenizer> {
+    config
+        .get(StoreConfigKey::TOKENIZER)
+        .and_then(|v| v.as_str())
+        .and_then(Tokenizer::from_key)
+}
+
+/// Reads the configured tokenizer into memory, so that counting doesn't need
+/// to go through the store. Called once when the app starts.
+pub fn load_active_tokenizer<R: tauri::Runtime>(app: &AppHandle<R>) {
+    let Ok(store) = open_store(app) else {
+        return;
+    };
+
+    let tokenizer = store
+        .get(StoreCategoryKey::CONFIG)
+        .and_then(|v| v.as_object().cloned())
+        .and_then(|config| get_tokenizer_from_config(&config))
+        .unwrap_or_default();
+
+    store.close_resource();
+    set_active_tokenizer(tokenizer);
+}
+
+/// Reads the `tokenCount` saved next to a stored instruction or page, counting
+/// `content` again when it was saved under a different tokenizer. Entries
+/// saved before tokenizers were selectable were counted with `cl100k_base`.
+pub fn stored_token_count(object: &Map<String, Value>, content: &str) -> Option<usize> {
+    let tokenizer = active_tokenizer();
+    let stored_with = object
+        .get("tokenizer")
+        .and_then(|v| v.as_str())
+        .and_then(Tokenizer::from_key)
+        .unwrap_or_default();
+
+    if stored_with != tokenizer {
+        return Some(tokenizer.count(content));
+    }
+
+    object
+        .get("tokenCount")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+}
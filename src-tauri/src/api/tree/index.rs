@@ -1,6 +1,7 @@
 use crate::api::directory::command::list::list_directory;
+use crate::api::git::snapshot::snapshot_tree;
 use crate::api::tree::cache::cache;
-use crate::errors::ApplicationError;
+use crate::errors::{codes, ApplicationError};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -76,7 +77,13 @@ pub fn ensure_index(path: &str, force_refresh: bool) -> Result<(), ApplicationEr
         }
     }
 
-    let full_tree = list_directory(path)?;
+    let full_tree = match snapshot_tree(path) {
+        Some(tree) => tree.map_err(|message| ApplicationError {
+            code: codes::GIT_REF_ERROR,
+            message: Some(message),
+        })?,
+        None => list_directory(path)?,
+    };
     let index = build_index(full_tree);
 
     {
@@ -1,6 +1,6 @@
 use crate::{
     api::{
-        tokenize::count_tokens_for_text,
+        tokenizer::lib::active_tokenizer,
         web::{
             favicon::save_favicon,
             lib::{extract_saved_pages_from_directory, SavedPageMetadata},
@@ -8,7 +8,7 @@ use crate::{
         },
     },
     errors::ApplicationError,
-    store::{open_store, save_store, StoreCategoryKey, StoreDataKey},
+    store::{data_write_lock, open_store, save_store, StoreCategoryKey, StoreDataKey},
 };
 use serde_json::{json, Map, Value};
 use std::fs;
@@ -31,7 +31,8 @@ pub async fn save_page_as_md(
         favicon_url,
     } = saved_web_page;
 
-    let token_count = count_tokens_for_text(&markdown);
+    let tokenizer = active_tokenizer();
+    let token_count = tokenizer.count(&markdown);
 
     let favicon_path = match favicon_url {
         Some(ref favicon_url) => save_favicon(&app, favicon_url).await,
@@ -45,6 +46,7 @@ pub async fn save_page_as_md(
         favicon_path: favicon_path.clone(),
     };
 
+    let _guard = data_write_lock();
     let mut data: Map<String, Value> = store
         .get(StoreCategoryKey::DATA)
         .and_then(|value| value.as_object().cloned())
@@ -77,6 +79,7 @@ pub async fn save_page_as_md(
                 "title": title,
                 "content": markdown,
                 "tokenCount": token_count,
+                "tokenizer": tokenizer.key(),
                 "faviconPath": favicon_path,
             }),
         );
@@ -96,6 +99,7 @@ pub fn edit_saved_page(
     url: String,
     new_title: String,
 ) -> Result<(), ApplicationError> {
+    let _guard = data_write_lock();
     let store = open_store(&app)?;
 
     let mut data = match store
@@ -154,6 +158,7 @@ pub fn delete_saved_page(
     directory_path: String,
     url: String,
 ) -> Result<(), ApplicationError> {
+    let _guard = data_write_lock();
     let store = open_store(&app)?;
 
     let mut data: Map<String, Value> = store
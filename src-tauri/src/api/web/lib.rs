@@ -3,6 +3,7 @@ use serde_json::Value;
 use tauri::{AppHandle, Wry};
 
 use crate::{
+    api::tokenizer::lib::stored_token_count,
     errors::ApplicationError,
     store::{open_store, StoreCategoryKey, StoreDataKey},
 };
@@ -39,10 +40,13 @@ pub fn extract_saved_pages_from_directory(value: &Value) -> Vec<SavedPageMetadat
             Some(SavedPageMetadata {
                 url: url.to_string(),
                 title: title.to_string(),
-                token_count: page_object
-                    .get("tokenCount")
-                    .and_then(|v| v.as_u64())
-                    .map(|v| v as usize),
+                token_count: stored_token_count(
+                    page_object,
+                    page_object
+                        .get("content")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default(),
+                ),
                 favicon_path: page_object
                     .get("faviconPath")
                     .and_then(|v| v.as_str())
@@ -56,7 +60,7 @@ pub(crate) fn load_page_contents_from_store(
     app: &AppHandle<Wry>,
     directory_path: &str,
     urls: &[String],
-) -> Result<Vec<String>, ApplicationError> {
+) -> Result<Vec<(String, String)>, ApplicationError> {
     let store = open_store(app)?;
     let parts = (|| {
         let data_object = store
@@ -78,10 +82,7 @@ pub(crate) fn load_page_contents_from_store(
                     let content = page_value.get("content")?.as_str()?;
                     let url = page_value.get("url")?.as_str()?;
 
-                    Some(format!(
-                        "The following content was fetched from: {}\n{}",
-                        url, content
-                    ))
+                    Some((url.to_string(), content.to_string()))
                 })
                 .collect(),
         )
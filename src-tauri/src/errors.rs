@@ -37,4 +37,28 @@ pub mod codes {
 
     /// Failed to open a file with the system default handler.
     pub const FILE_OPEN_ERROR: u8 = 10;
+
+    /// A user-provided regular expression failed to compile.
+    pub const INVALID_PATTERN_ERROR: u8 = 11;
+
+    /// Failed to write a file to disk.
+    pub const FILE_WRITE_ERROR: u8 = 12;
+
+    /// The requested prompt part doesn't exist or every part was copied.
+    pub const CHUNK_NOT_FOUND_ERROR: u8 = 13;
+
+    /// The text didn't contain any edit that could be parsed.
+    pub const PATCH_PARSE_ERROR: u8 = 14;
+
+    /// No model profile exists with the requested id.
+    pub const MODEL_PROFILE_NOT_FOUND_ERROR: u8 = 15;
+
+    /// A git ref or range couldn't be resolved in the repository.
+    pub const GIT_REF_ERROR: u8 = 16;
+
+    /// The git index couldn't be read or updated.
+    pub const GIT_INDEX_ERROR: u8 = 17;
+
+    /// An export to the configured path was requested but none is set.
+    pub const EXPORT_PATH_NOT_SET_ERROR: u8 = 18;
 }
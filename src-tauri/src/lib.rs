@@ -10,9 +10,17 @@ pub fn run() {
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_prevent_default::init())
+        .setup(|app| {
+            api::tokenizer::lib::load_active_tokenizer(app.handle());
+            api::model::lib::load_active_model_profile(app.handle());
+            api::git::settings::load_git_status_limit(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             api::directory::command::pick::pick_directory,
             api::directory::command::list::list_directory,
+            api::directory::command::list::list_directory_at_ref,
+            api::directory::command::list::close_directory_at_ref,
             api::directory::command::recent::get_recent_directories,
             api::directory::command::recent::add_recent_directory,
             api::tree::search::command::search_tree,
@@ -20,12 +28,42 @@ pub fn run() {
             api::tree::select::command::clear_selection,
             api::tree::render::command::count_rendered_tree_tokens,
             api::clipboard::command::copy_diffs_to_clipboard,
+            api::clipboard::command::copy_git_log_to_clipboard,
+            api::clipboard::command::copy_stash_to_clipboard,
             api::clipboard::command::copy_all_to_clipboard,
             api::clipboard::command::copy_pages_to_clipboard,
             api::clipboard::command::copy_files_to_clipboard,
             api::clipboard::command::copy_instructions_to_clipboard,
+            api::clipboard::command::preview_prompt,
+            api::clipboard::command::split_prompt_into_chunks,
+            api::clipboard::command::copy_prompt_chunk,
+            api::export::command::export_prompt_to_file,
+            api::export::command::get_export_path,
+            api::export::command::set_export_path,
+            api::cache::command::compact_token_caches,
+            api::profile::command::file_token_profile,
+            api::tokenizer::command::list_tokenizers,
+            api::tokenizer::command::get_tokenizer,
+            api::tokenizer::command::set_tokenizer,
+            api::model::command::list_model_profiles,
+            api::model::command::get_model_profile,
+            api::model::command::set_model_profile,
+            api::model::command::save_model_profile,
+            api::model::command::delete_model_profile,
             api::git::command::get_git_status,
+            api::git::command::get_git_status_limit,
+            api::git::command::set_git_status_limit,
+            api::git::command::get_git_diff_options,
+            api::git::command::set_git_diff_options,
+            api::git::command::resolve_git_conflict,
+            api::git::command::list_git_stashes,
+            api::git::command::get_git_repository_layout,
             api::git::command::watch_directory_for_git_changes,
+            api::git::command::unwatch_directory,
+            api::git::command::get_git_watch_debounce,
+            api::git::command::set_git_watch_debounce,
+            api::patch::command::apply_patch_from_text,
+            api::patch::command::apply_edit_blocks_from_text,
             api::web::command::save_page_as_md,
             api::web::command::delete_saved_page,
             api::web::command::list_saved_pages,
@@ -39,6 +77,8 @@ pub fn run() {
             api::editor::command::set_editor,
             api::editor::command::get_editor,
             api::editor::command::open_with_editor,
+            api::redact::command::list_redaction_patterns,
+            api::redact::command::set_redaction_patterns,
         ])
         .run(tauri::generate_context!())
         .expect("An error occurred while running the App");
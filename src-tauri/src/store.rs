@@ -3,23 +3,33 @@
  * {
  *     config: {
  *           path_to_editor: String
+ *           tokenizer: "cl100k_base" | "o200k_base" | "p50k_base" | "char_estimate"
+ *           model_profile: String
+ *           model_profiles: [ModelProfile, ...]
+ *           git_status_limit: Number
  *     },
  *     state: {
  *           recently_opened_directories: [PickedDirectory, ...]
  *     },
  *     data: {
  *           <directory_root>: {
- *              token_cache: { "<path>": CacheEntry, ... }
- *              git_token_cache: { "<path>": GitTokenCacheEntry, ... }
+ *              token_cache: { "<tokenizer>:<path>": CacheEntry, ... }
+ *              git_token_cache: { "<tokenizer>:<path>": GitTokenCacheEntry, ... }
  *              saved_web_pages: { "<url>": SavedWebPage, ... }
  *              saved_instructions: { "<uuid>": SavedInstruction, ... }
+ *              redaction_patterns: [RedactionPattern, ...]
+ *              git_diff_options: GitDiffOptions
+ *              git_watch_debounce_ms: Number
+ *              export_path: String
  *           }
  *     }
  * }
  */
 
 use crate::errors::{codes, ApplicationError};
-use std::sync::Arc;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
 use tauri::AppHandle;
 use tauri_plugin_store::{Store, StoreExt};
 
@@ -30,6 +40,10 @@ pub struct StoreDataKey;
 
 impl StoreConfigKey {
     pub const EDITOR: &'static str = "path_to_editor";
+    pub const TOKENIZER: &'static str = "tokenizer";
+    pub const MODEL_PROFILE: &'static str = "model_profile";
+    pub const MODEL_PROFILES: &'static str = "model_profiles";
+    pub const GIT_STATUS_LIMIT: &'static str = "git_status_limit";
 }
 
 impl StoreStateKey {
@@ -41,6 +55,10 @@ impl StoreDataKey {
     pub const GIT_TOKEN_CACHE: &'static str = "git_token_cache";
     pub const SAVED_WEB_PAGES: &'static str = "saved_web_pages";
     pub const SAVED_INSTRUCTIONS: &'static str = "saved_instructions";
+    pub const REDACTION_PATTERNS: &'static str = "redaction_patterns";
+    pub const GIT_DIFF_OPTIONS: &'static str = "git_diff_options";
+    pub const GIT_WATCH_DEBOUNCE_MS: &'static str = "git_watch_debounce_ms";
+    pub const EXPORT_PATH: &'static str = "export_path";
 }
 
 impl StoreCategoryKey {
@@ -66,3 +84,72 @@ pub fn save_store<R: tauri::Runtime>(store: &Arc<Store<R>>) -> Result<(), Applic
         message: Some("Failed to save store".to_string()),
     })
 }
+
+static DATA_WRITE_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+
+/// Serializes read-modify-write cycles on the `data` category. Every writer
+/// of it takes this lock, or one cycle could drop another's changes.
+pub fn data_write_lock() -> MutexGuard<'static, ()> {
+    DATA_WRITE_LOCK
+        .get_or_init(|| Mutex::new(()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Reads the value stored under `key` for the project at `root`.
+pub fn read_project_value<T: DeserializeOwned, R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    root: &str,
+    key: &str,
+) -> Option<T> {
+    let store = open_store(app).ok()?;
+    let value = store
+        .get(StoreCategoryKey::DATA)
+        .and_then(|data| data.get(root).cloned())
+        .and_then(|directory| directory.get(key).cloned());
+    store.close_resource();
+
+    serde_json::from_value(value?).ok()
+}
+
+/// Stores `value` under `key` for the project at `root`, removing the key
+/// when `value` is `None`.
+pub fn write_project_value<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    root: &str,
+    key: &str,
+    value: Option<Value>,
+) -> Result<(), ApplicationError> {
+    let _guard = data_write_lock();
+    let store = open_store(app)?;
+
+    let mut data: Map<String, Value> = store
+        .get(StoreCategoryKey::DATA)
+        .and_then(|value| value.as_object().cloned())
+        .unwrap_or_else(Map::new);
+
+    let directory_entry = data
+        .entry(root.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+
+    if !directory_entry.is_object() {
+        *directory_entry = Value::Object(Map::new());
+    }
+
+    let directory_object = directory_entry.as_object_mut().unwrap();
+
+    match value {
+        Some(value) => {
+            directory_object.insert(key.to_string(), value);
+        }
+        None => {
+            directory_object.remove(key);
+        }
+    }
+
+    store.set(StoreCategoryKey::DATA, Value::Object(data));
+    save_store(&store)?;
+    store.close_resource();
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use promptlab_lib::api::clipboard::assemble::{AssembledPrompt, PromptSection, SectionKind};
+use promptlab_lib::api::clipboard::budget::{
+    apply_token_budget, truncate_head_tail, PromptTokenSummary, TruncationStrategy,
+};
+
+fn numbered_lines(name: &str, count: usize) -> String {
+    (0..count)
+        .map(|i| format!("let {}_{} = compute_value({}, \"{}\");\n", name, i, i, name))
+        .collect()
+}
+
+fn prompt(sections: Vec<PromptSection>) -> AssembledPrompt {
+    let mut prompt = AssembledPrompt::new("/project");
+    prompt.extend(sections);
+    prompt
+}
+
+fn file(path: &str, lines: usize) -> PromptSection {
+    PromptSection::new(SectionKind::File, path, numbered_lines("file", lines))
+}
+
+fn page(url: &str, lines: usize) -> PromptSection {
+    PromptSection::new(SectionKind::WebPage, url, numbered_lines("page", lines))
+}
+
+fn unbounded_total(sections: Vec<PromptSection>) -> usize {
+    apply_token_budget(&mut prompt(sections), None, TruncationStrategy::default()).total_token_count
+}
+
+fn marker(summary: &PromptTokenSummary, idx: usize) -> String {
+    format!(
+        "[truncated {} tokens]\n",
+        summary.sections[idx].truncated_token_count
+    )
+}
+
+#[test]
+fn test_reports_each_section_without_a_budget() {
+    let mut prompt = prompt(vec![
+        PromptSection::new(SectionKind::Tree, "tree", "└── src/main.rs *"),
+        file("src/main.rs", 5),
+        page("https://example.com/docs", 5),
+    ]);
+    let before = prompt.render();
+
+    let summary = apply_token_budget(&mut prompt, None, TruncationStrategy::default());
+
+    assert_eq!(prompt.render(), before);
+    assert!(summary.within_budget);
+    assert_eq!(summary.max_tokens, None);
+
+    let breakdown: Vec<(SectionKind, &str)> = summary
+        .sections
+        .iter()
+        .map(|section| (section.kind, section.label.as_str()))
+        .collect();
+    assert_eq!(
+        breakdown,
+        vec![
+            (SectionKind::Tree, "tree"),
+            (SectionKind::File, "src/main.rs"),
+            (SectionKind::WebPage, "https://example.com/docs"),
+        ]
+    );
+    assert!(summary.sections.iter().all(|s| s.token_count > 0));
+    assert!(summary
+        .sections
+        .iter()
+        .all(|s| s.truncated_token_count == 0));
+
+    let sections_total: usize = summary.sections.iter().map(|s| s.token_count).sum();
+    assert!(summary.total_token_count >= sections_total);
+}
+
+#[test]
+fn test_drop_largest_files_replaces_the_largest_file_with_a_marker() {
+    let sections = vec![file("src/small.rs", 5), file("src/large.rs", 200)];
+    let max_tokens = unbounded_total(sections.clone()) - 100;
+    let mut prompt = prompt(sections);
+
+    let summary = apply_token_budget(
+        &mut prompt,
+        Some(max_tokens),
+        TruncationStrategy::DropLargestFiles,
+    );
+
+    assert!(summary.within_budget);
+    assert!(summary.total_token_count <= max_tokens);
+    assert_eq!(prompt.sections[0].body, numbered_lines("file", 5));
+    assert_eq!(summary.sections[0].truncated_token_count, 0);
+    assert!(summary.sections[1].truncated_token_count > 0);
+    assert_eq!(prompt.sections[1].body, marker(&summary, 1));
+}
+
+#[test]
+fn test_head_tail_keeps_both_ends_of_a_file() {
+    let sections = vec![file("src/lib.rs", 200)];
+    let max_tokens = unbounded_total(sections.clone()) / 2;
+    let mut prompt = prompt(sections);
+
+    let summary = apply_token_budget(&mut prompt, Some(max_tokens), TruncationStrategy::HeadTail);
+
+    let body = &prompt.sections[0].body;
+    assert!(summary.within_budget);
+    assert!(summary.sections[0].truncated_token_count > 0);
+    assert!(body.starts_with("let file_0 = "));
+    assert!(body.ends_with("let file_199 = compute_value(199, \"file\");\n"));
+    assert!(body.contains(&marker(&summary, 0)));
+    assert!(!body.contains("let file_100 = "));
+}
+
+#[test]
+fn test_head_tail_keeps_files_under_their_share_whole() {
+    let sections = vec![file("src/small.rs", 5), file("src/large.rs", 200)];
+    let max_tokens = unbounded_total(sections.clone()) / 2;
+    let mut prompt = prompt(sections);
+
+    let summary = apply_token_budget(&mut prompt, Some(max_tokens), TruncationStrategy::HeadTail);
+
+    assert!(summary.within_budget);
+    assert_eq!(prompt.sections[0].body, numbered_lines("file", 5));
+    assert_eq!(summary.sections[0].truncated_token_count, 0);
+    assert!(prompt.sections[1].body.contains(&marker(&summary, 1)));
+}
+
+#[test]
+fn test_drop_web_pages_first_keeps_files() {
+    let sections = vec![
+        file("src/large.rs", 200),
+        page("https://example.com/a", 100),
+    ];
+    let max_tokens = unbounded_total(sections.clone()) - 50;
+    let mut prompt = prompt(sections);
+
+    let summary = apply_token_budget(
+        &mut prompt,
+        Some(max_tokens),
+        TruncationStrategy::DropWebPagesFirst,
+    );
+
+    assert!(summary.within_budget);
+    assert_eq!(prompt.sections[0].body, numbered_lines("file", 200));
+    assert_eq!(prompt.sections[1].body, marker(&summary, 1));
+}
+
+#[test]
+fn test_falls_back_to_dropping_files_when_the_strategy_is_not_enough() {
+    let sections = vec![file("src/small.rs", 5), file("src/large.rs", 200)];
+    let max_tokens = unbounded_total(sections.clone()) - 100;
+    let mut prompt = prompt(sections);
+
+    let summary = apply_token_budget(
+        &mut prompt,
+        Some(max_tokens),
+        TruncationStrategy::DropWebPagesFirst,
+    );
+
+    assert!(summary.within_budget);
+    assert_eq!(prompt.sections[1].body, marker(&summary, 1));
+}
+
+#[test]
+fn test_never_truncates_the_tree_or_the_diff() {
+    let diff = numbered_lines("diff", 100);
+    let mut prompt = prompt(vec![
+        PromptSection::new(SectionKind::Tree, "tree", "└── src/main.rs *"),
+        PromptSection::new(SectionKind::GitDiff, "diff", diff.clone()),
+        file("src/main.rs", 50),
+    ]);
+
+    let summary = apply_token_budget(&mut prompt, Some(10), TruncationStrategy::HeadTail);
+
+    assert!(!summary.within_budget);
+    assert_eq!(prompt.sections[0].body, "└── src/main.rs *");
+    assert_eq!(prompt.sections[1].body, diff);
+    assert_eq!(summary.sections[1].truncated_token_count, 0);
+    assert_eq!(prompt.sections[2].body, marker(&summary, 2));
+}
+
+#[test]
+fn test_truncate_head_tail_leaves_text_that_fits() {
+    let text = numbered_lines("x", 3);
+
+    assert_eq!(truncate_head_tail(&text, 10_000), (text.clone(), 0));
+
+    let (truncated, removed) = truncate_head_tail(&numbered_lines("x", 100), 200);
+    assert!(removed > 0);
+    assert!(truncated.contains(&format!("[truncated {} tokens]\n", removed)));
+}
@@ -0,0 +1,144 @@
+use promptlab_lib::api::clipboard::assemble::{AssembledPrompt, PromptSection, SectionKind};
+use promptlab_lib::api::clipboard::chunk::{render_chunks, split_into_chunks};
+use promptlab_lib::api::tokenize::count_tokens_for_text;
+
+const ROOT: &str =
+    "/Users/developer/Documents/Projects/client-work/acme-corporation/internal-tools/prompt-lab";
+
+fn numbered_lines(name: &str, count: usize) -> String {
+    (0..count)
+        .map(|i| format!("let {}_{} = compute_value({}, \"{}\");\n", name, i, i, name))
+        .collect()
+}
+
+fn file(path: &str, lines: usize) -> PromptSection {
+    PromptSection::new(SectionKind::File, path, numbered_lines("file", lines))
+}
+
+fn tree(entries: usize) -> PromptSection {
+    let body = (0..entries)
+        .map(|i| format!("├── src/module_{}.rs *", i))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    PromptSection::new(SectionKind::Tree, "tree", body)
+}
+
+fn rendered_parts(prompt: &AssembledPrompt, max_tokens_per_part: usize) -> Vec<String> {
+    render_chunks(
+        split_into_chunks(prompt, max_tokens_per_part)
+            .into_iter()
+            .map(|chunk| chunk.render())
+            .collect(),
+    )
+}
+
+fn assert_within(parts: &[String], max_tokens_per_part: usize) {
+    for (idx, part) in parts.iter().enumerate() {
+        let tokens = count_tokens_for_text(part);
+        assert!(
+            tokens <= max_tokens_per_part,
+            "part {} has {} tokens, over {}",
+            idx + 1,
+            tokens,
+            max_tokens_per_part
+        );
+    }
+}
+
+#[test]
+fn test_prompt_that_fits_stays_in_one_part_without_a_header() {
+    let mut prompt = AssembledPrompt::new(ROOT);
+    prompt.extend([tree(3), file("src/main.rs", 5)]);
+
+    let parts = rendered_parts(&prompt, 10_000);
+
+    assert_eq!(parts, vec![prompt.render()]);
+}
+
+#[test]
+fn test_every_part_stays_within_the_limit() {
+    let mut prompt = AssembledPrompt::new(ROOT);
+    prompt.extend([
+        tree(40),
+        PromptSection::new(SectionKind::GitDiff, "diff", numbered_lines("diff", 30)),
+        PromptSection::new(
+            SectionKind::WebPage,
+            "https://example.com/docs",
+            numbered_lines("page", 30),
+        ),
+        PromptSection::new(
+            SectionKind::UserInstruction,
+            "Review",
+            "Look for bugs in the files above.",
+        ),
+    ]);
+    prompt.extend((0..30).map(|i| file(&format!("src/module_{}.rs", i), 3 + i % 7)));
+
+    for max_tokens_per_part in [150, 300, 1_000] {
+        let parts = rendered_parts(&prompt, max_tokens_per_part);
+
+        assert!(parts.len() > 1);
+        assert_within(&parts, max_tokens_per_part);
+    }
+}
+
+#[test]
+fn test_parts_break_between_files() {
+    let files: Vec<PromptSection> = (0..12)
+        .map(|i| file(&format!("src/module_{}.rs", i), 10))
+        .collect();
+    let mut prompt = AssembledPrompt::new(ROOT);
+    prompt.extend(files.clone());
+
+    let chunks = split_into_chunks(&prompt, 400);
+    let packed: Vec<(&str, &str)> = chunks
+        .iter()
+        .flat_map(|chunk| &chunk.sections)
+        .map(|section| (section.label.as_str(), section.body.as_str()))
+        .collect();
+    let expected: Vec<(&str, &str)> = files
+        .iter()
+        .map(|section| (section.label.as_str(), section.body.as_str()))
+        .collect();
+
+    assert!(chunks.len() > 1);
+    assert_eq!(packed, expected);
+}
+
+#[test]
+fn test_file_larger_than_a_part_is_cut_at_line_boundaries() {
+    let large = file("src/large.rs", 200);
+    let mut prompt = AssembledPrompt::new(ROOT);
+    prompt.extend([file("src/small.rs", 3), large.clone()]);
+
+    let chunks = split_into_chunks(&prompt, 300);
+    let pieces: Vec<&PromptSection> = chunks
+        .iter()
+        .flat_map(|chunk| &chunk.sections)
+        .filter(|section| section.label == "src/large.rs")
+        .collect();
+
+    assert!(pieces.len() > 1);
+    assert!(pieces.iter().all(|piece| piece.body.ends_with('\n')));
+    assert_eq!(
+        pieces
+            .iter()
+            .map(|piece| piece.body.as_str())
+            .collect::<String>(),
+        large.body
+    );
+    assert_within(&rendered_parts(&prompt, 300), 300);
+}
+
+#[test]
+fn test_tree_larger_than_a_part_keeps_room_for_the_root_and_legend() {
+    let mut prompt = AssembledPrompt::new(ROOT);
+    prompt.extend([tree(200)]);
+
+    let parts = rendered_parts(&prompt, 200);
+
+    assert!(parts.len() > 1);
+    assert!(parts.iter().all(|part| part.contains(ROOT)));
+    assert_within(&parts, 200);
+}
@@ -0,0 +1,157 @@
+use git2::{DiffOptions, Patch};
+use promptlab_lib::api::git::function_context::function_context_patch;
+use std::path::Path;
+
+/// Joins `lines`, each ended with a newline.
+fn text(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// The hunks of the function context patch between `old` and `new`, without
+/// the file header.
+fn hunks(path: &str, old: &str, new: &str, context_lines: u32) -> String {
+    let path = Path::new(path);
+    let mut opts = DiffOptions::new();
+    opts.context_lines(1 << 30);
+
+    let mut patch = Patch::from_buffers(
+        old.as_bytes(),
+        Some(path),
+        new.as_bytes(),
+        Some(path),
+        Some(&mut opts),
+    )
+    .expect("patch");
+
+    let out = String::from_utf8(function_context_patch(&mut patch, context_lines)).unwrap();
+    out.find("@@")
+        .map(|at| out[at..].to_string())
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_change_expands_to_the_enclosing_function_and_its_comments() {
+    let old = text(&[
+        "use std::fmt;",
+        "",
+        "/// Adds one.",
+        "fn add(a: i32) -> i32 {",
+        "    let b = a;",
+        "    let c = b;",
+        "    c + 1",
+        "}",
+        "",
+        "fn other() {}",
+    ]);
+    let new = old.replace("    c + 1\n", "    let d = c;\n    d + 1\n");
+
+    assert_eq!(
+        hunks("src/lib.rs", &old, &new, 0),
+        text(&[
+            "@@ -3,6 +3,7 @@",
+            " /// Adds one.",
+            " fn add(a: i32) -> i32 {",
+            "     let b = a;",
+            "     let c = b;",
+            "-    c + 1",
+            "+    let d = c;",
+            "+    d + 1",
+            " }",
+        ])
+    );
+}
+
+#[test]
+fn test_later_hunk_headers_follow_the_shifted_lines() {
+    let old = text(&[
+        "fn add(a: i32) -> i32 {",
+        "    a + 1",
+        "}",
+        "",
+        "",
+        "",
+        "fn other() {}",
+    ]);
+    let new = old
+        .replace("    a + 1\n", "    let b = a;\n    b + 1\n")
+        .replace("fn other() {}", "fn other() { }");
+
+    assert_eq!(
+        hunks("src/lib.rs", &old, &new, 0),
+        text(&[
+            "@@ -1,3 +1,4 @@",
+            " fn add(a: i32) -> i32 {",
+            "-    a + 1",
+            "+    let b = a;",
+            "+    b + 1",
+            " }",
+            "@@ -7,1 +8,1 @@",
+            "-fn other() {}",
+            "+fn other() { }",
+        ])
+    );
+}
+
+#[test]
+fn test_method_without_keyword_is_a_function() {
+    let old = text(&[
+        "class Store {",
+        "    public void save(User user) {",
+        "        validate(user);",
+        "        db.insert(user);",
+        "    }",
+        "",
+        "    public void load() {",
+        "        db.read();",
+        "    }",
+        "}",
+    ]);
+    let new = old.replace("db.insert(user)", "db.upsert(user)");
+
+    assert_eq!(
+        hunks("Store.java", &old, &new, 0),
+        text(&[
+            "@@ -2,4 +2,4 @@",
+            "     public void save(User user) {",
+            "         validate(user);",
+            "-        db.insert(user);",
+            "+        db.upsert(user);",
+            "     }",
+        ])
+    );
+}
+
+#[test]
+fn test_change_on_the_closing_line_expands_to_its_function() {
+    let old = text(&[
+        "function a() {",
+        "  one();",
+        "  two();",
+        "}",
+        "",
+        "function b() {}",
+    ]);
+    let new = old.replacen("}\n", "};\n", 1);
+
+    assert_eq!(
+        hunks("src/a.js", &old, &new, 0),
+        text(&[
+            "@@ -1,4 +1,4 @@",
+            " function a() {",
+            "   one();",
+            "   two();",
+            "-}",
+            "+};",
+        ])
+    );
+}
+
+#[test]
+fn test_new_file_hunk_starts_the_empty_side_at_zero() {
+    let new = text(&["a", "b"]);
+
+    assert_eq!(
+        hunks("notes.txt", "", &new, 3),
+        text(&["@@ -0,0 +1,2 @@", "+a", "+b"])
+    );
+}
@@ -0,0 +1,2 @@
+mod function_context;
+mod snapshot;
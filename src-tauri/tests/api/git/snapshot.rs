@@ -0,0 +1,23 @@
+use promptlab_lib::api::git::snapshot::{snapshot_root, snapshot_source};
+
+#[test]
+fn test_refs_that_differ_in_slashes_get_their_own_root() {
+    let slashed = snapshot_root("/code/app", "feature/x");
+    let dashed = snapshot_root("/code/app", "feature-x");
+
+    assert_eq!(slashed, "git-ref:/code/app@feature%2Fx");
+    assert_eq!(dashed, "git-ref:/code/app@feature-x");
+    assert_ne!(
+        snapshot_root("/code/app", "a%2Fb"),
+        snapshot_root("/code/app", "a/b")
+    );
+}
+
+#[test]
+fn test_snapshot_source_is_the_listed_project() {
+    assert_eq!(
+        snapshot_source(&snapshot_root("/code/my@app/", "release/v1@{1}")),
+        Some("/code/my@app")
+    );
+    assert_eq!(snapshot_source("/code/app"), None);
+}
@@ -1,3 +1,9 @@
+mod clipboard;
 mod directory;
 mod editor;
+mod git;
+mod patch;
+mod profile;
+mod redact;
+mod tokenizer;
 mod tree;
@@ -0,0 +1,219 @@
+use promptlab_lib::api::patch::blocks::{
+    parse_edit_blocks, plan_edit_blocks, resolve_block_path, EditBlock, EditBlockKind,
+};
+use promptlab_lib::api::patch::lib::{FileAction, PlannedChange};
+use promptlab_lib::api::tree::index::{DirectoryNode, TreeIndex};
+use std::collections::HashMap;
+
+use super::TestProject;
+
+/// Joins `lines`, each ended with a newline.
+fn text(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// An index holding only the given files, as the tree cache would list them.
+fn index_of(root: &str, files: &[&str]) -> TreeIndex {
+    let nodes = files
+        .iter()
+        .map(|file| {
+            let id = format!("{}/{}", root, file);
+            let node = DirectoryNode {
+                id: id.clone(),
+                title: file.rsplit('/').next().unwrap_or(file).to_string(),
+                node_type: "file".to_string(),
+                children: Vec::new(),
+                parent: None,
+                child_ids: Vec::new(),
+            };
+            (id, node)
+        })
+        .collect::<HashMap<_, _>>();
+
+    TreeIndex {
+        top_level: nodes.keys().cloned().collect(),
+        nodes,
+        titles: Vec::new(),
+    }
+}
+
+#[test]
+fn test_parses_whole_file_named_in_fence_info() {
+    let blocks = parse_edit_blocks(&text(&[
+        "Here is the change.",
+        "",
+        "```rust src/main.rs",
+        "fn main() {}",
+        "```",
+    ]));
+
+    assert_eq!(
+        blocks,
+        vec![EditBlock::WholeFile {
+            path: "src/main.rs".to_string(),
+            contents: "fn main() {}\n".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn test_parses_whole_file_under_heading_that_says_so() {
+    let blocks = parse_edit_blocks(&text(&[
+        "Here is the full updated `src/lib.rs`:",
+        "```rust",
+        "pub fn a() {}",
+        "```",
+    ]));
+
+    assert_eq!(
+        blocks,
+        vec![EditBlock::WholeFile {
+            path: "src/lib.rs".to_string(),
+            contents: "pub fn a() {}\n".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn test_fence_under_plain_path_heading_is_a_snippet() {
+    let blocks = parse_edit_blocks(&text(&[
+        "### src/lib.rs",
+        "```rust",
+        "pub fn a() {}",
+        "```",
+    ]));
+
+    assert_eq!(
+        blocks,
+        vec![EditBlock::Snippet {
+            path: "src/lib.rs".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn test_parses_search_replace_blocks_and_skips_diff_fences() {
+    let blocks = parse_edit_blocks(&text(&[
+        "src/lib.rs",
+        "```rust",
+        "<<<<<<< SEARCH",
+        "fn a() {}",
+        "=======",
+        "fn a() { 1 }",
+        ">>>>>>> REPLACE",
+        "src/main.rs",
+        "<<<<<<< SEARCH",
+        "fn main() {}",
+        "=======",
+        "fn main() { a(); }",
+        ">>>>>>> REPLACE",
+        "```",
+        "",
+        "```diff",
+        "--- a/src/lib.rs",
+        "+++ b/src/lib.rs",
+        "```",
+    ]));
+
+    assert_eq!(
+        blocks,
+        vec![
+            EditBlock::SearchReplace {
+                path: "src/lib.rs".to_string(),
+                search: "fn a() {}\n".to_string(),
+                replace: "fn a() { 1 }\n".to_string(),
+            },
+            EditBlock::SearchReplace {
+                path: "src/main.rs".to_string(),
+                search: "fn main() {}\n".to_string(),
+                replace: "fn main() { a(); }\n".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_plan_applies_search_replace_with_preview() {
+    let project = TestProject::new();
+    project.write("src/lib.rs", "fn a() {}\nfn b() {}\n");
+
+    let blocks = vec![EditBlock::SearchReplace {
+        path: "src/lib.rs".to_string(),
+        search: "fn b() {}\n".to_string(),
+        replace: "fn b() { 2 }\n".to_string(),
+    }];
+
+    let planned = plan_edit_blocks(project.root(), &blocks, None);
+    let (report, change) = &planned[0];
+
+    assert_eq!(report.path, "src/lib.rs");
+    assert_eq!(report.action, FileAction::Modify);
+    assert_eq!((report.lines_added, report.lines_deleted), (1, 1));
+    assert!(report.preview_diff.contains("+fn b() { 2 }"));
+    assert!(report.error.is_none());
+    assert!(matches!(
+        change,
+        Some(PlannedChange::Write { contents, .. }) if contents == "fn a() {}\nfn b() { 2 }\n"
+    ));
+}
+
+#[test]
+fn test_plan_refuses_search_text_found_more_than_once() {
+    let project = TestProject::new();
+    project.write("src/lib.rs", "x += 1;\ny();\nx += 1;\n");
+
+    let blocks = vec![EditBlock::SearchReplace {
+        path: "src/lib.rs".to_string(),
+        search: "x += 1;\n".to_string(),
+        replace: "x += 2;\n".to_string(),
+    }];
+
+    let planned = plan_edit_blocks(project.root(), &blocks, None);
+    let (report, change) = &planned[0];
+
+    assert!(change.is_none());
+    assert!(!report.blocks[0].applied);
+    assert!(report.blocks[0]
+        .error
+        .as_deref()
+        .is_some_and(|error| error.contains("matches 2 places")));
+    assert!(report.error.is_some());
+}
+
+#[test]
+fn test_plan_reports_snippets_without_writing() {
+    let project = TestProject::new();
+    project.write("src/lib.rs", "fn a() {}\n");
+
+    let blocks = vec![EditBlock::Snippet {
+        path: "src/lib.rs".to_string(),
+    }];
+
+    let planned = plan_edit_blocks(project.root(), &blocks, None);
+    let (report, change) = &planned[0];
+
+    assert!(change.is_none());
+    assert!(report.error.is_none());
+    assert_eq!(report.blocks[0].kind, EditBlockKind::Snippet);
+    assert!(!report.blocks[0].applied);
+}
+
+#[test]
+fn test_resolve_block_path_suggests_matching_files() {
+    let project = TestProject::new();
+    let index = index_of(project.root(), &["src/main.rs", "README.md"]);
+
+    assert_eq!(
+        resolve_block_path(project.root(), "./src/main.rs", Some(&index)),
+        Ok(project.root.join("src/main.rs"))
+    );
+    assert_eq!(
+        resolve_block_path(project.root(), "src/new.rs", Some(&index)),
+        Ok(project.root.join("src/new.rs"))
+    );
+    assert_eq!(
+        resolve_block_path(project.root(), "main.rs", Some(&index)),
+        Err("main.rs isn't a path in the project, did you mean src/main.rs?".to_string())
+    );
+    assert!(resolve_block_path(project.root(), "../outside.rs", Some(&index)).is_err());
+}
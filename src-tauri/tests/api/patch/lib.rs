@@ -0,0 +1,348 @@
+use promptlab_lib::api::patch::lib::{
+    apply_hunks, parse_unified_diff, plan_patches, resolve_project_path, write_changes_atomically,
+    FileAction, HunkStatus, PlannedChange,
+};
+
+use super::TestProject;
+
+/// Joins `lines`, each ended with a newline.
+fn text(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// Plans and writes `diff`, returning the error of each file report.
+fn apply(project: &TestProject, diff: &str) -> Vec<Option<String>> {
+    let patches = parse_unified_diff(diff);
+    let (reports, changes) = plan_patches(project.root(), &patches);
+    let errors: Vec<Option<String>> = reports.into_iter().map(|report| report.error).collect();
+
+    if errors.iter().all(Option::is_none) {
+        write_changes_atomically(&changes).expect("write changes");
+    }
+
+    errors
+}
+
+#[test]
+fn test_parse_git_diff_inside_prose_and_fence() {
+    let response = text(&[
+        "Here is the fix:",
+        "```diff",
+        "diff --git a/src/lib.rs b/src/lib.rs",
+        "index 1111111..2222222 100644",
+        "--- a/src/lib.rs",
+        "+++ b/src/lib.rs",
+        "@@ -1,2 +1,2 @@",
+        " fn main() {",
+        "-    old();",
+        "+    new();",
+        "```",
+        "Let me know if it works.",
+    ]);
+
+    let patches = parse_unified_diff(&response);
+
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].old_path.as_deref(), Some("src/lib.rs"));
+    assert_eq!(patches[0].new_path.as_deref(), Some("src/lib.rs"));
+    assert_eq!(patches[0].action(), FileAction::Modify);
+    assert_eq!(patches[0].hunks.len(), 1);
+}
+
+#[test]
+fn test_apply_modifies_file() {
+    let project = TestProject::new();
+    project.write("src/lib.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
+
+    let errors = apply(
+        &project,
+        &text(&[
+            "--- a/src/lib.rs",
+            "+++ b/src/lib.rs",
+            "@@ -1,3 +1,3 @@",
+            " fn a() {}",
+            "-fn b() {}",
+            "+fn b() { todo!() }",
+            " fn c() {}",
+        ]),
+    );
+
+    assert_eq!(errors, vec![None]);
+    assert_eq!(
+        project.read("src/lib.rs"),
+        "fn a() {}\nfn b() { todo!() }\nfn c() {}\n"
+    );
+}
+
+#[test]
+fn test_apply_creates_and_deletes_files() {
+    let project = TestProject::new();
+    project.write("old.txt", "gone\n");
+
+    let errors = apply(
+        &project,
+        &text(&[
+            "diff --git a/new.txt b/new.txt",
+            "new file mode 100644",
+            "--- /dev/null",
+            "+++ b/new.txt",
+            "@@ -0,0 +1,2 @@",
+            "+hello",
+            "+world",
+            "diff --git a/old.txt b/old.txt",
+            "deleted file mode 100644",
+            "--- a/old.txt",
+            "+++ /dev/null",
+            "@@ -1 +0,0 @@",
+            "-gone",
+        ]),
+    );
+
+    assert_eq!(errors, vec![None, None]);
+    assert_eq!(project.read("new.txt"), "hello\nworld\n");
+    assert!(!project.root.join("old.txt").exists());
+}
+
+#[test]
+fn test_hunk_found_at_offset() {
+    let patches = parse_unified_diff(&text(&[
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -1,2 +1,2 @@",
+        " three",
+        "-four",
+        "+FOUR",
+    ]));
+
+    let (result, hunks) = apply_hunks("one\ntwo\nthree\nfour\n", &patches[0].hunks);
+
+    assert_eq!(result.as_deref(), Some("one\ntwo\nthree\nFOUR\n"));
+    assert_eq!(hunks[0].status, HunkStatus::Offset);
+    assert_eq!(hunks[0].offset, 2);
+    assert_eq!(hunks[0].applied_line, Some(3));
+}
+
+#[test]
+fn test_hunk_matches_ignoring_trailing_whitespace() {
+    let patches = parse_unified_diff(&text(&[
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -1,2 +1,2 @@",
+        " keep",
+        "-change",
+        "+changed",
+    ]));
+
+    let (result, hunks) = apply_hunks("keep  \nchange\t\n", &patches[0].hunks);
+
+    assert_eq!(result.as_deref(), Some("keep\nchanged\n"));
+    assert_eq!(hunks[0].status, HunkStatus::Whitespace);
+}
+
+#[test]
+fn test_context_mismatch_fails_without_writing() {
+    let project = TestProject::new();
+    project.write("f.txt", "alpha\nbeta\n");
+
+    let patches = parse_unified_diff(&text(&[
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -1,2 +1,2 @@",
+        " gamma",
+        "-beta",
+        "+BETA",
+    ]));
+    let (reports, changes) = plan_patches(project.root(), &patches);
+
+    assert!(reports[0].error.is_some());
+    assert_eq!(reports[0].hunks[0].status, HunkStatus::Failed);
+    assert!(changes.is_empty());
+    assert_eq!(project.read("f.txt"), "alpha\nbeta\n");
+}
+
+#[test]
+fn test_duplicate_path_applies_both_diffs() {
+    let project = TestProject::new();
+    project.write("f.txt", "one\ntwo\nthree\nfour\nfive\n");
+
+    let patches = parse_unified_diff(&text(&[
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -1,2 +1,2 @@",
+        "-one",
+        "+ONE",
+        " two",
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -4,2 +4,2 @@",
+        " four",
+        "-five",
+        "+FIVE",
+    ]));
+    let (reports, changes) = plan_patches(project.root(), &patches);
+
+    assert_eq!(reports.len(), 2);
+    assert!(reports.iter().all(|report| report.error.is_none()));
+    assert_eq!(changes.len(), 1);
+
+    write_changes_atomically(&changes).unwrap();
+    assert_eq!(project.read("f.txt"), "ONE\ntwo\nthree\nfour\nFIVE\n");
+}
+
+#[test]
+fn test_duplicate_path_reports_second_diff_against_first_result() {
+    let project = TestProject::new();
+    project.write("f.txt", "value = 1\n");
+
+    let errors = apply(
+        &project,
+        &text(&[
+            "--- a/f.txt",
+            "+++ b/f.txt",
+            "@@ -1 +1 @@",
+            "-value = 1",
+            "+value = 2",
+            "--- a/f.txt",
+            "+++ b/f.txt",
+            "@@ -1 +1 @@",
+            "-value = 1",
+            "+value = 3",
+        ]),
+    );
+
+    assert!(errors[0].is_none());
+    assert!(errors[1].is_some());
+    assert_eq!(project.read("f.txt"), "value = 1\n");
+}
+
+#[test]
+fn test_paths_outside_the_project_are_refused() {
+    let project = TestProject::new();
+
+    assert!(resolve_project_path(project.root(), "../outside.txt").is_none());
+    assert!(resolve_project_path(project.root(), "src/../../outside.txt").is_none());
+    assert!(resolve_project_path(project.root(), "/etc/passwd").is_none());
+    assert!(resolve_project_path(project.root(), "").is_none());
+    assert_eq!(
+        resolve_project_path(project.root(), "src/new.rs"),
+        Some(project.root.join("src/new.rs"))
+    );
+
+    let patches = parse_unified_diff(&text(&[
+        "--- /dev/null",
+        "+++ b/../escape.txt",
+        "@@ -0,0 +1 @@",
+        "+nope",
+    ]));
+    let (reports, changes) = plan_patches(project.root(), &patches);
+
+    assert!(reports[0]
+        .error
+        .as_deref()
+        .is_some_and(|error| error.contains("outside the project")));
+    assert!(changes.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_paths_through_symlinks_outside_the_project_are_refused() {
+    let project = TestProject::new();
+    let outside = TestProject::new();
+    std::os::unix::fs::symlink(&outside.root, project.root.join("linked")).unwrap();
+    project.write("inside/file.txt", "ok\n");
+    std::os::unix::fs::symlink(project.root.join("inside"), project.root.join("alias")).unwrap();
+
+    assert!(resolve_project_path(project.root(), "linked/file.txt").is_none());
+    assert!(resolve_project_path(project.root(), "linked/new/dir/file.txt").is_none());
+    assert!(resolve_project_path(project.root(), "alias/file.txt").is_some());
+}
+
+#[test]
+fn test_no_newline_marker_on_new_side_drops_final_newline() {
+    let patches = parse_unified_diff(&text(&[
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -1,2 +1,2 @@",
+        " a",
+        "-b",
+        "+c",
+        "\\ No newline at end of file",
+    ]));
+
+    let (result, _) = apply_hunks("a\nb\n", &patches[0].hunks);
+
+    assert_eq!(result.as_deref(), Some("a\nc"));
+}
+
+#[test]
+fn test_no_newline_marker_on_old_side_adds_final_newline() {
+    let patches = parse_unified_diff(&text(&[
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -1,2 +1,2 @@",
+        " a",
+        "-b",
+        "\\ No newline at end of file",
+        "+b",
+    ]));
+
+    let (result, _) = apply_hunks("a\nb", &patches[0].hunks);
+
+    assert_eq!(result.as_deref(), Some("a\nb\n"));
+}
+
+#[test]
+fn test_no_newline_marker_after_context_keeps_it_missing() {
+    let patches = parse_unified_diff(&text(&[
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -1,2 +1,2 @@",
+        "-a",
+        "+A",
+        " b",
+        "\\ No newline at end of file",
+    ]));
+
+    let (result, _) = apply_hunks("a\nb", &patches[0].hunks);
+
+    assert_eq!(result.as_deref(), Some("A\nb"));
+}
+
+#[test]
+fn test_hunk_range_without_length_covers_one_line() {
+    let patches = parse_unified_diff(&text(&[
+        "--- a/f.txt",
+        "+++ b/f.txt",
+        "@@ -2 +2 @@",
+        "-b",
+        "+B",
+        " c",
+    ]));
+
+    assert_eq!(patches[0].hunks[0].lines.len(), 2);
+
+    let (result, _) = apply_hunks("a\nb\nc\n", &patches[0].hunks);
+
+    assert_eq!(result.as_deref(), Some("a\nB\nc\n"));
+}
+
+#[test]
+fn test_failed_write_removes_created_directories() {
+    let project = TestProject::new();
+    project.write("blocker.txt", "keep\n");
+
+    let changes = vec![
+        PlannedChange::Write {
+            path: project.root.join("new/nested/a.txt"),
+            contents: "a\n".to_string(),
+        },
+        PlannedChange::Write {
+            path: project.root.join("blocker.txt/b.txt"),
+            contents: "b\n".to_string(),
+        },
+    ];
+
+    assert!(write_changes_atomically(&changes).is_err());
+    assert!(!project.root.join("new").exists());
+    assert_eq!(project.read("blocker.txt"), "keep\n");
+}
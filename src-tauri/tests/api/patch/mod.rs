@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::PathBuf;
+
+mod blocks;
+mod lib;
+
+/// A scratch project directory, removed when dropped.
+pub(super) struct TestProject {
+    pub(super) root: PathBuf,
+}
+
+impl TestProject {
+    pub(super) fn new() -> Self {
+        let root = std::env::temp_dir().join(format!("promptlab-patch-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).expect("create test project");
+
+        Self {
+            root: root.canonicalize().expect("canonicalize test project"),
+        }
+    }
+
+    pub(super) fn root(&self) -> &str {
+        self.root.to_str().expect("utf-8 temp path")
+    }
+
+    pub(super) fn write(&self, relative: &str, contents: &str) {
+        let path = self.root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    pub(super) fn read(&self, relative: &str) -> String {
+        fs::read_to_string(self.root.join(relative)).unwrap()
+    }
+}
+
+impl Drop for TestProject {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
@@ -0,0 +1,185 @@
+use promptlab_lib::api::profile::lib::{
+    item_keyword, profile_text, FileTokenProfile, ProfileGranularity,
+};
+
+/// Profiles `lines` counting one token per line.
+fn profile_items(path: &str, lines: &[&str]) -> FileTokenProfile {
+    let text = lines.join("\n");
+    profile_text(
+        path,
+        &text,
+        &vec![1; lines.len()],
+        ProfileGranularity::Item,
+        50,
+    )
+}
+
+/// The start line, end line and label of every region.
+fn regions(profile: &FileTokenProfile) -> Vec<(usize, usize, Option<&str>)> {
+    profile
+        .regions
+        .iter()
+        .map(|region| (region.start_line, region.end_line, region.label.as_deref()))
+        .collect()
+}
+
+#[test]
+fn test_line_granularity_has_one_region_per_line() {
+    let profile = profile_text(
+        "notes.txt",
+        "a\nbb\nccc",
+        &[2, 3, 5],
+        ProfileGranularity::Line,
+        50,
+    );
+
+    assert_eq!(profile.total_token_count, 10);
+    assert_eq!(profile.line_count, 3);
+    assert_eq!(
+        regions(&profile),
+        vec![(1, 1, None), (2, 2, None), (3, 3, None)]
+    );
+
+    let percentages: Vec<f64> = profile.regions.iter().map(|r| r.percentage).collect();
+    assert_eq!(percentages, vec![20.0, 30.0, 50.0]);
+}
+
+#[test]
+fn test_block_granularity_groups_fixed_runs_of_lines() {
+    let profile = profile_text(
+        "notes.txt",
+        "1\n2\n3\n4\n5",
+        &[1, 2, 3, 4, 5],
+        ProfileGranularity::Block,
+        2,
+    );
+
+    assert_eq!(
+        regions(&profile),
+        vec![(1, 2, None), (3, 4, None), (5, 5, None)]
+    );
+
+    let counts: Vec<usize> = profile.regions.iter().map(|r| r.token_count).collect();
+    assert_eq!(counts, vec![3, 7, 5]);
+}
+
+#[test]
+fn test_block_granularity_treats_zero_lines_as_one() {
+    let profile = profile_text("notes.txt", "a\nb", &[1, 1], ProfileGranularity::Block, 0);
+
+    assert_eq!(regions(&profile), vec![(1, 1, None), (2, 2, None)]);
+}
+
+#[test]
+fn test_empty_file_has_no_regions() {
+    let profile = profile_text("empty.rs", "", &[], ProfileGranularity::Item, 50);
+
+    assert_eq!(profile.total_token_count, 0);
+    assert!(profile.regions.is_empty());
+}
+
+#[test]
+fn test_rust_items_take_their_doc_comments_and_attributes() {
+    let profile = profile_items(
+        "src/lib.rs",
+        &[
+            "use std::fmt;",
+            "",
+            "/// Adds one.",
+            "#[inline]",
+            "pub fn add(a: i32) -> i32 {",
+            "    a + 1",
+            "}",
+            "",
+            "pub(crate) struct Point {",
+            "    x: i32,",
+            "}",
+        ],
+    );
+
+    assert_eq!(
+        regions(&profile),
+        vec![
+            (1, 2, None),
+            (3, 8, Some("pub fn add(a: i32) -> i32 {")),
+            (9, 11, Some("pub(crate) struct Point {")),
+        ]
+    );
+}
+
+#[test]
+fn test_python_items_take_their_decorators() {
+    let profile = profile_items(
+        "app/config.py",
+        &[
+            "import os",
+            "",
+            "@dataclass",
+            "class Config:",
+            "    name: str",
+            "",
+            "async def load():",
+            "    pass",
+        ],
+    );
+
+    assert_eq!(
+        regions(&profile),
+        vec![
+            (1, 2, None),
+            (3, 6, Some("class Config:")),
+            (7, 8, Some("async def load():")),
+        ]
+    );
+}
+
+#[test]
+fn test_typescript_test_blocks_and_declarations_are_items() {
+    let profile = profile_items(
+        "src/parser.test.ts",
+        &[
+            "import { parse } from \"./parser\";",
+            "",
+            "describe(\"parser\", () => {",
+            "  it(\"parses\", () => {});",
+            "});",
+            "",
+            "export type Id = string;",
+            "export const MAX = 3;",
+            "test(\"works\", () => {});",
+        ],
+    );
+
+    assert_eq!(
+        regions(&profile),
+        vec![
+            (1, 2, None),
+            (3, 6, Some("describe(\"parser\", () => {")),
+            (7, 7, Some("export type Id = string;")),
+            (8, 8, Some("export const MAX = 3;")),
+            (9, 9, Some("test(\"works\", () => {});")),
+        ]
+    );
+}
+
+#[test]
+fn test_item_keyword_skips_modifiers_and_indentation() {
+    assert_eq!(item_keyword("it(\"parses\", () => {"), Some("it"));
+    assert_eq!(item_keyword("  test('works', () => {"), Some("test"));
+    assert_eq!(
+        item_keyword("describe(\"parser\", () => {"),
+        Some("describe")
+    );
+    assert_eq!(item_keyword("export type Id = string;"), Some("type"));
+    assert_eq!(item_keyword("export const MAX = 3;"), Some("const"));
+    assert_eq!(item_keyword("    pub async fn run() {"), Some("fn"));
+    assert_eq!(item_keyword("impl<T> Display for Point<T> {"), Some("impl"));
+}
+
+#[test]
+fn test_item_keyword_ignores_words_that_only_start_with_a_keyword() {
+    assert_eq!(item_keyword("iterator.next();"), None);
+    assert_eq!(item_keyword("testing = true"), None);
+    assert_eq!(item_keyword("return value;"), None);
+    assert_eq!(item_keyword(""), None);
+}
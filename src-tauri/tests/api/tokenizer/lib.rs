@@ -0,0 +1,70 @@
+use promptlab_lib::api::tokenizer::lib::{active_tokenizer, stored_token_count, Tokenizer};
+use serde_json::{json, Map, Value};
+
+fn stored(value: Value) -> Map<String, Value> {
+    value.as_object().cloned().expect("object")
+}
+
+#[test]
+fn test_cache_key_round_trips_for_every_tokenizer() {
+    for tokenizer in Tokenizer::ALL {
+        let key = tokenizer.cache_key("/project/src/main.rs");
+
+        assert_eq!(
+            Tokenizer::split_cache_key(&key),
+            (tokenizer, "/project/src/main.rs")
+        );
+    }
+}
+
+#[test]
+fn test_legacy_cache_keys_were_counted_with_cl100k() {
+    assert_eq!(
+        Tokenizer::split_cache_key("/project/src/main.rs"),
+        (Tokenizer::Cl100kBase, "/project/src/main.rs")
+    );
+    assert_eq!(
+        Tokenizer::split_cache_key("C:/project/main.rs"),
+        (Tokenizer::Cl100kBase, "C:/project/main.rs")
+    );
+}
+
+#[test]
+fn test_stored_count_is_kept_for_the_active_tokenizer() {
+    let tokenizer = active_tokenizer();
+    let object = stored(json!({ "tokenizer": tokenizer.key(), "tokenCount": 7 }));
+
+    assert_eq!(
+        stored_token_count(&object, "some instruction text"),
+        Some(7)
+    );
+}
+
+#[test]
+fn test_stored_count_is_recounted_after_the_tokenizer_changes() {
+    let tokenizer = active_tokenizer();
+    let other = Tokenizer::ALL
+        .into_iter()
+        .find(|t| *t != tokenizer)
+        .expect("another tokenizer");
+    let object = stored(json!({ "tokenizer": other.key(), "tokenCount": 999 }));
+    let content = "Explain how the cache is invalidated when files change.";
+
+    assert_eq!(
+        stored_token_count(&object, content),
+        Some(tokenizer.count(content))
+    );
+}
+
+#[test]
+fn test_stored_count_without_tokenizer_was_counted_with_cl100k() {
+    let object = stored(json!({ "tokenCount": 7 }));
+    let content = "Explain how the cache is invalidated when files change.";
+    let expected = if active_tokenizer() == Tokenizer::Cl100kBase {
+        7
+    } else {
+        active_tokenizer().count(content)
+    };
+
+    assert_eq!(stored_token_count(&object, content), Some(expected));
+}
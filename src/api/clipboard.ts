@@ -15,21 +15,23 @@ export async function copyAllToClipboard(params: {
   unsavedInstruction: Instruction | null
 }) {
   await invoke('copy_all_to_clipboard', {
-    treeDisplayMode: params.treeDisplayMode,
-    fullTree: params.fullTree,
-    root: params.root,
-    selectedNodes: Array.from(params.selectedNodes),
-    gitDiffPaths: Array.from(params.gitDiffPaths),
-    urls: Array.from(params.urls),
-    instructionIds: Array.from(params.instructionIds),
-    instructions: params.unsavedInstruction
-      ? [
-          {
-            name: params.unsavedInstruction.name,
-            content: params.unsavedInstruction.content,
-          },
-        ]
-      : [],
+    inputs: {
+      treeDisplayMode: params.treeDisplayMode,
+      fullTree: params.fullTree,
+      root: params.root,
+      selectedNodes: Array.from(params.selectedNodes),
+      gitDiffPaths: Array.from(params.gitDiffPaths),
+      urls: Array.from(params.urls),
+      instructionIds: Array.from(params.instructionIds),
+      instructions: params.unsavedInstruction
+        ? [
+            {
+              name: params.unsavedInstruction.name,
+              content: params.unsavedInstruction.content,
+            },
+          ]
+        : [],
+    },
   })
 }
 